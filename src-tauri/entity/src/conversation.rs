@@ -3,20 +3,114 @@
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
 #[sea_orm(table_name = "conversation")]
 pub struct Model {
     #[sea_orm(primary_key)]
     #[serde(skip_deserializing)]
     pub id: i32,
     pub title: String,
+    /// Set once the user renames the conversation; generated titles never
+    /// replace a user's.
+    #[serde(default)]
+    pub title_edited: bool,
     #[serde(skip_deserializing)]
     pub created_at: String,
     #[serde(skip_deserializing)]
     pub updated_at: String,
+    #[serde(default)]
+    pub transcription_model: Option<String>,
+    /// Language code to transcribe in, `None` to auto-detect.
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub translate: bool,
+    #[serde(default)]
+    pub initial_prompt: Option<String>,
+    /// Beam search width, `None` for greedy decoding.
+    #[serde(default)]
+    pub beam_size: Option<i32>,
+    #[serde(default)]
+    pub temperature: Option<f64>,
+    #[serde(skip_deserializing)]
+    pub detected_language: Option<String>,
+    /// Set when the app quit during the recording and it was finalized from
+    /// the segments on disk at the next start.
+    #[serde(skip_deserializing)]
+    pub recovered: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
-pub enum Relation {}
+pub enum Relation {
+    #[sea_orm(has_many = "super::action_item::Entity")]
+    ActionItem,
+    #[sea_orm(has_many = "super::embedding::Entity")]
+    Embedding,
+    #[sea_orm(has_many = "super::job::Entity")]
+    Job,
+    #[sea_orm(has_many = "super::speaker::Entity")]
+    Speaker,
+    #[sea_orm(has_many = "super::summary::Entity")]
+    Summary,
+    #[sea_orm(has_one = "super::transcript::Entity")]
+    Transcript,
+    #[sea_orm(has_many = "super::transcript_segment::Entity")]
+    TranscriptSegment,
+}
+
+impl Related<super::action_item::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ActionItem.def()
+    }
+}
+
+impl Related<super::embedding::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Embedding.def()
+    }
+}
+
+impl Related<super::job::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Job.def()
+    }
+}
+
+impl Related<super::speaker::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Speaker.def()
+    }
+}
+
+impl Related<super::summary::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Summary.def()
+    }
+}
+
+impl Related<super::transcript::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Transcript.def()
+    }
+}
+
+impl Related<super::transcript_segment::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::TranscriptSegment.def()
+    }
+}
 
 impl ActiveModelBehavior for ActiveModel {}
+
+impl Model {
+    /// The day the conversation was recorded, as YYYY-MM-DD.
+    pub fn date(&self) -> &str {
+        self.created_at.get(..10).unwrap_or(&self.created_at)
+    }
+}
+
+/// The rowid of a conversation's summary in the `conversation_search` index.
+/// Segments use their own id and titles `-(id * 2)`, set by the index's triggers.
+pub fn summary_search_rowid(conversation_id: i32) -> i64 {
+    -(conversation_id as i64 * 2 + 1)
+}
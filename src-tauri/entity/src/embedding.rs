@@ -0,0 +1,44 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
+#[sea_orm(table_name = "embedding")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    #[serde(skip_deserializing)]
+    pub id: i32,
+    pub conversation_id: i32,
+    /// First transcript segment of the embedded chunk.
+    pub segment_id: i32,
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub text: String,
+    /// Vectors of different models can't be compared, so each is tagged.
+    pub model: String,
+    /// Unit-length `f32` vector, little endian.
+    #[sea_orm(column_type = "Blob")]
+    #[serde(skip)]
+    pub vector: Vec<u8>,
+    #[serde(skip_deserializing)]
+    pub created_at: String,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::conversation::Entity",
+        from = "Column::ConversationId",
+        to = "super::conversation::Column::Id",
+        on_update = "NoAction",
+        on_delete = "Cascade"
+    )]
+    Conversation,
+}
+
+impl Related<super::conversation::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Conversation.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
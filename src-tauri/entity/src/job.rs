@@ -0,0 +1,77 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::None)")]
+#[serde(rename_all = "lowercase")]
+pub enum JobKind {
+    #[sea_orm(string_value = "concat")]
+    Concat,
+    #[sea_orm(string_value = "mix")]
+    Mix,
+    #[sea_orm(string_value = "transcribe")]
+    Transcribe,
+    #[sea_orm(string_value = "diarize")]
+    Diarize,
+    #[sea_orm(string_value = "summarize")]
+    Summarize,
+    /// A new summary version with the template and model in the job's params.
+    #[sea_orm(string_value = "resummarize")]
+    Resummarize,
+    #[sea_orm(string_value = "title")]
+    Title,
+    #[sea_orm(string_value = "embed")]
+    Embed,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::None)")]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    #[sea_orm(string_value = "pending")]
+    Pending,
+    #[sea_orm(string_value = "running")]
+    Running,
+    #[sea_orm(string_value = "done")]
+    Done,
+    #[sea_orm(string_value = "failed")]
+    Failed,
+}
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
+#[sea_orm(table_name = "job")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub conversation_id: i32,
+    pub kind: JobKind,
+    pub status: JobStatus,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    /// Unix time in ms before which a retried job isn't picked up.
+    pub run_after: Option<i64>,
+    /// JSON options for the job, for kinds that take any.
+    pub params: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::conversation::Entity",
+        from = "Column::ConversationId",
+        to = "super::conversation::Column::Id",
+        on_update = "NoAction",
+        on_delete = "Cascade"
+    )]
+    Conversation,
+}
+
+impl Related<super::conversation::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Conversation.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -2,4 +2,13 @@
 
 pub mod prelude;
 
+pub mod action_item;
 pub mod conversation;
+pub mod embedding;
+pub mod job;
+pub mod setting;
+pub mod speaker;
+pub mod summary;
+pub mod summary_template;
+pub mod transcript;
+pub mod transcript_segment;
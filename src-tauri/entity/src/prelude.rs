@@ -1,3 +1,12 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.15
 
+pub use super::action_item::Entity as ActionItem;
 pub use super::conversation::Entity as Conversation;
+pub use super::embedding::Entity as Embedding;
+pub use super::job::Entity as Job;
+pub use super::setting::Entity as Setting;
+pub use super::speaker::Entity as Speaker;
+pub use super::summary::Entity as Summary;
+pub use super::summary_template::Entity as SummaryTemplate;
+pub use super::transcript::Entity as Transcript;
+pub use super::transcript_segment::Entity as TranscriptSegment;
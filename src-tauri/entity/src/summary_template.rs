@@ -0,0 +1,31 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
+#[sea_orm(table_name = "summary_template")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    #[serde(skip_deserializing)]
+    pub id: i32,
+    pub name: String,
+    /// Instructions with `{{transcript}}`, `{{participants}}` and `{{date}}`
+    /// placeholders.
+    pub prompt: String,
+    /// JSON schema the summary must adhere to, for structured output.
+    pub output_schema: Option<String>,
+    /// Used when no template is chosen. Exactly one template is the default.
+    #[serde(default)]
+    pub is_default: bool,
+    /// Shipped with the app; can be edited but not deleted.
+    #[serde(skip_deserializing)]
+    pub builtin: bool,
+    #[serde(skip_deserializing)]
+    pub created_at: String,
+    #[serde(skip_deserializing)]
+    pub updated_at: String,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
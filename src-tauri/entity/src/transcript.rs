@@ -0,0 +1,53 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
+#[sea_orm(table_name = "transcript")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    #[serde(skip_deserializing)]
+    pub id: i32,
+    #[sea_orm(unique)]
+    pub conversation_id: i32,
+    /// One paragraph per speaker turn, separated by blank lines.
+    pub text: String,
+    #[serde(skip_deserializing)]
+    pub created_at: String,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::conversation::Entity",
+        from = "Column::ConversationId",
+        to = "super::conversation::Column::Id",
+        on_update = "NoAction",
+        on_delete = "Cascade"
+    )]
+    Conversation,
+}
+
+impl Related<super::conversation::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Conversation.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// Groups consecutive segments of the same speaker, given in order as
+/// `(speaker, text)`, into one paragraph per speaker turn.
+pub fn paragraphs<'a>(segments: impl IntoIterator<Item = (i32, &'a str)>) -> Vec<String> {
+    let mut paragraphs: Vec<String> = Vec::new();
+    let mut current_speaker = None;
+    for (speaker, text) in segments {
+        if current_speaker != Some(speaker) {
+            paragraphs.push(String::new());
+            current_speaker = Some(speaker);
+        }
+        if let Some(paragraph) = paragraphs.last_mut() {
+            paragraph.push_str(text);
+        }
+    }
+    paragraphs
+}
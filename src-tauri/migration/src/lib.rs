@@ -3,6 +3,24 @@ pub use sea_orm_migration::prelude::*;
 mod m20240523_173708_create_conversation_table;
 mod m20240523_214940_add_dates_to_conversation_table;
 mod m20240523_225333_add_updated_at_to_conversation_table;
+mod m20241021_184512_create_transcript_segment_table;
+mod m20241022_093047_create_conversation_search_table;
+mod m20241024_161205_create_job_table;
+mod m20241027_110934_create_setting_table;
+mod m20241027_112458_add_transcription_model_to_conversation_table;
+mod m20241028_090215_add_transcription_options_to_conversation_table;
+mod m20241029_141830_create_action_item_table;
+mod m20241030_101544_add_title_edited_to_conversation_table;
+mod m20241031_093412_create_summary_table;
+mod m20241031_093655_create_transcript_table;
+mod m20241031_094120_backfill_results_from_json_files;
+mod m20241102_152207_add_version_columns_to_summary_table;
+mod m20241103_101230_create_summary_template_table;
+mod m20241104_143318_create_embedding_table;
+mod m20241105_091744_create_speaker_table;
+mod m20241106_120431_add_recovered_to_conversation_table;
+mod m20241107_083512_add_run_after_to_job_table;
+mod m20241107_094120_add_params_to_job_table;
 
 pub struct Migrator;
 
@@ -13,6 +31,24 @@ impl MigratorTrait for Migrator {
             Box::new(m20240523_173708_create_conversation_table::Migration),
             Box::new(m20240523_214940_add_dates_to_conversation_table::Migration),
             Box::new(m20240523_225333_add_updated_at_to_conversation_table::Migration),
+            Box::new(m20241021_184512_create_transcript_segment_table::Migration),
+            Box::new(m20241022_093047_create_conversation_search_table::Migration),
+            Box::new(m20241024_161205_create_job_table::Migration),
+            Box::new(m20241027_110934_create_setting_table::Migration),
+            Box::new(m20241027_112458_add_transcription_model_to_conversation_table::Migration),
+            Box::new(m20241028_090215_add_transcription_options_to_conversation_table::Migration),
+            Box::new(m20241029_141830_create_action_item_table::Migration),
+            Box::new(m20241030_101544_add_title_edited_to_conversation_table::Migration),
+            Box::new(m20241031_093412_create_summary_table::Migration),
+            Box::new(m20241031_093655_create_transcript_table::Migration),
+            Box::new(m20241031_094120_backfill_results_from_json_files::Migration),
+            Box::new(m20241102_152207_add_version_columns_to_summary_table::Migration),
+            Box::new(m20241103_101230_create_summary_template_table::Migration),
+            Box::new(m20241104_143318_create_embedding_table::Migration),
+            Box::new(m20241105_091744_create_speaker_table::Migration),
+            Box::new(m20241106_120431_add_recovered_to_conversation_table::Migration),
+            Box::new(m20241107_083512_add_run_after_to_job_table::Migration),
+            Box::new(m20241107_094120_add_params_to_job_table::Migration),
         ]
     }
 }
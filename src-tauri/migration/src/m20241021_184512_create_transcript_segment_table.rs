@@ -0,0 +1,88 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(TranscriptSegment::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(TranscriptSegment::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(TranscriptSegment::ConversationId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(TranscriptSegment::StartMs)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(TranscriptSegment::EndMs)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(TranscriptSegment::Speaker)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(ColumnDef::new(TranscriptSegment::Text).text().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-transcript_segment-conversation_id")
+                            .from(TranscriptSegment::Table, TranscriptSegment::ConversationId)
+                            .to(Conversation::Table, Conversation::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-transcript_segment-conversation_id")
+                    .table(TranscriptSegment::Table)
+                    .col(TranscriptSegment::ConversationId)
+                    .col(TranscriptSegment::StartMs)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(TranscriptSegment::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum TranscriptSegment {
+    Table,
+    Id,
+    ConversationId,
+    StartMs,
+    EndMs,
+    Speaker,
+    Text,
+}
+
+#[derive(DeriveIden)]
+enum Conversation {
+    Table,
+    Id,
+}
@@ -0,0 +1,97 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// Rows are keyed so that every indexed document has a stable rowid:
+// transcript segments use their own id, conversation titles use
+// -(id * 2) and summaries use -(id * 2 + 1).
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        db.execute_unprepared(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS conversation_search USING fts5(
+                content,
+                conversation_id UNINDEXED,
+                kind UNINDEXED,
+                segment_id UNINDEXED,
+                start_ms UNINDEXED,
+                tokenize = 'porter unicode61'
+            )",
+        )
+        .await?;
+
+        db.execute_unprepared(
+            "CREATE TRIGGER IF NOT EXISTS conversation_search_title_insert
+            AFTER INSERT ON conversation BEGIN
+                INSERT INTO conversation_search (rowid, content, conversation_id, kind)
+                VALUES (-(new.id * 2), new.title, new.id, 'title');
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS conversation_search_title_update
+            AFTER UPDATE OF title ON conversation BEGIN
+                DELETE FROM conversation_search WHERE rowid = -(old.id * 2);
+                INSERT INTO conversation_search (rowid, content, conversation_id, kind)
+                VALUES (-(new.id * 2), new.title, new.id, 'title');
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS conversation_search_conversation_delete
+            AFTER DELETE ON conversation BEGIN
+                DELETE FROM conversation_search
+                WHERE rowid IN (-(old.id * 2), -(old.id * 2 + 1));
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS conversation_search_segment_insert
+            AFTER INSERT ON transcript_segment BEGIN
+                INSERT INTO conversation_search
+                    (rowid, content, conversation_id, kind, segment_id, start_ms)
+                VALUES (new.id, new.text, new.conversation_id, 'transcript', new.id, new.start_ms);
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS conversation_search_segment_update
+            AFTER UPDATE ON transcript_segment BEGIN
+                DELETE FROM conversation_search WHERE rowid = old.id;
+                INSERT INTO conversation_search
+                    (rowid, content, conversation_id, kind, segment_id, start_ms)
+                VALUES (new.id, new.text, new.conversation_id, 'transcript', new.id, new.start_ms);
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS conversation_search_segment_delete
+            AFTER DELETE ON transcript_segment BEGIN
+                DELETE FROM conversation_search WHERE rowid = old.id;
+            END;",
+        )
+        .await?;
+
+        db.execute_unprepared(
+            "INSERT INTO conversation_search (rowid, content, conversation_id, kind)
+            SELECT -(id * 2), title, id, 'title' FROM conversation;
+
+            INSERT INTO conversation_search
+                (rowid, content, conversation_id, kind, segment_id, start_ms)
+            SELECT id, text, conversation_id, 'transcript', id, start_ms FROM transcript_segment;",
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "DROP TRIGGER IF EXISTS conversation_search_title_insert;
+                DROP TRIGGER IF EXISTS conversation_search_title_update;
+                DROP TRIGGER IF EXISTS conversation_search_conversation_delete;
+                DROP TRIGGER IF EXISTS conversation_search_segment_insert;
+                DROP TRIGGER IF EXISTS conversation_search_segment_update;
+                DROP TRIGGER IF EXISTS conversation_search_segment_delete;
+                DROP TABLE IF EXISTS conversation_search;",
+            )
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite only allows one column per ALTER TABLE
+        let columns = [
+            ColumnDef::new(Conversation::Language).string().to_owned(),
+            ColumnDef::new(Conversation::Translate)
+                .boolean()
+                .not_null()
+                .default(false)
+                .to_owned(),
+            ColumnDef::new(Conversation::InitialPrompt)
+                .string()
+                .to_owned(),
+            ColumnDef::new(Conversation::BeamSize).integer().to_owned(),
+            ColumnDef::new(Conversation::Temperature)
+                .double()
+                .to_owned(),
+            ColumnDef::new(Conversation::DetectedLanguage)
+                .string()
+                .to_owned(),
+        ];
+
+        for mut column in columns {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Conversation::Table)
+                        .add_column_if_not_exists(&mut column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [
+            Conversation::Language,
+            Conversation::Translate,
+            Conversation::InitialPrompt,
+            Conversation::BeamSize,
+            Conversation::Temperature,
+            Conversation::DetectedLanguage,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Conversation::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Conversation {
+    Table,
+    Language,
+    Translate,
+    InitialPrompt,
+    BeamSize,
+    Temperature,
+    DetectedLanguage,
+}
@@ -0,0 +1,73 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Summary::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Summary::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Summary::ConversationId).integer().not_null())
+                    .col(ColumnDef::new(Summary::Content).text().not_null())
+                    .col(ColumnDef::new(Summary::Chunks).text())
+                    .col(
+                        ColumnDef::new(Summary::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-summary-conversation_id")
+                            .from(Summary::Table, Summary::ConversationId)
+                            .to(Conversation::Table, Conversation::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-summary-conversation_id")
+                    .table(Summary::Table)
+                    .col(Summary::ConversationId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Summary::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Summary {
+    Table,
+    Id,
+    ConversationId,
+    Content,
+    Chunks,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Conversation {
+    Table,
+    Id,
+}
@@ -0,0 +1,219 @@
+use std::path::{Path, PathBuf};
+
+use entity::{conversation, transcript};
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ConnectionTrait, DbBackend, Statement};
+use serde::Deserialize;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(Deserialize)]
+struct TranscriptionJson {
+    full_text: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct SummaryJson {
+    result: String,
+    #[serde(default)]
+    action_items: Vec<ActionItemJson>,
+    #[serde(default)]
+    chunks: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct ActionItemJson {
+    title: String,
+    assignee: Option<String>,
+    due_date: Option<String>,
+    source_ms: Option<i64>,
+}
+
+/// The data directory holds `db.sqlite` and the per-conversation recordings
+/// in `chunks/audio/<id>`. Returns `None` for in-memory databases.
+async fn data_dir(db: &impl ConnectionTrait) -> Result<Option<PathBuf>, DbErr> {
+    let rows = db
+        .query_all(Statement::from_string(
+            DbBackend::Sqlite,
+            "PRAGMA database_list",
+        ))
+        .await?;
+
+    for row in rows {
+        let name: String = row.try_get("", "name")?;
+        let file: String = row.try_get("", "file")?;
+        if name == "main" && !file.is_empty() {
+            return Ok(Path::new(&file).parent().map(Path::to_path_buf));
+        }
+    }
+
+    Ok(None)
+}
+
+fn read_json<T: for<'de> Deserialize<'de>>(path: &Path) -> Option<T> {
+    let content = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+async fn count(db: &impl ConnectionTrait, table: &str, conversation_id: i32) -> Result<i64, DbErr> {
+    let row = db
+        .query_one(Statement::from_sql_and_values(
+            DbBackend::Sqlite,
+            format!(
+                "SELECT COUNT(*) AS count FROM {} WHERE conversation_id = $1",
+                table
+            ),
+            [conversation_id.into()],
+        ))
+        .await?;
+    match row {
+        Some(row) => row.try_get("", "count"),
+        None => Ok(0),
+    }
+}
+
+/// Moves results written before they were stored in the database:
+/// `transcription.json` becomes a `transcript` row and `summary.json` a
+/// `summary` row plus its action items. Transcripts that already have
+/// segments get their `transcript` row from those. The files are left in place.
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        // One paragraph per speaker turn, like the transcript view shows it
+        let segments = db
+            .query_all(Statement::from_string(
+                DbBackend::Sqlite,
+                "SELECT conversation_id, speaker, text FROM transcript_segment
+                ORDER BY conversation_id, start_ms, id",
+            ))
+            .await?;
+        let mut transcripts: Vec<(i32, Vec<(i32, String)>)> = Vec::new();
+        for segment in segments {
+            let conversation_id: i32 = segment.try_get("", "conversation_id")?;
+            let speaker: i32 = segment.try_get("", "speaker")?;
+            let text: String = segment.try_get("", "text")?;
+
+            if transcripts.last().map(|(id, _)| *id) != Some(conversation_id) {
+                transcripts.push((conversation_id, Vec::new()));
+            }
+            let (_, segments) = transcripts.last_mut().expect("pushed above");
+            segments.push((speaker, text));
+        }
+        for (conversation_id, segments) in transcripts {
+            let paragraphs = transcript::paragraphs(
+                segments
+                    .iter()
+                    .map(|(speaker, text)| (*speaker, text.as_str())),
+            );
+            db.execute(Statement::from_sql_and_values(
+                DbBackend::Sqlite,
+                "INSERT INTO transcript (conversation_id, text) VALUES ($1, $2)",
+                [conversation_id.into(), paragraphs.join("\n\n").into()],
+            ))
+            .await?;
+        }
+
+        let Some(data_dir) = data_dir(db).await? else {
+            return Ok(());
+        };
+
+        let conversations = db
+            .query_all(Statement::from_string(
+                DbBackend::Sqlite,
+                "SELECT id FROM conversation",
+            ))
+            .await?;
+
+        for conversation in conversations {
+            let conversation_id: i32 = conversation.try_get("", "id")?;
+            let recording_dir = data_dir
+                .join("chunks/audio")
+                .join(conversation_id.to_string());
+
+            if count(db, "transcript", conversation_id).await? == 0 {
+                if let Some(transcription) =
+                    read_json::<TranscriptionJson>(&recording_dir.join("transcription.json"))
+                {
+                    db.execute(Statement::from_sql_and_values(
+                        DbBackend::Sqlite,
+                        "INSERT INTO transcript (conversation_id, text) VALUES ($1, $2)",
+                        [
+                            conversation_id.into(),
+                            transcription.full_text.join("\n\n").into(),
+                        ],
+                    ))
+                    .await?;
+                }
+            }
+
+            if count(db, "summary", conversation_id).await? > 0 {
+                continue;
+            }
+            let Some(summary) = read_json::<SummaryJson>(&recording_dir.join("summary.json"))
+            else {
+                continue;
+            };
+
+            db.execute(Statement::from_sql_and_values(
+                DbBackend::Sqlite,
+                "INSERT INTO summary (conversation_id, content, chunks) VALUES ($1, $2, $3)",
+                [
+                    conversation_id.into(),
+                    summary.result.clone().into(),
+                    summary.chunks.map(|chunks| chunks.to_string()).into(),
+                ],
+            ))
+            .await?;
+
+            let rowid = conversation::summary_search_rowid(conversation_id);
+            db.execute(Statement::from_sql_and_values(
+                DbBackend::Sqlite,
+                "DELETE FROM conversation_search WHERE rowid = $1",
+                [rowid.into()],
+            ))
+            .await?;
+            db.execute(Statement::from_sql_and_values(
+                DbBackend::Sqlite,
+                "INSERT INTO conversation_search (rowid, content, conversation_id, kind)
+                VALUES ($1, $2, $3, 'summary')",
+                [rowid.into(), summary.result.into(), conversation_id.into()],
+            ))
+            .await?;
+
+            if count(db, "action_item", conversation_id).await? > 0 {
+                continue;
+            }
+            for item in summary.action_items {
+                db.execute(Statement::from_sql_and_values(
+                    DbBackend::Sqlite,
+                    "INSERT INTO action_item
+                        (conversation_id, title, assignee, due_date, status, source_ms)
+                    VALUES ($1, $2, $3, $4, 'open', $5)",
+                    [
+                        conversation_id.into(),
+                        item.title.into(),
+                        item.assignee.into(),
+                        item.due_date.into(),
+                        item.source_ms.into(),
+                    ],
+                ))
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        // The JSON files are still there, so emptying the tables is enough
+        db.execute_unprepared("DELETE FROM summary; DELETE FROM transcript;")
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite only allows one column per ALTER TABLE
+        let columns = [
+            ColumnDef::new(Summary::TemplateId).integer().to_owned(),
+            ColumnDef::new(Summary::Prompt).text().to_owned(),
+            ColumnDef::new(Summary::Model).string().to_owned(),
+            ColumnDef::new(Summary::Pinned)
+                .boolean()
+                .not_null()
+                .default(false)
+                .to_owned(),
+        ];
+
+        for mut column in columns {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Summary::Table)
+                        .add_column_if_not_exists(&mut column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [
+            Summary::TemplateId,
+            Summary::Prompt,
+            Summary::Model,
+            Summary::Pinned,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Summary::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Summary {
+    Table,
+    TemplateId,
+    Prompt,
+    Model,
+    Pinned,
+}
@@ -0,0 +1,144 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+const GENERAL_PROMPT: &str = "Summarize this meeting from {{date}} between {{participants}}. \
+Keep names, decisions and numbers.
+{{transcript}}";
+
+const STANDUP_PROMPT: &str = "This is a daily standup from {{date}} with {{participants}}. \
+For every participant list what they did since the last standup, what they plan to do next and \
+what is blocking them.
+{{transcript}}";
+
+const STANDUP_SCHEMA: &str = r#"{
+  "type": "object",
+  "properties": {
+    "participants": {
+      "type": "array",
+      "items": {
+        "type": "object",
+        "properties": {
+          "name": { "type": "string" },
+          "done": { "type": "array", "items": { "type": "string" } },
+          "next": { "type": "array", "items": { "type": "string" } },
+          "blockers": { "type": "array", "items": { "type": "string" } }
+        },
+        "required": ["name", "done", "next", "blockers"]
+      }
+    }
+  },
+  "required": ["participants"]
+}"#;
+
+const ONE_ON_ONE_PROMPT: &str = "This is a 1:1 from {{date}} between {{participants}}. \
+Summarize the topics discussed, feedback given in either direction, concerns raised and what \
+each person agreed to follow up on.
+{{transcript}}";
+
+const SALES_CALL_PROMPT: &str = "This is a sales call from {{date}} with {{participants}}. \
+Summarize the customer's needs and pain points, their budget and timeline, the objections they \
+raised, the competitors they mentioned and the agreed next steps.
+{{transcript}}";
+
+const INTERVIEW_PROMPT: &str = "This is a job interview from {{date}} with {{participants}}. \
+Summarize the questions asked, the candidate's answers and relevant experience, strengths, \
+concerns and any questions the candidate asked.
+{{transcript}}";
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(SummaryTemplate::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(SummaryTemplate::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(SummaryTemplate::Name).string().not_null())
+                    .col(ColumnDef::new(SummaryTemplate::Prompt).text().not_null())
+                    .col(ColumnDef::new(SummaryTemplate::OutputSchema).text())
+                    .col(
+                        ColumnDef::new(SummaryTemplate::IsDefault)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .col(
+                        ColumnDef::new(SummaryTemplate::Builtin)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .col(
+                        ColumnDef::new(SummaryTemplate::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(
+                        ColumnDef::new(SummaryTemplate::UpdatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        let builtins = [
+            ("General", GENERAL_PROMPT, None, true),
+            ("Standup", STANDUP_PROMPT, Some(STANDUP_SCHEMA), false),
+            ("1:1", ONE_ON_ONE_PROMPT, None, false),
+            ("Sales call", SALES_CALL_PROMPT, None, false),
+            ("Interview", INTERVIEW_PROMPT, None, false),
+        ];
+        let mut insert = Query::insert()
+            .into_table(SummaryTemplate::Table)
+            .columns([
+                SummaryTemplate::Name,
+                SummaryTemplate::Prompt,
+                SummaryTemplate::OutputSchema,
+                SummaryTemplate::IsDefault,
+                SummaryTemplate::Builtin,
+            ])
+            .to_owned();
+        for (name, prompt, output_schema, is_default) in builtins {
+            insert.values_panic([
+                name.into(),
+                prompt.into(),
+                output_schema.into(),
+                is_default.into(),
+                true.into(),
+            ]);
+        }
+
+        manager.exec_stmt(insert).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(SummaryTemplate::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum SummaryTemplate {
+    Table,
+    Id,
+    Name,
+    Prompt,
+    OutputSchema,
+    IsDefault,
+    Builtin,
+    CreatedAt,
+    UpdatedAt,
+}
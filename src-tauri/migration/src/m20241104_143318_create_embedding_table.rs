@@ -0,0 +1,85 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Embedding::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Embedding::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Embedding::ConversationId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Embedding::SegmentId).integer().not_null())
+                    .col(ColumnDef::new(Embedding::StartMs).big_integer().not_null())
+                    .col(ColumnDef::new(Embedding::EndMs).big_integer().not_null())
+                    .col(ColumnDef::new(Embedding::Text).text().not_null())
+                    .col(ColumnDef::new(Embedding::Model).string().not_null())
+                    .col(ColumnDef::new(Embedding::Vector).blob().not_null())
+                    .col(
+                        ColumnDef::new(Embedding::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-embedding-conversation_id")
+                            .from(Embedding::Table, Embedding::ConversationId)
+                            .to(Conversation::Table, Conversation::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-embedding-model")
+                    .table(Embedding::Table)
+                    .col(Embedding::Model)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Embedding::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Embedding {
+    Table,
+    Id,
+    ConversationId,
+    SegmentId,
+    StartMs,
+    EndMs,
+    Text,
+    Model,
+    Vector,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Conversation {
+    Table,
+    Id,
+}
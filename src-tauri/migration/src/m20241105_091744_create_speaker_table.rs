@@ -0,0 +1,90 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Speaker::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Speaker::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Speaker::ConversationId).integer().not_null())
+                    .col(ColumnDef::new(Speaker::Index).integer().not_null())
+                    .col(ColumnDef::new(Speaker::Name).string().not_null())
+                    .col(
+                        ColumnDef::new(Speaker::Source)
+                            .string()
+                            .not_null()
+                            .default("unknown"),
+                    )
+                    .col(
+                        ColumnDef::new(Speaker::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(
+                        ColumnDef::new(Speaker::UpdatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-speaker-conversation_id")
+                            .from(Speaker::Table, Speaker::ConversationId)
+                            .to(Conversation::Table, Conversation::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-speaker-conversation_id-speaker_index")
+                    .table(Speaker::Table)
+                    .col(Speaker::ConversationId)
+                    .col(Speaker::Index)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Speaker::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Speaker {
+    Table,
+    Id,
+    ConversationId,
+    #[sea_orm(iden = "speaker_index")]
+    Index,
+    Name,
+    Source,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Conversation {
+    Table,
+    Id,
+}
@@ -1,6 +1,23 @@
-use ::entity::{conversation, conversation::Entity as Conversation};
+use ::entity::{
+    action_item::{self, ActionItemStatus, Entity as ActionItem},
+    conversation,
+    conversation::Entity as Conversation,
+    embedding::{self, Entity as Embedding},
+    job::{self, Entity as Job, JobKind, JobStatus},
+    setting,
+    setting::Entity as Setting,
+    speaker::{self, Entity as Speaker},
+    summary::{self, Entity as Summary},
+    summary_template::{self, Entity as SummaryTemplate},
+    transcript::{self, Entity as Transcript},
+    transcript_segment,
+    transcript_segment::Entity as TranscriptSegment,
+};
 use chrono::Utc;
-use sea_orm::*;
+use sea_orm::{
+    sea_query::{Expr, OnConflict},
+    *,
+};
 
 pub struct Mutation;
 
@@ -11,6 +28,7 @@ impl Mutation {
     ) -> Result<conversation::ActiveModel, DbErr> {
         conversation::ActiveModel {
             title: Set(form_data.title.to_owned()),
+            title_edited: Set(form_data.title_edited),
             ..Default::default()
         }
         .save(db)
@@ -29,15 +47,76 @@ impl Mutation {
             .map(Into::into)?;
 
         conversation::ActiveModel {
-            id: post.id,
             title: Set(form_data.title.to_owned()),
-            created_at: post.created_at,
+            title_edited: Set(form_data.title_edited),
             updated_at: Set(Utc::now().to_string()),
+            ..post
         }
         .update(db)
         .await
     }
 
+    /// Sets the whisper model used for a conversation, or `None` to use the global one.
+    pub async fn update_conversation_transcription_model(
+        db: &DbConn,
+        id: i32,
+        transcription_model: Option<String>,
+    ) -> Result<conversation::Model, DbErr> {
+        let mut conversation: conversation::ActiveModel = Conversation::find_by_id(id)
+            .one(db)
+            .await?
+            .ok_or(DbErr::Custom("Cannot find conversation.".to_owned()))
+            .map(Into::into)?;
+
+        conversation.transcription_model = Set(transcription_model);
+        conversation.updated_at = Set(Utc::now().to_string());
+        conversation.update(db).await
+    }
+
+    /// Stores how a conversation should be transcribed.
+    pub async fn update_conversation_transcription_options(
+        db: &DbConn,
+        id: i32,
+        form_data: conversation::Model,
+    ) -> Result<conversation::Model, DbErr> {
+        let mut conversation: conversation::ActiveModel = Conversation::find_by_id(id)
+            .one(db)
+            .await?
+            .ok_or(DbErr::Custom("Cannot find conversation.".to_owned()))
+            .map(Into::into)?;
+
+        conversation.language = Set(form_data.language);
+        conversation.translate = Set(form_data.translate);
+        conversation.initial_prompt = Set(form_data.initial_prompt);
+        conversation.beam_size = Set(form_data.beam_size);
+        conversation.temperature = Set(form_data.temperature);
+        conversation.updated_at = Set(Utc::now().to_string());
+        conversation.update(db).await
+    }
+
+    pub async fn update_conversation_detected_language(
+        db: &DbConn,
+        id: i32,
+        detected_language: Option<String>,
+    ) -> Result<UpdateResult, DbErr> {
+        Conversation::update_many()
+            .col_expr(
+                conversation::Column::DetectedLanguage,
+                Expr::value(detected_language),
+            )
+            .filter(conversation::Column::Id.eq(id))
+            .exec(db)
+            .await
+    }
+
+    pub async fn mark_conversation_recovered(db: &DbConn, id: i32) -> Result<UpdateResult, DbErr> {
+        Conversation::update_many()
+            .col_expr(conversation::Column::Recovered, Expr::value(true))
+            .filter(conversation::Column::Id.eq(id))
+            .exec(db)
+            .await
+    }
+
     pub async fn delete_conversation(db: &DbConn, id: i32) -> Result<DeleteResult, DbErr> {
         let conversation: conversation::ActiveModel = Conversation::find_by_id(id)
             .one(db)
@@ -51,4 +130,502 @@ impl Mutation {
     pub async fn delete_all_conversations(db: &DbConn) -> Result<DeleteResult, DbErr> {
         Conversation::delete_many().exec(db).await
     }
+
+    /// Replaces the transcript of a conversation with the given segments.
+    pub async fn create_transcript_segments(
+        db: &DbConn,
+        conversation_id: i32,
+        segments: Vec<transcript_segment::Model>,
+    ) -> Result<(), DbErr> {
+        let txn = db.begin().await?;
+
+        TranscriptSegment::delete_many()
+            .filter(transcript_segment::Column::ConversationId.eq(conversation_id))
+            .exec(&txn)
+            .await?;
+
+        // The readable transcript: one paragraph per speaker turn
+        let paragraphs = transcript::paragraphs(
+            segments
+                .iter()
+                .map(|segment| (segment.speaker, segment.text.as_str())),
+        );
+
+        Transcript::delete_many()
+            .filter(transcript::Column::ConversationId.eq(conversation_id))
+            .exec(&txn)
+            .await?;
+        transcript::ActiveModel {
+            conversation_id: Set(conversation_id),
+            text: Set(paragraphs.join("\n\n")),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+
+        if !segments.is_empty() {
+            TranscriptSegment::insert_many(segments.into_iter().map(|segment| {
+                transcript_segment::ActiveModel {
+                    conversation_id: Set(conversation_id),
+                    start_ms: Set(segment.start_ms),
+                    end_ms: Set(segment.end_ms),
+                    speaker: Set(segment.speaker),
+                    text: Set(segment.text),
+                    ..Default::default()
+                }
+            }))
+            .exec(&txn)
+            .await?;
+        }
+
+        txn.commit().await
+    }
+
+    /// Stores a new summary version. Earlier versions of the conversation are kept.
+    pub async fn create_summary(
+        db: &DbConn,
+        form_data: summary::Model,
+    ) -> Result<summary::Model, DbErr> {
+        summary::ActiveModel {
+            conversation_id: Set(form_data.conversation_id),
+            content: Set(form_data.content),
+            chunks: Set(form_data.chunks),
+            template_id: Set(form_data.template_id),
+            prompt: Set(form_data.prompt),
+            model: Set(form_data.model),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+    }
+
+    /// Pins one summary version, unpinning the others of its conversation.
+    pub async fn pin_summary(db: &DbConn, id: i32) -> Result<summary::Model, DbErr> {
+        let txn = db.begin().await?;
+
+        let summary = Summary::find_by_id(id)
+            .one(&txn)
+            .await?
+            .ok_or(DbErr::Custom("Cannot find summary.".to_owned()))?;
+
+        Summary::update_many()
+            .col_expr(summary::Column::Pinned, Expr::value(false))
+            .filter(summary::Column::ConversationId.eq(summary.conversation_id))
+            .exec(&txn)
+            .await?;
+
+        let summary = summary::ActiveModel {
+            pinned: Set(true),
+            ..summary.into()
+        }
+        .update(&txn)
+        .await?;
+
+        txn.commit().await?;
+        Ok(summary)
+    }
+
+    pub async fn create_summary_template(
+        db: &DbConn,
+        form_data: summary_template::Model,
+    ) -> Result<summary_template::Model, DbErr> {
+        summary_template::ActiveModel {
+            name: Set(form_data.name),
+            prompt: Set(form_data.prompt),
+            output_schema: Set(form_data.output_schema),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+    }
+
+    pub async fn update_summary_template(
+        db: &DbConn,
+        id: i32,
+        form_data: summary_template::Model,
+    ) -> Result<summary_template::Model, DbErr> {
+        let template: summary_template::ActiveModel = SummaryTemplate::find_by_id(id)
+            .one(db)
+            .await?
+            .ok_or(DbErr::Custom("Cannot find summary template.".to_owned()))
+            .map(Into::into)?;
+
+        summary_template::ActiveModel {
+            name: Set(form_data.name),
+            prompt: Set(form_data.prompt),
+            output_schema: Set(form_data.output_schema),
+            updated_at: Set(Utc::now().to_string()),
+            ..template
+        }
+        .update(db)
+        .await
+    }
+
+    /// Makes a template the one used when none is chosen.
+    pub async fn set_default_summary_template(
+        db: &DbConn,
+        id: i32,
+    ) -> Result<summary_template::Model, DbErr> {
+        let txn = db.begin().await?;
+
+        let template = SummaryTemplate::find_by_id(id)
+            .one(&txn)
+            .await?
+            .ok_or(DbErr::Custom("Cannot find summary template.".to_owned()))?;
+
+        SummaryTemplate::update_many()
+            .col_expr(summary_template::Column::IsDefault, Expr::value(false))
+            .exec(&txn)
+            .await?;
+
+        let template = summary_template::ActiveModel {
+            is_default: Set(true),
+            ..template.into()
+        }
+        .update(&txn)
+        .await?;
+
+        txn.commit().await?;
+        Ok(template)
+    }
+
+    pub async fn delete_summary_template(db: &DbConn, id: i32) -> Result<DeleteResult, DbErr> {
+        let template: summary_template::ActiveModel = SummaryTemplate::find_by_id(id)
+            .one(db)
+            .await?
+            .ok_or(DbErr::Custom("Cannot find summary template.".to_owned()))
+            .map(Into::into)?;
+
+        template.delete(db).await
+    }
+
+    /// Adds (or replaces) the summary of a conversation in the search index.
+    pub async fn index_conversation_summary(
+        db: &DbConn,
+        conversation_id: i32,
+        summary: &str,
+    ) -> Result<(), DbErr> {
+        let rowid = conversation::summary_search_rowid(conversation_id);
+
+        db.execute(Statement::from_sql_and_values(
+            DbBackend::Sqlite,
+            "DELETE FROM conversation_search WHERE rowid = $1",
+            [rowid.into()],
+        ))
+        .await?;
+
+        db.execute(Statement::from_sql_and_values(
+            DbBackend::Sqlite,
+            "INSERT INTO conversation_search (rowid, content, conversation_id, kind)
+            VALUES ($1, $2, $3, 'summary')",
+            [rowid.into(), summary.into(), conversation_id.into()],
+        ))
+        .await?;
+
+        Ok(())
+    }
+
+    /// Replaces the open action items of a conversation. Items marked done
+    /// stay, and extracted items repeating one of them are dropped.
+    pub async fn create_action_items(
+        db: &DbConn,
+        conversation_id: i32,
+        action_items: Vec<action_item::Model>,
+    ) -> Result<(), DbErr> {
+        let txn = db.begin().await?;
+
+        ActionItem::delete_many()
+            .filter(action_item::Column::ConversationId.eq(conversation_id))
+            .filter(action_item::Column::Status.eq(action_item::ActionItemStatus::Open))
+            .exec(&txn)
+            .await?;
+
+        let done_titles: Vec<String> = ActionItem::find()
+            .filter(action_item::Column::ConversationId.eq(conversation_id))
+            .all(&txn)
+            .await?
+            .into_iter()
+            .map(|action_item| action_item.title.trim().to_lowercase())
+            .collect();
+        let action_items: Vec<action_item::Model> = action_items
+            .into_iter()
+            .filter(|action_item| !done_titles.contains(&action_item.title.trim().to_lowercase()))
+            .collect();
+
+        if !action_items.is_empty() {
+            ActionItem::insert_many(action_items.into_iter().map(|action_item| {
+                action_item::ActiveModel {
+                    conversation_id: Set(conversation_id),
+                    title: Set(action_item.title),
+                    assignee: Set(action_item.assignee),
+                    due_date: Set(action_item.due_date),
+                    status: Set(action_item.status),
+                    source_ms: Set(action_item.source_ms),
+                    ..Default::default()
+                }
+            }))
+            .exec(&txn)
+            .await?;
+        }
+
+        txn.commit().await
+    }
+
+    /// Replaces the embedded chunks of a conversation.
+    pub async fn create_embeddings(
+        db: &DbConn,
+        conversation_id: i32,
+        embeddings: Vec<embedding::Model>,
+    ) -> Result<(), DbErr> {
+        let txn = db.begin().await?;
+
+        Embedding::delete_many()
+            .filter(embedding::Column::ConversationId.eq(conversation_id))
+            .exec(&txn)
+            .await?;
+
+        if !embeddings.is_empty() {
+            Embedding::insert_many(embeddings.into_iter().map(|embedding| {
+                embedding::ActiveModel {
+                    conversation_id: Set(conversation_id),
+                    segment_id: Set(embedding.segment_id),
+                    start_ms: Set(embedding.start_ms),
+                    end_ms: Set(embedding.end_ms),
+                    text: Set(embedding.text),
+                    model: Set(embedding.model),
+                    vector: Set(embedding.vector),
+                    ..Default::default()
+                }
+            }))
+            .exec(&txn)
+            .await?;
+        }
+
+        txn.commit().await
+    }
+
+    /// Sets the speakers of a conversation by speaker index. Speakers that
+    /// already exist keep their name, speakers no longer heard are removed.
+    pub async fn create_speakers(
+        db: &DbConn,
+        conversation_id: i32,
+        speakers: Vec<speaker::Model>,
+    ) -> Result<(), DbErr> {
+        let txn = db.begin().await?;
+
+        Speaker::delete_many()
+            .filter(speaker::Column::ConversationId.eq(conversation_id))
+            .filter(
+                speaker::Column::SpeakerIndex.is_not_in(
+                    speakers
+                        .iter()
+                        .map(|speaker| speaker.speaker_index)
+                        .collect::<Vec<_>>(),
+                ),
+            )
+            .exec(&txn)
+            .await?;
+
+        let existing = Speaker::find()
+            .filter(speaker::Column::ConversationId.eq(conversation_id))
+            .all(&txn)
+            .await?;
+
+        for speaker in speakers {
+            match existing
+                .iter()
+                .find(|existing| existing.speaker_index == speaker.speaker_index)
+            {
+                Some(existing) => {
+                    speaker::ActiveModel {
+                        id: Set(existing.id),
+                        source: Set(speaker.source),
+                        updated_at: Set(Utc::now().to_string()),
+                        ..Default::default()
+                    }
+                    .update(&txn)
+                    .await?;
+                }
+                None => {
+                    speaker::ActiveModel {
+                        conversation_id: Set(conversation_id),
+                        speaker_index: Set(speaker.speaker_index),
+                        name: Set(speaker.name),
+                        source: Set(speaker.source),
+                        ..Default::default()
+                    }
+                    .insert(&txn)
+                    .await?;
+                }
+            }
+        }
+
+        txn.commit().await
+    }
+
+    /// Reassigns transcript segments to speakers, given as (segment id, speaker) pairs.
+    pub async fn update_transcript_segment_speakers(
+        db: &DbConn,
+        speakers: Vec<(i32, i32)>,
+    ) -> Result<(), DbErr> {
+        let txn = db.begin().await?;
+
+        for (segment_id, speaker) in speakers {
+            TranscriptSegment::update_many()
+                .col_expr(transcript_segment::Column::Speaker, Expr::value(speaker))
+                .filter(transcript_segment::Column::Id.eq(segment_id))
+                .exec(&txn)
+                .await?;
+        }
+
+        txn.commit().await
+    }
+
+    /// Renames a speaker and the action items assigned to them, returning
+    /// the speaker and their old name. Summaries are left as they were written.
+    pub async fn rename_speaker(
+        db: &DbConn,
+        id: i32,
+        name: String,
+    ) -> Result<(speaker::Model, String), DbErr> {
+        let txn = db.begin().await?;
+
+        let speaker = Speaker::find_by_id(id)
+            .one(&txn)
+            .await?
+            .ok_or(DbErr::Custom("Cannot find speaker.".to_owned()))?;
+        let old_name = speaker.name.clone();
+        let conversation_id = speaker.conversation_id;
+
+        let speaker = speaker::ActiveModel {
+            name: Set(name.clone()),
+            updated_at: Set(Utc::now().to_string()),
+            ..speaker.into()
+        }
+        .update(&txn)
+        .await?;
+
+        ActionItem::update_many()
+            .col_expr(action_item::Column::Assignee, Expr::value(name))
+            .filter(action_item::Column::ConversationId.eq(conversation_id))
+            .filter(action_item::Column::Assignee.eq(old_name.clone()))
+            .exec(&txn)
+            .await?;
+
+        txn.commit().await?;
+        Ok((speaker, old_name))
+    }
+
+    pub async fn update_action_item_status(
+        db: &DbConn,
+        id: i32,
+        status: ActionItemStatus,
+    ) -> Result<action_item::Model, DbErr> {
+        let mut action_item: action_item::ActiveModel = ActionItem::find_by_id(id)
+            .one(db)
+            .await?
+            .ok_or(DbErr::Custom("Cannot find action item.".to_owned()))
+            .map(Into::into)?;
+
+        action_item.status = Set(status);
+        action_item.updated_at = Set(Utc::now().to_string());
+        action_item.update(db).await
+    }
+
+    pub async fn create_job(
+        db: &DbConn,
+        conversation_id: i32,
+        kind: JobKind,
+        params: Option<String>,
+    ) -> Result<job::Model, DbErr> {
+        job::ActiveModel {
+            conversation_id: Set(conversation_id),
+            kind: Set(kind),
+            status: Set(JobStatus::Pending),
+            attempts: Set(0),
+            params: Set(params),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+    }
+
+    /// Marks a job as running and counts the attempt.
+    pub async fn start_job(db: &DbConn, id: i32) -> Result<job::Model, DbErr> {
+        let job: job::Model = Job::find_by_id(id)
+            .one(db)
+            .await?
+            .ok_or(DbErr::Custom("Cannot find job.".to_owned()))?;
+        let attempts = job.attempts + 1;
+
+        let mut job: job::ActiveModel = job.into();
+        job.status = Set(JobStatus::Running);
+        job.attempts = Set(attempts);
+        job.updated_at = Set(Utc::now().to_string());
+        job.update(db).await
+    }
+
+    pub async fn update_job_status(
+        db: &DbConn,
+        id: i32,
+        status: JobStatus,
+        last_error: Option<String>,
+    ) -> Result<job::Model, DbErr> {
+        let mut job: job::ActiveModel = Job::find_by_id(id)
+            .one(db)
+            .await?
+            .ok_or(DbErr::Custom("Cannot find job.".to_owned()))
+            .map(Into::into)?;
+
+        job.status = Set(status);
+        job.last_error = Set(last_error);
+        job.updated_at = Set(Utc::now().to_string());
+        job.update(db).await
+    }
+
+    /// Puts a failed job back in the queue, to be picked up at `run_after`
+    /// (Unix ms) at the earliest.
+    pub async fn retry_job(
+        db: &DbConn,
+        id: i32,
+        last_error: String,
+        run_after: i64,
+    ) -> Result<job::Model, DbErr> {
+        let mut job: job::ActiveModel = Job::find_by_id(id)
+            .one(db)
+            .await?
+            .ok_or(DbErr::Custom("Cannot find job.".to_owned()))
+            .map(Into::into)?;
+
+        job.status = Set(JobStatus::Pending);
+        job.last_error = Set(Some(last_error));
+        job.run_after = Set(Some(run_after));
+        job.updated_at = Set(Utc::now().to_string());
+        job.update(db).await
+    }
+
+    /// Puts jobs that were interrupted by an app exit back in the queue.
+    pub async fn reset_running_jobs(db: &DbConn) -> Result<UpdateResult, DbErr> {
+        Job::update_many()
+            .col_expr(job::Column::Status, Expr::value(JobStatus::Pending))
+            .filter(job::Column::Status.eq(JobStatus::Running))
+            .exec(db)
+            .await
+    }
+
+    pub async fn set_setting(db: &DbConn, key: &str, value: String) -> Result<(), DbErr> {
+        Setting::insert(setting::ActiveModel {
+            key: Set(key.to_owned()),
+            value: Set(value),
+        })
+        .on_conflict(
+            OnConflict::column(setting::Column::Key)
+                .update_column(setting::Column::Value)
+                .to_owned(),
+        )
+        .exec(db)
+        .await?;
+
+        Ok(())
+    }
 }
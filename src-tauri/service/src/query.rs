@@ -1,8 +1,56 @@
-use ::entity::{conversation, conversation::Entity as Conversation};
+use ::entity::{
+    action_item::{self, Entity as ActionItem},
+    conversation,
+    conversation::Entity as Conversation,
+    embedding::{self, Entity as Embedding},
+    job::{self, Entity as Job, JobStatus},
+    setting::Entity as Setting,
+    speaker::{self, Entity as Speaker},
+    summary::{self, Entity as Summary},
+    summary_template::{self, Entity as SummaryTemplate},
+    transcript::{self, Entity as Transcript},
+    transcript_segment,
+    transcript_segment::Entity as TranscriptSegment,
+};
 use sea_orm::*;
+use serde::Serialize;
 
 pub struct Query;
 
+#[derive(Debug, Clone, PartialEq, FromQueryResult, Serialize)]
+pub struct SearchHit {
+    pub conversation_id: i32,
+    pub title: String,
+    /// One of `title`, `transcript` or `summary`.
+    pub kind: String,
+    pub segment_id: Option<i32>,
+    pub start_ms: Option<i64>,
+    /// Matching text with the matched terms wrapped in `<mark>` tags.
+    pub snippet: String,
+    pub rank: f64,
+}
+
+/// Turns free text into an FTS5 query that matches every term as a prefix,
+/// so user input can never produce an FTS5 syntax error.
+fn fts_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .map(|term| format!("\"{}\"*", term.replace('"', "\"\"")))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Like [`fts_query`] but matches any of the terms, for ranking passages
+/// against a natural language question. Very short words are dropped.
+fn fts_any_query(query: &str) -> String {
+    query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|term| term.chars().count() > 2)
+        .map(|term| format!("\"{}\"*", term))
+        .collect::<Vec<String>>()
+        .join(" OR ")
+}
+
 impl Query {
     pub async fn find_conversation_by_id(
         db: &DbConn,
@@ -26,4 +74,267 @@ impl Query {
         // Fetch paginated posts
         paginator.fetch_page(page - 1).await.map(|p| (p, num_pages))
     }
+
+    pub async fn find_transcript_segments_by_conversation_id(
+        db: &DbConn,
+        conversation_id: i32,
+    ) -> Result<Vec<transcript_segment::Model>, DbErr> {
+        TranscriptSegment::find()
+            .filter(transcript_segment::Column::ConversationId.eq(conversation_id))
+            .order_by_asc(transcript_segment::Column::StartMs)
+            .order_by_asc(transcript_segment::Column::Id)
+            .all(db)
+            .await
+    }
+
+    pub async fn find_speakers_by_conversation_id(
+        db: &DbConn,
+        conversation_id: i32,
+    ) -> Result<Vec<speaker::Model>, DbErr> {
+        Speaker::find()
+            .filter(speaker::Column::ConversationId.eq(conversation_id))
+            .order_by_asc(speaker::Column::SpeakerIndex)
+            .all(db)
+            .await
+    }
+
+    pub async fn find_setting(db: &DbConn, key: &str) -> Result<Option<String>, DbErr> {
+        Ok(Setting::find_by_id(key)
+            .one(db)
+            .await?
+            .map(|setting| setting.value))
+    }
+
+    /// The oldest pending job that is due at `now_ms`.
+    pub async fn find_next_pending_job(
+        db: &DbConn,
+        now_ms: i64,
+    ) -> Result<Option<job::Model>, DbErr> {
+        Job::find()
+            .filter(job::Column::Status.eq(JobStatus::Pending))
+            .filter(
+                Condition::any()
+                    .add(job::Column::RunAfter.is_null())
+                    .add(job::Column::RunAfter.lte(now_ms)),
+            )
+            .order_by_asc(job::Column::Id)
+            .one(db)
+            .await
+    }
+
+    /// When the next pending job that waits for a retry is due, in Unix ms.
+    pub async fn find_next_job_run_after(db: &DbConn) -> Result<Option<i64>, DbErr> {
+        Ok(Job::find()
+            .filter(job::Column::Status.eq(JobStatus::Pending))
+            .filter(job::Column::RunAfter.is_not_null())
+            .order_by_asc(job::Column::RunAfter)
+            .one(db)
+            .await?
+            .and_then(|job| job.run_after))
+    }
+
+    /// The summary shown for a conversation: the pinned version, or else the newest.
+    pub async fn find_current_summary_by_conversation_id(
+        db: &DbConn,
+        conversation_id: i32,
+    ) -> Result<Option<summary::Model>, DbErr> {
+        Summary::find()
+            .filter(summary::Column::ConversationId.eq(conversation_id))
+            .order_by_desc(summary::Column::Pinned)
+            .order_by_desc(summary::Column::Id)
+            .one(db)
+            .await
+    }
+
+    /// All summary versions of a conversation, newest first.
+    pub async fn find_summaries_by_conversation_id(
+        db: &DbConn,
+        conversation_id: i32,
+    ) -> Result<Vec<summary::Model>, DbErr> {
+        Summary::find()
+            .filter(summary::Column::ConversationId.eq(conversation_id))
+            .order_by_desc(summary::Column::Id)
+            .all(db)
+            .await
+    }
+
+    pub async fn find_summary_templates(
+        db: &DbConn,
+    ) -> Result<Vec<summary_template::Model>, DbErr> {
+        SummaryTemplate::find()
+            .order_by_asc(summary_template::Column::Id)
+            .all(db)
+            .await
+    }
+
+    pub async fn find_summary_template_by_id(
+        db: &DbConn,
+        id: i32,
+    ) -> Result<Option<summary_template::Model>, DbErr> {
+        SummaryTemplate::find_by_id(id).one(db).await
+    }
+
+    pub async fn find_default_summary_template(
+        db: &DbConn,
+    ) -> Result<Option<summary_template::Model>, DbErr> {
+        SummaryTemplate::find()
+            .filter(summary_template::Column::IsDefault.eq(true))
+            .one(db)
+            .await
+    }
+
+    pub async fn find_transcript_by_conversation_id(
+        db: &DbConn,
+        conversation_id: i32,
+    ) -> Result<Option<transcript::Model>, DbErr> {
+        Transcript::find()
+            .filter(transcript::Column::ConversationId.eq(conversation_id))
+            .one(db)
+            .await
+    }
+
+    pub async fn find_action_items_by_conversation_id(
+        db: &DbConn,
+        conversation_id: i32,
+    ) -> Result<Vec<action_item::Model>, DbErr> {
+        ActionItem::find()
+            .filter(action_item::Column::ConversationId.eq(conversation_id))
+            .order_by_asc(action_item::Column::SourceMs)
+            .order_by_asc(action_item::Column::Id)
+            .all(db)
+            .await
+    }
+
+    /// Every embedded chunk made with `model`.
+    pub async fn find_embeddings_by_model(
+        db: &DbConn,
+        model: &str,
+    ) -> Result<Vec<embedding::Model>, DbErr> {
+        Embedding::find()
+            .filter(embedding::Column::Model.eq(model))
+            .all(db)
+            .await
+    }
+
+    /// Conversations with a transcript but no chunks embedded with `model`.
+    pub async fn find_conversation_ids_without_embeddings(
+        db: &DbConn,
+        model: &str,
+    ) -> Result<Vec<i32>, DbErr> {
+        let embedded: Vec<i32> = Embedding::find()
+            .select_only()
+            .column(embedding::Column::ConversationId)
+            .distinct()
+            .filter(embedding::Column::Model.eq(model))
+            .into_tuple()
+            .all(db)
+            .await?;
+        let transcribed: Vec<i32> = TranscriptSegment::find()
+            .select_only()
+            .column(transcript_segment::Column::ConversationId)
+            .distinct()
+            .order_by_asc(transcript_segment::Column::ConversationId)
+            .into_tuple()
+            .all(db)
+            .await?;
+
+        Ok(transcribed
+            .into_iter()
+            .filter(|id| !embedded.contains(id))
+            .collect())
+    }
+
+    pub async fn find_jobs_by_conversation_id(
+        db: &DbConn,
+        conversation_id: i32,
+    ) -> Result<Vec<job::Model>, DbErr> {
+        Job::find()
+            .filter(job::Column::ConversationId.eq(conversation_id))
+            .order_by_asc(job::Column::Id)
+            .all(db)
+            .await
+    }
+
+    /// If ok, returns (ranked hits, num pages).
+    pub async fn search_conversations(
+        db: &DbConn,
+        query: &str,
+        page: u64,
+        per_page: u64,
+    ) -> Result<(Vec<SearchHit>, u64), DbErr> {
+        let query = fts_query(query);
+        if query.is_empty() {
+            return Ok((Vec::new(), 0));
+        }
+
+        let count = db
+            .query_one(Statement::from_sql_and_values(
+                DbBackend::Sqlite,
+                "SELECT COUNT(*) AS count FROM conversation_search WHERE conversation_search MATCH $1",
+                [query.clone().into()],
+            ))
+            .await?
+            .map(|row| row.try_get::<i64>("", "count"))
+            .transpose()?
+            .unwrap_or(0) as u64;
+        let num_pages = count.div_ceil(per_page.max(1));
+
+        let hits = SearchHit::find_by_statement(Statement::from_sql_and_values(
+            DbBackend::Sqlite,
+            r#"SELECT
+                s.conversation_id AS conversation_id,
+                c.title AS title,
+                s.kind AS kind,
+                s.segment_id AS segment_id,
+                s.start_ms AS start_ms,
+                snippet(conversation_search, 0, '<mark>', '</mark>', '…', 16) AS snippet,
+                bm25(conversation_search) AS rank
+            FROM conversation_search s
+            JOIN conversation c ON c.id = s.conversation_id
+            WHERE conversation_search MATCH $1
+            ORDER BY rank
+            LIMIT $2 OFFSET $3"#,
+            [
+                query.into(),
+                (per_page as i64).into(),
+                ((page.saturating_sub(1) * per_page) as i64).into(),
+            ],
+        ))
+        .all(db)
+        .await?;
+
+        Ok((hits, num_pages))
+    }
+
+    /// Ids of the transcript segments of a conversation that best match
+    /// `question`, best first.
+    pub async fn rank_transcript_segments(
+        db: &DbConn,
+        conversation_id: i32,
+        question: &str,
+        limit: u64,
+    ) -> Result<Vec<i32>, DbErr> {
+        let query = fts_any_query(question);
+        if query.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let rows = db
+            .query_all(Statement::from_sql_and_values(
+                DbBackend::Sqlite,
+                r#"SELECT segment_id
+                FROM conversation_search
+                WHERE conversation_search MATCH $1
+                    AND conversation_id = $2
+                    AND kind = 'transcript'
+                ORDER BY bm25(conversation_search)
+                LIMIT $3"#,
+                [query.into(), conversation_id.into(), (limit as i64).into()],
+            ))
+            .await?;
+
+        rows.iter()
+            .map(|row| row.try_get::<i32>("", "segment_id"))
+            .collect()
+    }
 }
@@ -32,8 +32,17 @@ async fn main() {
             conversation::Model {
                 id: 0,
                 title: "Title D".to_owned(),
+                title_edited: false,
                 updated_at: None,
                 created_at: None,
+                transcription_model: None,
+                language: None,
+                translate: false,
+                initial_prompt: None,
+                beam_size: None,
+                temperature: None,
+                detected_language: None,
+                recovered: false,
             },
         )
         .await
@@ -57,8 +66,17 @@ async fn main() {
             conversation::Model {
                 id: 1,
                 title: "New Title A".to_owned(),
+                title_edited: false,
                 created_at: None,
                 updated_at: None,
+                transcription_model: None,
+                language: None,
+                translate: false,
+                initial_prompt: None,
+                beam_size: None,
+                temperature: None,
+                detected_language: None,
+                recovered: false,
             },
         )
         .await
@@ -0,0 +1,280 @@
+use entity::transcript_segment;
+use log::error;
+use serde::{Deserialize, Serialize};
+use service::Query;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::jobs::recording_dir;
+use crate::llm::SummaryBackends;
+use crate::manifest::RecordingManifest;
+use crate::speakers::SpeakerNames;
+use crate::summarize::{estimate_tokens, format_offset, parse_offset, CHUNK_TOKEN_BUDGET};
+use crate::AppState;
+
+// Segments around every match, so the model sees what was said before and after
+const CONTEXT_SEGMENTS: usize = 2;
+const MAX_MATCHES: u64 = 20;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnswerTokenEvent {
+    pub conversation_id: i32,
+    pub token: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Citation {
+    pub segment_id: i32,
+    /// Start of the segment in the conversation, counting pauses.
+    pub start_ms: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Answer {
+    pub answer: String,
+    /// The segments the answer cites, in the order they are cited.
+    pub citations: Vec<Citation>,
+}
+
+/// Picks the segments the question is answered from. A transcript that fits
+/// `token_budget` is used whole, otherwise the best matches (`ranked_ids`)
+/// with the segments around them, until the budget is used up.
+pub fn select_segments<'a>(
+    segments: &'a [transcript_segment::Model],
+    ranked_ids: &[i32],
+    token_budget: usize,
+) -> Vec<&'a transcript_segment::Model> {
+    let total: usize = segments
+        .iter()
+        .map(|segment| estimate_tokens(&segment.text))
+        .sum();
+    if total <= token_budget {
+        return segments.iter().collect();
+    }
+
+    let mut selected = vec![false; segments.len()];
+    let mut tokens = 0;
+    'matches: for id in ranked_ids {
+        let Some(position) = segments.iter().position(|segment| segment.id == *id) else {
+            continue;
+        };
+        let window = position.saturating_sub(CONTEXT_SEGMENTS)
+            ..=(position + CONTEXT_SEGMENTS).min(segments.len() - 1);
+        for (segment, selected) in segments[window.clone()].iter().zip(&mut selected[window]) {
+            if *selected {
+                continue;
+            }
+            let segment_tokens = estimate_tokens(&segment.text);
+            if tokens + segment_tokens > token_budget {
+                break 'matches;
+            }
+            tokens += segment_tokens;
+            *selected = true;
+        }
+    }
+
+    segments
+        .iter()
+        .zip(selected)
+        .filter_map(|(segment, selected)| selected.then_some(segment))
+        .collect()
+}
+
+/// One line per segment, led by its `[mm:ss]` offset. Skipped parts of the
+/// transcript are marked with an ellipsis.
+fn format_excerpts(
+    segments: &[transcript_segment::Model],
+    selected: &[&transcript_segment::Model],
+    speakers: &SpeakerNames,
+) -> String {
+    let mut lines = Vec::with_capacity(selected.len());
+    let mut previous: Option<usize> = None;
+    for segment in selected {
+        let position = segments.iter().position(|s| s.id == segment.id);
+        if let (Some(previous), Some(position)) = (previous, position) {
+            if position > previous + 1 {
+                lines.push("…".to_string());
+            }
+        }
+        previous = position;
+        lines.push(format!(
+            "[{}] {}: {}",
+            format_offset(segment.start_ms),
+            speakers.label(segment.speaker),
+            segment.text.trim()
+        ));
+    }
+    lines.join("\n")
+}
+
+/// Resolves the `[mm:ss]` timestamps cited in `answer` to the segments they
+/// point into. Timestamps that point to no segment are dropped.
+pub fn find_citations(answer: &str, segments: &[&transcript_segment::Model]) -> Vec<Citation> {
+    let mut citations: Vec<Citation> = Vec::new();
+    for part in answer.split('[').skip(1) {
+        let Some((inside, _)) = part.split_once(']') else {
+            continue;
+        };
+        for timestamp in inside.split(',') {
+            let Some(ms) = parse_offset(timestamp) else {
+                continue;
+            };
+            // Offsets are rounded to the second, so allow for that
+            let Some(segment) = segments
+                .iter()
+                .rev()
+                .find(|segment| segment.start_ms / 1000 * 1000 <= ms && ms < segment.end_ms)
+            else {
+                continue;
+            };
+            let citation = Citation {
+                segment_id: segment.id,
+                start_ms: segment.start_ms,
+            };
+            if !citations.contains(&citation) {
+                citations.push(citation);
+            }
+        }
+    }
+    citations
+}
+
+/// Answers a question about a conversation from the relevant parts of its
+/// transcript. The answer is streamed as `conversation-answer` events while
+/// it is generated and returned with the segments it cites.
+#[tauri::command]
+pub async fn ask_conversation(
+    handle: AppHandle,
+    conversation_id: i32,
+    question: String,
+) -> Result<Answer, String> {
+    let question = question.trim().to_string();
+    if question.is_empty() {
+        return Err("Question cannot be empty".to_string());
+    }
+
+    let app_state: State<AppState> = handle.state();
+    let mut segments =
+        Query::find_transcript_segments_by_conversation_id(&app_state.db, conversation_id)
+            .await
+            .map_err(|e| e.to_string())?;
+    if segments.is_empty() {
+        return Err("Conversation has no transcript".to_string());
+    }
+    RecordingManifest::load_conversation_times(
+        &recording_dir(&handle, conversation_id)?,
+        &mut segments,
+    )?;
+    let ranked_ids =
+        Query::rank_transcript_segments(&app_state.db, conversation_id, &question, MAX_MATCHES)
+            .await
+            .map_err(|e| e.to_string())?;
+    let summary = Query::find_current_summary_by_conversation_id(&app_state.db, conversation_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    let speakers = SpeakerNames::load(&app_state.db, conversation_id).await?;
+
+    let mut prompt = String::from(
+        "Answer the question about a meeting using only the transcript excerpts below. Every \
+        line starts with its [mm:ss] timestamp and speaker label. After every statement, cite \
+        the timestamps of the lines it is based on, like [12:34]. If the excerpts don't contain \
+        the answer, say so.\n",
+    );
+    // The summary shares the budget with the excerpts, a long one is left out
+    // rather than crowding them out
+    let summary = summary
+        .map(|summary| format!("Summary of the meeting:\n{}\n", summary.content))
+        .filter(|summary| estimate_tokens(summary) <= CHUNK_TOKEN_BUDGET / 2);
+    if let Some(summary) = &summary {
+        prompt.push_str(summary);
+    }
+    let token_budget =
+        CHUNK_TOKEN_BUDGET.saturating_sub(estimate_tokens(&prompt) + estimate_tokens(&question));
+    let selected = select_segments(&segments, &ranked_ids, token_budget);
+    prompt.push_str(&format!(
+        "Transcript excerpts:\n{}\nQuestion: {}",
+        format_excerpts(&segments, &selected, &speakers),
+        question
+    ));
+
+    let backends: State<SummaryBackends> = handle.state();
+    let backend = backends.current(&app_state.db).await?;
+    let emitter = handle.clone();
+    let answer = backend
+        .complete_streaming(&prompt, &move |token: &str| {
+            let event = AnswerTokenEvent {
+                conversation_id,
+                token: token.to_string(),
+            };
+            if let Err(err) = emitter.emit("conversation-answer", event) {
+                error!("Failed to emit answer token: {}", err);
+            }
+        })
+        .await?;
+    let answer = answer.trim().to_string();
+
+    Ok(Answer {
+        citations: find_citations(&answer, &selected),
+        answer,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::segment;
+
+    #[test]
+    fn short_transcripts_are_used_whole() {
+        let segments = vec![
+            segment(1, 0, 5_000, 0, "Hello."),
+            segment(2, 5_000, 10_000, 0, "Bye."),
+        ];
+
+        let selected = select_segments(&segments, &[], 100);
+
+        assert_eq!(selected.len(), 2);
+    }
+
+    #[test]
+    fn matches_are_selected_with_their_neighbours() {
+        let segments: Vec<_> = (0..20)
+            .map(|i| {
+                let start_ms = i as i64 * 5_000;
+                segment(i + 1, start_ms, start_ms + 5_000, 0, &"word ".repeat(8))
+            })
+            .collect();
+
+        let selected = select_segments(&segments, &[10], 100);
+
+        let ids: Vec<i32> = selected.iter().map(|segment| segment.id).collect();
+        assert_eq!(ids, vec![8, 9, 10, 11, 12]);
+    }
+
+    #[test]
+    fn cited_timestamps_resolve_to_segments() {
+        let segments = vec![
+            segment(1, 0, 5_000, 0, "a"),
+            segment(2, 5_400, 10_400, 0, "b"),
+        ];
+        let selected: Vec<_> = segments.iter().collect();
+
+        let citations = find_citations(
+            "We moved the launch [00:05]. Nobody objected [00:05, 00:01] [99:00] [note].",
+            &selected,
+        );
+
+        assert_eq!(
+            citations,
+            vec![
+                Citation {
+                    segment_id: 2,
+                    start_ms: 5_400
+                },
+                Citation {
+                    segment_id: 1,
+                    start_ms: 0
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,293 @@
+use byteorder::{ByteOrder, LittleEndian};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Device, SampleFormat};
+use log::info;
+use std::sync::Arc;
+use std::time::Instant;
+use tauri::async_runtime::Mutex;
+use tokio::sync::mpsc;
+
+use super::{CaptureStream, StreamFormat};
+
+pub enum DeviceType {
+    AudioInput,
+    AudioOutput,
+}
+
+/// A capture stream of a cpal input device.
+pub struct CpalStream {
+    stream: cpal::Stream,
+    format: StreamFormat,
+    device_name: String,
+}
+
+impl CaptureStream for CpalStream {
+    fn play(&mut self) -> Result<(), String> {
+        self.stream
+            .play()
+            .map_err(|err| format!("Failed to play stream: {}", err))
+    }
+
+    fn pause(&mut self) -> Result<(), String> {
+        self.stream
+            .pause()
+            .map_err(|err| format!("Failed to pause stream: {}", err))
+    }
+
+    fn format(&self) -> StreamFormat {
+        self.format
+    }
+
+    fn device_name(&self) -> &str {
+        &self.device_name
+    }
+}
+
+/// Names of the input devices of the default host, the default one first.
+pub fn input_device_names() -> Result<Vec<String>, String> {
+    let host = cpal::default_host();
+    let default_device = host
+        .default_input_device()
+        .ok_or("No default input device available")?;
+    let default_device_name = default_device.name().map_err(|e| e.to_string())?;
+
+    let devices = host.input_devices().map_err(|e| e.to_string())?;
+    let mut input_device_names: Vec<String> = devices
+        .filter_map(|device| {
+            let supported_input_configs = device.supported_input_configs();
+            if supported_input_configs.is_ok() && supported_input_configs.unwrap().count() > 0 {
+                device.name().ok()
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    input_device_names.retain(|name| name != &default_device_name);
+    input_device_names.insert(0, default_device_name);
+
+    Ok(input_device_names)
+}
+
+/// Opens a stream of the input device called `device_name`, or of the
+/// default input device.
+pub fn open_input_stream(
+    device_name: Option<&str>,
+    sender: mpsc::Sender<Vec<u8>>,
+) -> Result<CpalStream, String> {
+    let device = get_device(device_name, DeviceType::AudioInput)?;
+    let config: cpal::SupportedStreamConfig = device
+        .supported_input_configs()
+        .map_err(|e| format!("Failed to get supported input configs: {}", e))?
+        .find(|c| {
+            c.sample_format() == SampleFormat::F32
+                || c.sample_format() == SampleFormat::I16
+                || c.sample_format() == SampleFormat::I8
+                || c.sample_format() == SampleFormat::I32
+        })
+        .or_else(|| {
+            device
+                .supported_input_configs()
+                .ok()
+                .and_then(|mut configs| configs.next())
+        })
+        .ok_or("No supported input config")?
+        .with_max_sample_rate();
+    let sample_format = match config.sample_format() {
+        SampleFormat::I8 => "s8",
+        SampleFormat::I16 => "s16le",
+        SampleFormat::I32 => "s32le",
+        SampleFormat::F32 => "f32le",
+        sample_format => return Err(format!("Unsupported sample format {}", sample_format)),
+    };
+
+    let device_name = device.name().map_err(|e| e.to_string())?;
+    info!("Building input stream for {}", device_name);
+    let stream = build_audio_stream(&config, &device, Arc::new(Mutex::new(None)), Some(sender))
+        .map_err(|err| format!("Failed to build input stream: {}", err))?;
+
+    Ok(CpalStream {
+        stream,
+        format: StreamFormat {
+            sample_rate: config.sample_rate().0,
+            channels: config.channels(),
+            sample_format,
+        },
+        device_name,
+    })
+}
+
+/// The device called `custom_device`, or the default input device when there
+/// is none by that name.
+pub fn get_device(custom_device: Option<&str>, device_type: DeviceType) -> Result<Device, String> {
+    info!("Custom device: {:?}", custom_device);
+
+    let host = cpal::default_host();
+    let all_devices = host
+        .devices()
+        .map_err(|err| format!("Failed to get devices: {}", err))?;
+    let mut devices = all_devices.filter_map(|device| match device_type {
+        DeviceType::AudioInput => {
+            let supported_input_configs = device.supported_input_configs();
+            if supported_input_configs.is_ok() && supported_input_configs.unwrap().count() > 0 {
+                Some(device)
+            } else {
+                None
+            }
+        }
+        DeviceType::AudioOutput => {
+            let supported_output_configs = device.supported_output_configs();
+            if supported_output_configs.is_ok() && supported_output_configs.unwrap().count() > 0 {
+                Some(device)
+            } else {
+                None
+            }
+        }
+    });
+
+    let custom_device = custom_device.and_then(|custom_device_name| {
+        devices.find(|d| {
+            d.name()
+                .map(|name| name == custom_device_name)
+                .unwrap_or(false)
+        })
+    });
+    let device = match custom_device {
+        Some(device) => device,
+        None => host
+            .default_input_device()
+            .ok_or("No default input device available")?,
+    };
+
+    info!(
+        "Using audio input device: {}",
+        device
+            .name()
+            .map_err(|err| format!("Failed to get device name: {}", err))?
+    );
+    Ok(device)
+}
+
+fn build_audio_stream(
+    stream_config: &cpal::SupportedStreamConfig,
+    device: &Device,
+    audio_start_time: Arc<Mutex<Option<Instant>>>,
+    audio_channel_sender: Option<mpsc::Sender<Vec<u8>>>,
+) -> Result<cpal::Stream, cpal::BuildStreamError> {
+    let err_fn = move |err| {
+        info!("an error occurred on stream: {}", err);
+    };
+
+    let stream_result: Result<cpal::Stream, cpal::BuildStreamError> =
+        match stream_config.sample_format() {
+            SampleFormat::I8 => device.build_input_stream(
+                &stream_config.config(),
+                {
+                    let audio_start_time = Arc::clone(&audio_start_time);
+                    move |data: &[i8], _: &_| {
+                        let mut first_frame_time_guard = audio_start_time.try_lock();
+
+                        let bytes = data.iter().map(|&sample| sample as u8).collect::<Vec<u8>>();
+                        if let Some(sender) = &audio_channel_sender {
+                            if sender.try_send(bytes).is_err() {
+                                info!("Channel send error. Dropping data.");
+                            }
+                        }
+
+                        if let Ok(ref mut start_time_option) = first_frame_time_guard {
+                            if start_time_option.is_none() {
+                                **start_time_option = Some(Instant::now());
+
+                                info!("Audio start time captured");
+                            }
+                        }
+                    }
+                },
+                err_fn,
+                None,
+            ),
+            SampleFormat::I16 => device.build_input_stream(
+                &stream_config.config(),
+                {
+                    let audio_start_time = Arc::clone(&audio_start_time);
+                    move |data: &[i16], _: &_| {
+                        let mut first_frame_time_guard = audio_start_time.try_lock();
+
+                        let mut bytes = vec![0; data.len() * 2];
+                        LittleEndian::write_i16_into(data, &mut bytes);
+                        if let Some(sender) = &audio_channel_sender {
+                            if sender.try_send(bytes).is_err() {
+                                info!("Channel send error. Dropping data.");
+                            }
+                        }
+
+                        if let Ok(ref mut start_time_option) = first_frame_time_guard {
+                            if start_time_option.is_none() {
+                                **start_time_option = Some(Instant::now());
+
+                                info!("Audio start time captured");
+                            }
+                        }
+                    }
+                },
+                err_fn,
+                None,
+            ),
+            SampleFormat::I32 => device.build_input_stream(
+                &stream_config.config(),
+                {
+                    let audio_start_time = Arc::clone(&audio_start_time);
+                    move |data: &[i32], _: &_| {
+                        let mut first_frame_time_guard = audio_start_time.try_lock();
+
+                        let mut bytes = vec![0; data.len() * 4];
+                        LittleEndian::write_i32_into(data, &mut bytes);
+                        if let Some(sender) = &audio_channel_sender {
+                            if sender.try_send(bytes).is_err() {
+                                info!("Channel send error. Dropping data.");
+                            }
+                        }
+
+                        if let Ok(ref mut start_time_option) = first_frame_time_guard {
+                            if start_time_option.is_none() {
+                                **start_time_option = Some(Instant::now());
+
+                                info!("Audio start time captured");
+                            }
+                        }
+                    }
+                },
+                err_fn,
+                None,
+            ),
+            SampleFormat::F32 => device.build_input_stream(
+                &stream_config.config(),
+                {
+                    let audio_start_time = Arc::clone(&audio_start_time);
+                    move |data: &[f32], _: &_| {
+                        let mut first_frame_time_guard = audio_start_time.try_lock();
+
+                        let mut bytes = vec![0; data.len() * 4];
+                        LittleEndian::write_f32_into(data, &mut bytes);
+                        if let Some(sender) = &audio_channel_sender {
+                            if sender.try_send(bytes).is_err() {
+                                info!("Channel send error. Dropping data.");
+                            }
+                        }
+
+                        if let Ok(ref mut start_time_option) = first_frame_time_guard {
+                            if start_time_option.is_none() {
+                                **start_time_option = Some(Instant::now());
+
+                                info!("Audio start time captured");
+                            }
+                        }
+                    }
+                },
+                err_fn,
+                None,
+            ),
+            _sample_format => Err(cpal::BuildStreamError::DeviceNotAvailable),
+        };
+    stream_result
+}
@@ -0,0 +1,190 @@
+use std::io::Read;
+use std::process::{Child, Command, Stdio};
+use std::thread::{self, JoinHandle};
+
+use log::info;
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+use super::cpal_stream::{self, input_device_names};
+use super::{AudioCaptureBackend, CaptureStream, StreamFormat};
+
+// PulseAudio, and PipeWire's PulseAudio server, resolve this to the monitor
+// of the default sink
+const DEFAULT_MONITOR: &str = "@DEFAULT_MONITOR@";
+// What system audio is recorded as, the server converts to it
+const MONITOR_FORMAT: StreamFormat = StreamFormat {
+    sample_rate: 48_000,
+    channels: 2,
+    sample_format: "s16le",
+};
+// 10 ms of audio in the monitor format
+const MONITOR_CHUNK_BYTES: usize = 48_000 / 100 * 2 * 2;
+
+/// Records the microphone through ALSA and system audio from the PulseAudio
+/// monitor source of the selected sink.
+#[derive(Default)]
+pub struct LinuxCaptureBackend {
+    input_device: Option<String>,
+    monitor_source: Option<String>,
+}
+
+impl LinuxCaptureBackend {
+    pub fn new() -> Self {
+        LinuxCaptureBackend::default()
+    }
+}
+
+/// Names of the PulseAudio sinks, from `pactl list short sinks`.
+fn pulse_sinks() -> Result<Vec<String>, String> {
+    let output = Command::new("pactl")
+        .args(["list", "short", "sinks"])
+        .output()
+        .map_err(|err| format!("Failed to list PulseAudio sinks: {}", err))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to list PulseAudio sinks: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split('\t').nth(1))
+        .map(str::to_string)
+        .collect())
+}
+
+impl AudioCaptureBackend for LinuxCaptureBackend {
+    fn input_devices(&self) -> Result<Vec<String>, String> {
+        input_device_names()
+    }
+
+    fn output_devices(&self) -> Result<Vec<String>, String> {
+        pulse_sinks()
+    }
+
+    fn select_input_device(&mut self, name: &str) -> Result<(), String> {
+        self.input_device = Some(name.to_string());
+        Ok(())
+    }
+
+    fn select_output_device(&mut self, name: &str) -> Result<(), String> {
+        if !pulse_sinks()?.iter().any(|sink| sink == name) {
+            return Err(format!("Unknown output device {}", name));
+        }
+        self.monitor_source = Some(format!("{}.monitor", name));
+        Ok(())
+    }
+
+    fn open_mic_stream(
+        &mut self,
+        sender: mpsc::Sender<Vec<u8>>,
+    ) -> Result<Box<dyn CaptureStream>, String> {
+        Ok(Box::new(cpal_stream::open_input_stream(
+            self.input_device.as_deref(),
+            sender,
+        )?))
+    }
+
+    fn open_loopback_stream(
+        &mut self,
+        sender: mpsc::Sender<Vec<u8>>,
+    ) -> Result<Box<dyn CaptureStream>, String> {
+        let available = Command::new("parec")
+            .arg("--version")
+            .output()
+            .is_ok_and(|output| output.status.success());
+        if !available {
+            return Err("Recording system audio needs parec from pulseaudio-utils".to_string());
+        }
+
+        let monitor_source = self
+            .monitor_source
+            .clone()
+            .unwrap_or_else(|| DEFAULT_MONITOR.to_string());
+        info!("Recording system audio from {}", monitor_source);
+        Ok(Box::new(MonitorStream {
+            source: monitor_source,
+            sender,
+            recorder: None,
+        }))
+    }
+}
+
+/// Records a PulseAudio source through `parec`, which is told the source on
+/// its command line. Pausing stops the process, playing starts a new one.
+struct MonitorStream {
+    source: String,
+    sender: mpsc::Sender<Vec<u8>>,
+    /// The running `parec` and the thread forwarding what it records.
+    recorder: Option<(Child, JoinHandle<()>)>,
+}
+
+impl CaptureStream for MonitorStream {
+    fn play(&mut self) -> Result<(), String> {
+        if self.recorder.is_some() {
+            return Ok(());
+        }
+
+        let mut child = Command::new("parec")
+            .arg(format!("--device={}", self.source))
+            .arg(format!("--format={}", MONITOR_FORMAT.sample_format))
+            .arg(format!("--rate={}", MONITOR_FORMAT.sample_rate))
+            .arg(format!("--channels={}", MONITOR_FORMAT.channels))
+            .arg("--raw")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| format!("Failed to record {}: {}", self.source, err))?;
+        let mut stdout = child
+            .stdout
+            .take()
+            .ok_or("Failed to read the output of parec")?;
+
+        let sender = self.sender.clone();
+        let thread = thread::spawn(move || {
+            let mut buffer = vec![0; MONITOR_CHUNK_BYTES];
+            loop {
+                let read = match stdout.read(&mut buffer) {
+                    Ok(0) | Err(_) => break,
+                    Ok(read) => read,
+                };
+                match sender.try_send(buffer[..read].to_vec()) {
+                    Err(TrySendError::Full(_)) => info!("Channel send error. Dropping data."),
+                    Err(TrySendError::Closed(_)) => break,
+                    Ok(()) => {}
+                }
+            }
+        });
+        self.recorder = Some((child, thread));
+        Ok(())
+    }
+
+    fn pause(&mut self) -> Result<(), String> {
+        let Some((mut child, thread)) = self.recorder.take() else {
+            return Ok(());
+        };
+        // Fails when it already exited, which is what pausing is after
+        let _ = child.kill();
+        child
+            .wait()
+            .map_err(|err| format!("Failed to stop parec: {}", err))?;
+        thread
+            .join()
+            .map_err(|_| "System audio thread panicked".to_string())
+    }
+
+    fn format(&self) -> StreamFormat {
+        MONITOR_FORMAT
+    }
+
+    fn device_name(&self) -> &str {
+        &self.source
+    }
+}
+
+impl Drop for MonitorStream {
+    fn drop(&mut self) {
+        let _ = self.pause();
+    }
+}
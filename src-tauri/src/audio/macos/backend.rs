@@ -0,0 +1,262 @@
+use byteorder::{ByteOrder, LittleEndian};
+use coreaudio::audio_unit::macos_helpers::{
+    audio_unit_from_device_id, get_audio_device_ids_for_scope, get_audio_device_supports_scope,
+    get_default_device_id, get_device_id_from_name, get_device_name,
+};
+use coreaudio::audio_unit::render_callback::{self, data};
+use coreaudio::audio_unit::{AudioUnit, Element, Scope};
+use coreaudio_sys::AudioDeviceID;
+use log::info;
+use std::sync::Arc;
+use std::time::Instant;
+use tauri::async_runtime::Mutex;
+use tokio::sync::{mpsc, watch};
+use uuid::Uuid;
+
+use super::aggregate_device::{create_input_aggregate_device, create_output_aggregate_device};
+use super::device_listener::ActiveListener;
+use super::helpers::{check_device_exists, get_device_uid};
+use crate::audio::cpal_stream::{self, input_device_names};
+use crate::audio::{AudioCaptureBackend, CaptureStream, StreamFormat};
+
+const LOOPBACK_SAMPLE_RATE: f64 = 44100.0;
+
+/// Records the microphone through the "Platy Microphone" aggregate device and
+/// system audio through a process tap on the selected output device.
+pub struct MacosCaptureBackend {
+    // Boxed, the listener hands its own address to CoreAudio
+    active_listener: Box<ActiveListener>,
+    input_device_id: AudioDeviceID,
+    aggregate_device_id: AudioDeviceID,
+}
+
+impl MacosCaptureBackend {
+    pub fn new(activity: watch::Sender<bool>) -> Result<Self, String> {
+        let device_id = get_default_device_id(false).ok_or("Failed to get default device")?;
+        let device_uid = get_device_uid(device_id).map_err(|e| e.to_string())?;
+        let aggregate_device_result = create_output_aggregate_device(
+            &device_uid,
+            "Platy Speaker",
+            &Uuid::new_v4().to_string(),
+        )
+        .map_err(|err| format!("Failed to create aggregate device: {}", err))?;
+
+        let device_exists = check_device_exists("Platy Microphone");
+        if !device_exists {
+            info!("Aggregate microphone device not found, creating one");
+            create_input_aggregate_device("BuiltInMicrophoneDevice")
+                .map_err(|err| format!("Failed to create aggregate device: {}", err))?;
+        } else {
+            info!("Aggregate microphone already exists");
+        }
+
+        let input_device_id = get_device_id_from_name("Platy Microphone", true)
+            .ok_or("Platy Microphone doesn't exist")?;
+
+        let mut active_listener = Box::new(ActiveListener::new(activity));
+        active_listener
+            .register(input_device_id)
+            .map_err(|err| format!("Failed to register listener: {}", err))?;
+
+        Ok(MacosCaptureBackend {
+            active_listener,
+            input_device_id,
+            aggregate_device_id: aggregate_device_result.aggregate_device_id,
+        })
+    }
+}
+
+impl AudioCaptureBackend for MacosCaptureBackend {
+    fn input_devices(&self) -> Result<Vec<String>, String> {
+        let mut input_device_names = input_device_names()?;
+        input_device_names.retain(|name| name != "Platy Speaker" && name != "Platy Microphone");
+        Ok(input_device_names)
+    }
+
+    fn output_devices(&self) -> Result<Vec<String>, String> {
+        let all_devices = get_audio_device_ids_for_scope(Scope::Output)
+            .map_err(|err| format!("Failed to get device ids: {}", err))?;
+        let output_devices: Vec<String> = all_devices
+            .into_iter()
+            .filter(|device| {
+                get_audio_device_supports_scope(*device, Scope::Output).unwrap_or(false)
+            })
+            .filter_map(|device| get_device_name(device).ok())
+            .filter(|device_name| device_name != "Platy Speaker")
+            .collect();
+
+        Ok(output_devices)
+    }
+
+    /// Recording always goes through the aggregate microphone, the selected
+    /// device is only watched for activity.
+    fn select_input_device(&mut self, name: &str) -> Result<(), String> {
+        self.active_listener
+            .unregister()
+            .map_err(|err| format!("Failed to unregister device listener: {}", err))?;
+
+        let device_id =
+            get_device_id_from_name(name, true).ok_or("Failed to get device from name")?;
+
+        self.active_listener
+            .register(device_id)
+            .map_err(|err| format!("Failed to register listener: {}", err))
+    }
+
+    fn select_output_device(&mut self, name: &str) -> Result<(), String> {
+        let device_id =
+            get_device_id_from_name(name, false).ok_or("Failed to get device id from name")?;
+        let device_uid = get_device_uid(device_id).map_err(|e| e.to_string())?;
+        let result = create_output_aggregate_device(
+            &device_uid,
+            "Platy Speaker",
+            &Uuid::new_v4().to_string(),
+        )
+        .map_err(|err| format!("Failed to create aggregate device: {}", err))?;
+        info!(
+            "updated output device {} aggregate id: {} tap id: {}",
+            name, result.aggregate_device_id, result.tap_id,
+        );
+        self.aggregate_device_id = result.aggregate_device_id;
+        Ok(())
+    }
+
+    fn open_mic_stream(
+        &mut self,
+        sender: mpsc::Sender<Vec<u8>>,
+    ) -> Result<Box<dyn CaptureStream>, String> {
+        let input_device_name = get_device_name(self.input_device_id)
+            .map_err(|err| format!("Failed to get input device name: {}", err))?;
+        Ok(Box::new(cpal_stream::open_input_stream(
+            Some(&input_device_name),
+            sender,
+        )?))
+    }
+
+    fn open_loopback_stream(
+        &mut self,
+        sender: mpsc::Sender<Vec<u8>>,
+    ) -> Result<Box<dyn CaptureStream>, String> {
+        let device_name = get_device_name(self.aggregate_device_id)
+            .map_err(|err| format!("Failed to get output device name: {}", err))?;
+        info!(
+            "output_device id: {} name: {}",
+            self.aggregate_device_id, device_name
+        );
+        let audio_unit = build_coreaudio_audio_stream(
+            self.aggregate_device_id,
+            LOOPBACK_SAMPLE_RATE,
+            2,
+            Arc::new(Mutex::new(None)),
+            Some(sender),
+        )
+        .map_err(|err| format!("Failed to build output stream: {}", err))?;
+
+        Ok(Box::new(CoreAudioStream {
+            audio_unit,
+            device_name,
+        }))
+    }
+}
+
+/// A capture stream of a CoreAudio device, delivering mono `f32` samples.
+struct CoreAudioStream {
+    audio_unit: AudioUnit,
+    device_name: String,
+}
+
+impl CaptureStream for CoreAudioStream {
+    fn play(&mut self) -> Result<(), String> {
+        self.audio_unit
+            .start()
+            .map_err(|err| format!("Failed to play stream: {}", err))
+    }
+
+    fn pause(&mut self) -> Result<(), String> {
+        self.audio_unit
+            .stop()
+            .map_err(|err| format!("Failed to pause stream: {}", err))
+    }
+
+    fn format(&self) -> StreamFormat {
+        StreamFormat {
+            sample_rate: LOOPBACK_SAMPLE_RATE as u32,
+            channels: 1,
+            sample_format: "f32le",
+        }
+    }
+
+    fn device_name(&self) -> &str {
+        &self.device_name
+    }
+}
+
+type S = f32;
+const SAMPLE_FORMAT: coreaudio::audio_unit::SampleFormat = coreaudio::audio_unit::SampleFormat::F32;
+fn build_coreaudio_audio_stream(
+    device_id: AudioDeviceID,
+    sample_rate: f64,
+    _channels: u32,
+    audio_start_time: Arc<Mutex<Option<Instant>>>,
+    audio_channel_sender: Option<mpsc::Sender<Vec<u8>>>,
+) -> Result<AudioUnit, coreaudio::Error> {
+    info!("Input device: {}", get_device_name(device_id).unwrap());
+    let format_flag = match SAMPLE_FORMAT {
+        coreaudio::audio_unit::SampleFormat::F32 => {
+            coreaudio::audio_unit::audio_format::LinearPcmFlags::IS_FLOAT
+                | coreaudio::audio_unit::audio_format::LinearPcmFlags::IS_PACKED
+        }
+        coreaudio::audio_unit::SampleFormat::I32
+        | coreaudio::audio_unit::SampleFormat::I16
+        | coreaudio::audio_unit::SampleFormat::I8 => {
+            coreaudio::audio_unit::audio_format::LinearPcmFlags::IS_SIGNED_INTEGER
+                | coreaudio::audio_unit::audio_format::LinearPcmFlags::IS_PACKED
+        }
+        _ => {
+            unimplemented!("Please use one of the packed formats");
+        }
+    };
+
+    let in_stream_format = coreaudio::audio_unit::StreamFormat {
+        sample_rate,
+        sample_format: SAMPLE_FORMAT,
+        flags: format_flag,
+        channels: 1,
+    };
+
+    let mut input_audio_unit = audio_unit_from_device_id(device_id, true)?;
+    let id = coreaudio::sys::kAudioUnitProperty_StreamFormat;
+    let asbd = in_stream_format.to_asbd();
+    input_audio_unit.set_property(id, Scope::Output, Element::Input, Some(&asbd))?;
+
+    type Args = render_callback::Args<data::Interleaved<S>>;
+    // Define input callback
+    let callback = move |args: Args| {
+        let Args { data, .. } = args;
+        let audio_start_time = Arc::clone(&audio_start_time);
+
+        let mut first_frame_time_guard = audio_start_time.try_lock();
+
+        if let Some(sender) = &audio_channel_sender {
+            let mut bytes = vec![0; data.buffer.len() * 4];
+            LittleEndian::write_f32_into(data.buffer, &mut bytes);
+            if sender.try_send(bytes).is_err() {
+                info!("Channel send error. Dropping data.");
+            }
+        }
+
+        if let Ok(ref mut start_time_option) = first_frame_time_guard {
+            if start_time_option.is_none() {
+                **start_time_option = Some(Instant::now());
+
+                info!("Audio start time captured");
+            }
+        }
+
+        Ok(())
+    };
+
+    input_audio_unit.set_input_callback(callback)?;
+
+    Ok(input_audio_unit)
+}
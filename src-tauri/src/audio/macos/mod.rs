@@ -1,4 +1,7 @@
 pub mod aggregate_device;
+pub mod backend;
 mod ca_tap_description;
+pub mod device_listener;
 pub mod helpers;
+pub mod proxy_audio;
 pub mod tap;
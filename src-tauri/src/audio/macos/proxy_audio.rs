@@ -0,0 +1,175 @@
+use std::ffi::{CStr, CString};
+use std::mem;
+use std::os::raw::{c_char, c_void};
+use std::ptr::null;
+
+use coreaudio::audio_unit::macos_helpers::get_device_id_from_name;
+use coreaudio::sys::{
+    kAudioHardwarePropertyTranslateUIDToDevice, kAudioObjectPropertyElementMaster,
+    kAudioObjectPropertyScopeGlobal, AudioDeviceID, AudioObjectGetPropertyData,
+    AudioObjectPropertyAddress,
+};
+use coreaudio_sys::{
+    kAudioDevicePropertyDeviceUID, kAudioHardwareNoError, kAudioHardwarePropertyTranslateUIDToBox,
+    kAudioObjectPropertyIdentify, kAudioObjectPropertyName, kCFAllocatorDefault,
+    kCFStringEncodingUTF8, AudioObjectPropertySelector, AudioObjectSetPropertyData, CFRelease,
+    CFStringCreateWithCString, CFStringRef, CFTypeRef,
+};
+use log::info;
+
+/// Points the proxy audio driver's box at the output device called `device`.
+pub fn set_proxy_output_device(device: &str) -> Result<(), String> {
+    let proxy_audio_box = audio_device_id_for_box_id("ProxyAudioBox_UID");
+    let device_id = get_device_id_from_name(device, false).ok_or("Failed to get device id")?;
+    let device_uid = audio_device_uid_for_device_id(device_id).map_err(|e| e.to_string())?;
+    set_object_name(proxy_audio_box, "outputDevice=", &device_uid).map_err(|err| err.to_string())
+}
+
+fn set_object_name(
+    box_device_id: AudioDeviceID,
+    action: &str,
+    new_name: &str,
+) -> Result<(), std::io::Error> {
+    let command = format!("{}{}", action, new_name);
+    let set_name_address = AudioObjectPropertyAddress {
+        mSelector: kAudioObjectPropertyName,
+        mScope: kAudioObjectPropertyScopeGlobal,
+        mElement: kAudioObjectPropertyIdentify,
+    };
+    unsafe {
+        let c_string_new_name = CString::new(command).unwrap();
+        let cf_new_name = CFStringCreateWithCString(
+            kCFAllocatorDefault,
+            c_string_new_name.as_ptr(),
+            kCFStringEncodingUTF8,
+        );
+        let status = AudioObjectSetPropertyData(
+            box_device_id,
+            &set_name_address,
+            0,
+            null(),
+            std::mem::size_of::<CFStringRef>() as u32,
+            &cf_new_name as *const _ as *const c_void,
+        );
+
+        if coreaudio::Error::from_os_status(status).is_err() {
+            info!(
+                "Error setting object name to device ID: {}",
+                coreaudio::Error::from_os_status(status).unwrap_err()
+            );
+        } else {
+            info!(
+                "Successfully set object name to device ID: {}",
+                box_device_id
+            );
+        }
+        CFRelease(cf_new_name as CFTypeRef);
+    }
+    Ok(())
+}
+
+pub fn audio_device_id_for_uid(
+    device_uid: &str,
+    selector: AudioObjectPropertySelector,
+) -> AudioDeviceID {
+    let mut device_id: coreaudio_sys::AudioDeviceID = coreaudio_sys::kAudioObjectUnknown;
+    let mut size = std::mem::size_of::<coreaudio_sys::AudioDeviceID>() as u32;
+    let property_address = coreaudio_sys::AudioObjectPropertyAddress {
+        mSelector: selector,
+        mScope: coreaudio_sys::kAudioObjectPropertyScopeGlobal,
+        mElement: coreaudio_sys::kAudioObjectPropertyElementMaster,
+    };
+    info!("Property address: {:?}", property_address);
+    let uid = CString::new(device_uid).unwrap();
+    let cf_uid = unsafe {
+        CFStringCreateWithCString(kCFAllocatorDefault, uid.as_ptr(), kCFStringEncodingUTF8)
+    };
+    info!("UID: {:?}", uid);
+    info!("CFString UID: {:?}", cf_uid);
+    unsafe {
+        let status = coreaudio_sys::AudioObjectGetPropertyData(
+            coreaudio_sys::kAudioObjectSystemObject,
+            &property_address,
+            std::mem::size_of::<CFStringRef>() as u32,
+            &cf_uid as *const _ as *const c_void,
+            &mut size,
+            &mut device_id as *mut _ as *mut c_void,
+        );
+        if coreaudio::Error::from_os_status(status).is_err() {
+            info!(
+                "Error translating UID to device ID: {}",
+                coreaudio::Error::from_os_status(status).unwrap_err()
+            );
+        } else {
+            info!("Successfully translated UID to device ID: {}", device_id);
+        }
+        CFRelease(cf_uid as CFTypeRef);
+    }
+    device_id
+}
+
+fn audio_device_id_for_box_id(uid: &str) -> AudioDeviceID {
+    audio_device_id_for_uid(&uid, kAudioHardwarePropertyTranslateUIDToBox)
+}
+
+fn audio_device_id_for_device_uid(uid: &str) -> AudioDeviceID {
+    audio_device_id_for_uid(&uid, kAudioHardwarePropertyTranslateUIDToDevice)
+}
+
+fn audio_device_uid_for_device_id(device_id: AudioDeviceID) -> Result<String, coreaudio::Error> {
+    let property_address = AudioObjectPropertyAddress {
+        mSelector: kAudioDevicePropertyDeviceUID,
+        mScope: kAudioObjectPropertyScopeGlobal,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+
+    macro_rules! try_status_or_return {
+        ($status:expr) => {
+            if $status != kAudioHardwareNoError as i32 {
+                return Err(coreaudio::Error::Unknown($status));
+            }
+        };
+    }
+
+    let device_name: core_foundation_sys::string::CFStringRef = null();
+    let data_size = mem::size_of::<core_foundation_sys::string::CFStringRef>();
+    let c_str = unsafe {
+        let status = AudioObjectGetPropertyData(
+            device_id,
+            &property_address as *const _,
+            0,
+            null(),
+            &data_size as *const _ as *mut _,
+            &device_name as *const _ as *mut _,
+        );
+        try_status_or_return!(status);
+
+        let c_string: *const c_char =
+            core_foundation_sys::string::CFStringGetCStringPtr(device_name, kCFStringEncodingUTF8);
+        if c_string.is_null() {
+            let status = AudioObjectGetPropertyData(
+                device_id,
+                &property_address as *const _,
+                0,
+                null(),
+                &data_size as *const _ as *mut _,
+                &device_name as *const _ as *mut _,
+            );
+            try_status_or_return!(status);
+            let mut buf: [i8; 255] = [0; 255];
+            let result = core_foundation_sys::string::CFStringGetCString(
+                device_name,
+                buf.as_mut_ptr(),
+                buf.len() as _,
+                kCFStringEncodingUTF8,
+            );
+            if result == 0 {
+                return Err(coreaudio::Error::Unknown(result as i32));
+            }
+            let name: &CStr = CStr::from_ptr(buf.as_ptr());
+            return Ok(name.to_str().unwrap().to_owned());
+        }
+        CStr::from_ptr(c_string as *mut _)
+    };
+    Ok(c_str.to_string_lossy().into_owned())
+}
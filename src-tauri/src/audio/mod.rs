@@ -1 +1,151 @@
+pub mod cpal_stream;
+pub mod file;
+#[cfg(target_os = "linux")]
+pub mod linux;
+#[cfg(target_os = "macos")]
 pub mod macos;
+pub mod pipeline;
+
+use std::path::PathBuf;
+
+use log::info;
+use tokio::sync::{mpsc, watch};
+
+/// Set to `<mic.wav>,<system.wav>` to record those files instead of devices.
+pub const CAPTURE_FILES_VAR: &str = "PLATY_CAPTURE_FILES";
+/// How many times faster than real time the capture files are played.
+pub const CAPTURE_SPEED_VAR: &str = "PLATY_CAPTURE_SPEED";
+
+/// The raw samples a capture stream sends.
+#[derive(Debug, Clone, Copy)]
+pub struct StreamFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// The sample format as ffmpeg names it, e.g. `f32le`.
+    pub sample_format: &'static str,
+}
+
+/// A capture stream sending interleaved little endian samples to the channel
+/// it was opened with. Capturing stops when it is dropped.
+pub trait CaptureStream {
+    fn play(&mut self) -> Result<(), String>;
+    fn pause(&mut self) -> Result<(), String>;
+    fn format(&self) -> StreamFormat;
+    fn device_name(&self) -> &str;
+}
+
+/// Captures the microphone and the system audio on one platform.
+pub trait AudioCaptureBackend: Send {
+    /// Microphones that can be recorded, the default one first.
+    fn input_devices(&self) -> Result<Vec<String>, String>;
+    /// Output devices whose audio can be recorded as system audio.
+    fn output_devices(&self) -> Result<Vec<String>, String>;
+    fn select_input_device(&mut self, name: &str) -> Result<(), String>;
+    fn select_output_device(&mut self, name: &str) -> Result<(), String>;
+    fn open_mic_stream(
+        &mut self,
+        sender: mpsc::Sender<Vec<u8>>,
+    ) -> Result<Box<dyn CaptureStream>, String>;
+    /// Opens a stream of what the selected output device plays.
+    fn open_loopback_stream(
+        &mut self,
+        sender: mpsc::Sender<Vec<u8>>,
+    ) -> Result<Box<dyn CaptureStream>, String>;
+}
+
+/// The backend of the platform the app runs on, or the files named by
+/// `PLATY_CAPTURE_FILES`. `activity` is told whenever the microphone starts or
+/// stops being used, where the platform reports it.
+pub fn default_backend(
+    activity: watch::Sender<bool>,
+) -> Result<Box<dyn AudioCaptureBackend>, String> {
+    let Ok(files) = std::env::var(CAPTURE_FILES_VAR) else {
+        return platform_backend(activity);
+    };
+    let (mic_path, system_path) = files
+        .split_once(',')
+        .ok_or_else(|| format!("{} must be <mic.wav>,<system.wav>", CAPTURE_FILES_VAR))?;
+    let speed = match std::env::var(CAPTURE_SPEED_VAR) {
+        Ok(speed) => speed
+            .parse::<f32>()
+            .ok()
+            .filter(|speed| *speed > 0.0)
+            .ok_or_else(|| format!("{} must be a positive number", CAPTURE_SPEED_VAR))?,
+        Err(_) => 1.0,
+    };
+    info!("Capturing audio from {} and {}", mic_path, system_path);
+
+    Ok(Box::new(
+        file::FileCaptureBackend::new(PathBuf::from(mic_path), PathBuf::from(system_path))
+            .with_speed(speed),
+    ))
+}
+
+#[cfg(target_os = "macos")]
+fn platform_backend(activity: watch::Sender<bool>) -> Result<Box<dyn AudioCaptureBackend>, String> {
+    Ok(Box::new(macos::backend::MacosCaptureBackend::new(
+        activity,
+    )?))
+}
+
+#[cfg(target_os = "linux")]
+fn platform_backend(
+    _activity: watch::Sender<bool>,
+) -> Result<Box<dyn AudioCaptureBackend>, String> {
+    Ok(Box::new(linux::LinuxCaptureBackend::new()))
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+fn platform_backend(
+    _activity: watch::Sender<bool>,
+) -> Result<Box<dyn AudioCaptureBackend>, String> {
+    Err("Audio capture is not supported on this platform".to_string())
+}
+
+/// Stands in when no capture backend could be set up: it has no devices and
+/// refuses to record, telling why.
+pub struct UnavailableBackend {
+    reason: String,
+}
+
+impl UnavailableBackend {
+    pub fn new(reason: String) -> Self {
+        UnavailableBackend { reason }
+    }
+
+    fn unavailable<T>(&self) -> Result<T, String> {
+        Err(format!("Audio capture is unavailable: {}", self.reason))
+    }
+}
+
+impl AudioCaptureBackend for UnavailableBackend {
+    fn input_devices(&self) -> Result<Vec<String>, String> {
+        Ok(Vec::new())
+    }
+
+    fn output_devices(&self) -> Result<Vec<String>, String> {
+        Ok(Vec::new())
+    }
+
+    fn select_input_device(&mut self, _name: &str) -> Result<(), String> {
+        self.unavailable()
+    }
+
+    fn select_output_device(&mut self, _name: &str) -> Result<(), String> {
+        self.unavailable()
+    }
+
+    fn open_mic_stream(
+        &mut self,
+        _sender: mpsc::Sender<Vec<u8>>,
+    ) -> Result<Box<dyn CaptureStream>, String> {
+        self.unavailable()
+    }
+
+    fn open_loopback_stream(
+        &mut self,
+        _sender: mpsc::Sender<Vec<u8>>,
+    ) -> Result<Box<dyn CaptureStream>, String> {
+        self.unavailable()
+    }
+}
@@ -0,0 +1,738 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use byteorder::{ByteOrder, LittleEndian};
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+use log::info;
+use rubato::{FftFixedIn, Resampler};
+
+use super::StreamFormat;
+
+/// Recordings are stored at the sample rate whisper expects.
+pub const SAMPLE_RATE: u32 = 16_000;
+/// Lists the complete segments of a track, in order.
+pub const SEGMENT_LIST: &str = "segment_list.txt";
+const SEGMENT_FRAMES: u32 = 3 * SAMPLE_RATE;
+/// How long every segment but the last of a track is.
+pub const SEGMENT_MS: i64 = SEGMENT_FRAMES as i64 * 1000 / SAMPLE_RATE as i64;
+const RESAMPLER_CHUNK: usize = 1024;
+
+// Loudness normalization works on blocks of 100 ms
+const LOUDNESS_BLOCK: usize = SAMPLE_RATE as usize / 10;
+// Around -24 dBFS, what ffmpeg's loudnorm aims for
+const TARGET_RMS: f32 = 0.063;
+// Blocks below -50 dBFS are silence and leave the gain alone
+const SILENCE_RMS: f32 = 0.003;
+const MAX_GAIN: f32 = 10.0;
+const MIN_GAIN: f32 = 0.1;
+// How quickly the measured loudness follows a new block
+const LOUDNESS_SMOOTHING: f32 = 0.1;
+
+fn wav_spec(channels: u16) -> WavSpec {
+    WavSpec {
+        channels,
+        sample_rate: SAMPLE_RATE,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    }
+}
+
+fn to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16
+}
+
+fn bytes_per_sample(sample_format: &str) -> Result<usize, String> {
+    match sample_format {
+        "s8" => Ok(1),
+        "s16le" => Ok(2),
+        "s32le" | "f32le" => Ok(4),
+        sample_format => Err(format!("Unsupported sample format {}", sample_format)),
+    }
+}
+
+fn decode_sample(sample_format: &str, bytes: &[u8]) -> f32 {
+    match sample_format {
+        "s8" => bytes[0] as i8 as f32 / 128.0,
+        "s16le" => LittleEndian::read_i16(bytes) as f32 / 32_768.0,
+        "s32le" => LittleEndian::read_i32(bytes) as f32 / 2_147_483_648.0,
+        _ => LittleEndian::read_f32(bytes),
+    }
+}
+
+/// Turns the bytes a capture stream sends into mono samples. Bytes of an
+/// incomplete frame are kept until the rest arrives.
+struct Decoder {
+    format: StreamFormat,
+    frame_len: usize,
+    pending: Vec<u8>,
+}
+
+impl Decoder {
+    fn new(format: StreamFormat) -> Result<Self, String> {
+        let frame_len = bytes_per_sample(format.sample_format)? * format.channels.max(1) as usize;
+        Ok(Decoder {
+            format,
+            frame_len,
+            pending: Vec::new(),
+        })
+    }
+
+    fn decode(&mut self, bytes: &[u8], samples: &mut Vec<f32>) {
+        self.pending.extend_from_slice(bytes);
+        let channels = self.format.channels.max(1) as usize;
+        let sample_len = self.frame_len / channels;
+        let complete = self.pending.len() / self.frame_len * self.frame_len;
+
+        for frame in self.pending[..complete].chunks_exact(self.frame_len) {
+            let sum: f32 = frame
+                .chunks_exact(sample_len)
+                .map(|sample| decode_sample(self.format.sample_format, sample))
+                .sum();
+            samples.push(sum / channels as f32);
+        }
+        self.pending.drain(..complete);
+    }
+}
+
+/// Resamples mono audio to 16 kHz, leaving it alone when it already is.
+struct Resample {
+    resampler: Option<FftFixedIn<f32>>,
+    sample_rate: u32,
+    input: Vec<f32>,
+    /// Output frames still to drop, the resampler starts with a delay.
+    delay: usize,
+    frames_in: usize,
+    frames_out: usize,
+}
+
+impl Resample {
+    fn new(sample_rate: u32) -> Result<Self, String> {
+        let resampler = if sample_rate == SAMPLE_RATE {
+            None
+        } else {
+            Some(
+                FftFixedIn::new(
+                    sample_rate as usize,
+                    SAMPLE_RATE as usize,
+                    RESAMPLER_CHUNK,
+                    2,
+                    1,
+                )
+                .map_err(|err| format!("Failed to create resampler: {}", err))?,
+            )
+        };
+        let delay = resampler
+            .as_ref()
+            .map_or(0, |resampler| resampler.output_delay());
+
+        Ok(Resample {
+            resampler,
+            sample_rate,
+            input: Vec::new(),
+            delay,
+            frames_in: 0,
+            frames_out: 0,
+        })
+    }
+
+    fn process(&mut self, samples: &[f32], output: &mut Vec<f32>) -> Result<(), String> {
+        let Some(resampler) = self.resampler.as_mut() else {
+            output.extend_from_slice(samples);
+            return Ok(());
+        };
+        self.input.extend_from_slice(samples);
+        self.frames_in += samples.len();
+
+        let mut start = 0;
+        while self.input.len() - start >= resampler.input_frames_next() {
+            let end = start + resampler.input_frames_next();
+            let resampled = resampler
+                .process(&[&self.input[start..end]], None)
+                .map_err(|err| format!("Failed to resample: {}", err))?;
+            start = end;
+            Self::emit(
+                &mut self.delay,
+                &mut self.frames_out,
+                usize::MAX,
+                &resampled[0],
+                output,
+            );
+        }
+        self.input.drain(..start);
+        Ok(())
+    }
+
+    /// Resamples what is left and flushes the resampler, so that the output
+    /// is exactly as long as the input.
+    fn finish(&mut self, output: &mut Vec<f32>) -> Result<(), String> {
+        let Some(resampler) = self.resampler.as_mut() else {
+            return Ok(());
+        };
+        let expected =
+            (self.frames_in as u64 * SAMPLE_RATE as u64 / self.sample_rate as u64) as usize;
+
+        let rest = std::mem::take(&mut self.input);
+        let mut resampled = resampler
+            .process_partial(Some(&[rest.as_slice()][..]), None)
+            .map_err(|err| format!("Failed to resample: {}", err))?;
+        loop {
+            Self::emit(
+                &mut self.delay,
+                &mut self.frames_out,
+                expected,
+                &resampled[0],
+                output,
+            );
+            if self.frames_out >= expected || resampled[0].is_empty() {
+                return Ok(());
+            }
+            resampled = resampler
+                .process_partial::<Vec<f32>>(None, None)
+                .map_err(|err| format!("Failed to resample: {}", err))?;
+        }
+    }
+
+    fn emit(
+        delay: &mut usize,
+        frames_out: &mut usize,
+        limit: usize,
+        resampled: &[f32],
+        output: &mut Vec<f32>,
+    ) {
+        let skip = (*delay).min(resampled.len());
+        *delay -= skip;
+        let take = (resampled.len() - skip).min(limit.saturating_sub(*frames_out));
+        output.extend_from_slice(&resampled[skip..skip + take]);
+        *frames_out += take;
+    }
+}
+
+/// Slowly steers the level towards a target loudness, the way ffmpeg's
+/// `loudnorm` does for a live stream, without boosting silence. Only the mix
+/// is normalized, attributing speech to a track compares their raw levels.
+struct Loudness {
+    gain: f32,
+    mean_square: f32,
+    block: Vec<f32>,
+}
+
+impl Loudness {
+    fn new() -> Self {
+        Loudness {
+            gain: 1.0,
+            mean_square: TARGET_RMS * TARGET_RMS,
+            block: Vec::with_capacity(LOUDNESS_BLOCK),
+        }
+    }
+
+    fn process(&mut self, samples: &[f32], output: &mut Vec<f32>) {
+        for &sample in samples {
+            self.block.push(sample);
+            if self.block.len() == LOUDNESS_BLOCK {
+                self.flush(output);
+            }
+        }
+    }
+
+    /// Applies the gain to the buffered block, ramping to the new gain over
+    /// the block so that changes don't click.
+    fn flush(&mut self, output: &mut Vec<f32>) {
+        if self.block.is_empty() {
+            return;
+        }
+        let mean_square =
+            self.block.iter().map(|sample| sample * sample).sum::<f32>() / self.block.len() as f32;
+        let target_gain = if mean_square.sqrt() > SILENCE_RMS {
+            self.mean_square += (mean_square - self.mean_square) * LOUDNESS_SMOOTHING;
+            (TARGET_RMS / self.mean_square.sqrt()).clamp(MIN_GAIN, MAX_GAIN)
+        } else {
+            self.gain
+        };
+
+        let step = (target_gain - self.gain) / self.block.len() as f32;
+        for sample in self.block.drain(..) {
+            self.gain += step;
+            output.push((sample * self.gain).clamp(-1.0, 1.0));
+        }
+        self.gain = target_gain;
+    }
+}
+
+type SegmentListener = Box<dyn FnMut(&str) -> Result<(), String> + Send>;
+
+/// The file name of the segment at `index`.
+pub fn segment_name(index: usize) -> String {
+    format!("audio_recording_{:03}.wav", index)
+}
+
+/// Turns what a capture stream sends into the 3 second, 16 kHz mono WAV
+/// segments that live transcription and the post-processing jobs read. A
+/// segment is only listed in `segment_list.txt` once it is complete.
+pub struct SegmentWriter {
+    dir: PathBuf,
+    decoder: Decoder,
+    resample: Resample,
+    sample_rate: u32,
+    /// Frames received so far, at the stream's rate.
+    received_frames: u64,
+    segment: Option<WavWriter<BufWriter<File>>>,
+    segment_index: usize,
+    segment_frames: u32,
+    on_segment: Option<SegmentListener>,
+    decoded: Vec<f32>,
+    resampled: Vec<f32>,
+}
+
+impl SegmentWriter {
+    pub fn new(dir: &Path, format: StreamFormat) -> Result<Self, String> {
+        info!(
+            "Writing segments of {} Hz, {} channel {} audio to {}",
+            format.sample_rate,
+            format.channels,
+            format.sample_format,
+            dir.display()
+        );
+        Ok(SegmentWriter {
+            dir: dir.to_path_buf(),
+            decoder: Decoder::new(format)?,
+            resample: Resample::new(format.sample_rate)?,
+            sample_rate: format.sample_rate,
+            received_frames: 0,
+            segment: None,
+            segment_index: 0,
+            segment_frames: 0,
+            on_segment: None,
+            decoded: Vec::new(),
+            resampled: Vec::new(),
+        })
+    }
+
+    /// Calls `listener` with the name of every segment once it is complete,
+    /// before it is listed.
+    pub fn on_segment(
+        mut self,
+        listener: impl FnMut(&str) -> Result<(), String> + Send + 'static,
+    ) -> Self {
+        self.on_segment = Some(Box::new(listener));
+        self
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.decoder.decode(bytes, &mut self.decoded);
+        self.received_frames += self.decoded.len() as u64;
+        self.resample.process(&self.decoded, &mut self.resampled)?;
+        self.decoded.clear();
+        self.write_resampled()
+    }
+
+    /// How much audio was written so far, counting what the resampler still
+    /// holds on to.
+    pub fn recorded_ms(&self) -> i64 {
+        (self.received_frames * 1000 / self.sample_rate as u64) as i64
+    }
+
+    /// Writes out everything still buffered and closes the last segment.
+    pub fn finish(mut self) -> Result<(), String> {
+        self.resample.finish(&mut self.resampled)?;
+        self.write_resampled()?;
+        self.close_segment()
+    }
+
+    fn write_resampled(&mut self) -> Result<(), String> {
+        let resampled = std::mem::take(&mut self.resampled);
+        for &sample in &resampled {
+            if self.segment.is_none() {
+                let segment = WavWriter::create(self.segment_path(), wav_spec(1))
+                    .map_err(|err| format!("Failed to create segment: {}", err))?;
+                self.segment = Some(segment);
+            }
+            if let Some(segment) = self.segment.as_mut() {
+                segment
+                    .write_sample(to_i16(sample))
+                    .map_err(|err| format!("Failed to write segment: {}", err))?;
+            }
+            self.segment_frames += 1;
+            if self.segment_frames == SEGMENT_FRAMES {
+                self.close_segment()?;
+            }
+        }
+        // Keep the allocation for the next write
+        self.resampled = resampled;
+        self.resampled.clear();
+        Ok(())
+    }
+
+    fn segment_name(&self) -> String {
+        segment_name(self.segment_index)
+    }
+
+    fn segment_path(&self) -> PathBuf {
+        self.dir.join(self.segment_name())
+    }
+
+    fn close_segment(&mut self) -> Result<(), String> {
+        let Some(segment) = self.segment.take() else {
+            return Ok(());
+        };
+        segment
+            .finalize()
+            .map_err(|err| format!("Failed to finish segment: {}", err))?;
+        if let Some(listener) = self.on_segment.as_mut() {
+            listener(&segment_name(self.segment_index))?;
+        }
+
+        let mut segment_list = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(SEGMENT_LIST))
+            .map_err(|err| format!("Failed to open segment list: {}", err))?;
+        writeln!(segment_list, "{}", self.segment_name())
+            .map_err(|err| format!("Failed to update segment list: {}", err))?;
+
+        self.segment_index += 1;
+        self.segment_frames = 0;
+        Ok(())
+    }
+}
+
+type TrackReader = WavReader<BufReader<File>>;
+type TrackWriter = WavWriter<BufWriter<File>>;
+
+fn open_track(path: &Path) -> Result<TrackReader, String> {
+    let reader = WavReader::open(path)
+        .map_err(|err| format!("Failed to open file {}: {}", path.display(), err))?;
+    if reader.spec() != wav_spec(1) {
+        return Err(format!("{} is not 16 kHz mono audio", path.display()));
+    }
+    Ok(reader)
+}
+
+/// Reads a track one sample at a time after `delay_ms` of silence, then keeps
+/// returning silence, so that two tracks line up and the shorter one is
+/// padded.
+struct PaddedSamples<'a> {
+    path: &'a Path,
+    samples: hound::WavIntoSamples<BufReader<File>, i16>,
+    delay: u64,
+    /// The delay and the track together.
+    frames: u64,
+    ended: bool,
+}
+
+impl<'a> PaddedSamples<'a> {
+    fn open(path: &'a Path, delay_ms: i64) -> Result<Self, String> {
+        let reader = open_track(path)?;
+        let delay = delay_ms.max(0) as u64 * SAMPLE_RATE as u64 / 1000;
+        Ok(PaddedSamples {
+            path,
+            frames: delay + reader.duration() as u64,
+            samples: reader.into_samples(),
+            delay,
+            ended: false,
+        })
+    }
+
+    fn next_sample(&mut self) -> Result<i16, String> {
+        if self.delay > 0 {
+            self.delay -= 1;
+            return Ok(0);
+        }
+        if self.ended {
+            return Ok(0);
+        }
+        match self.samples.next() {
+            Some(sample) => sample
+                .map_err(|err| format!("Failed to read file {}: {}", self.path.display(), err)),
+            None => {
+                self.ended = true;
+                Ok(0)
+            }
+        }
+    }
+}
+
+fn create_track(path: &Path, channels: u16) -> Result<TrackWriter, String> {
+    WavWriter::create(path, wav_spec(channels))
+        .map_err(|err| format!("Failed to create file {}: {}", path.display(), err))
+}
+
+fn write_sample(writer: &mut TrackWriter, path: &Path, sample: i16) -> Result<(), String> {
+    writer
+        .write_sample(sample)
+        .map_err(|err| format!("Failed to write file {}: {}", path.display(), err))
+}
+
+fn finalize_track(writer: TrackWriter, path: &Path) -> Result<(), String> {
+    writer
+        .finalize()
+        .map_err(|err| format!("Failed to write file {}: {}", path.display(), err))
+}
+
+/// Rewrites a segment that was still being written when the app quit, whose
+/// header may not match its data. Returns `false` when it holds no samples.
+pub fn repair_segment(path: &Path) -> Result<bool, String> {
+    let bytes = std::fs::read(path)
+        .map_err(|err| format!("Failed to read file {}: {}", path.display(), err))?;
+    let Some(data_start) = bytes
+        .windows(4)
+        .position(|id| id == b"data")
+        .map(|position| position + 8)
+    else {
+        return Ok(false);
+    };
+
+    let data = bytes.get(data_start..).unwrap_or_default();
+    let mut samples = vec![0; data.len() / 2];
+    LittleEndian::read_i16_into(&data[..samples.len() * 2], &mut samples);
+    if samples.is_empty() {
+        return Ok(false);
+    }
+    write_samples(path, 1, &samples)?;
+    Ok(true)
+}
+
+fn write_samples(path: &Path, channels: u16, samples: &[i16]) -> Result<(), String> {
+    let mut writer = create_track(path, channels)?;
+    for &sample in samples {
+        write_sample(&mut writer, path, sample)?;
+    }
+    finalize_track(writer, path)
+}
+
+/// Joins the listed segments in `dir` into `combined.wav`, one segment at a
+/// time.
+pub fn concat_segments(dir: &Path) -> Result<(), String> {
+    let segment_list = std::fs::read_to_string(dir.join(SEGMENT_LIST))
+        .map_err(|err| format!("Failed to read segment list: {}", err))?;
+
+    let destination = dir.join("combined.wav");
+    let mut writer = create_track(&destination, 1)?;
+    let mut joined = 0usize;
+    for segment in segment_list.lines().map(str::trim) {
+        if segment.is_empty() {
+            continue;
+        }
+        let path = dir.join(segment);
+        for sample in open_track(&path)?.into_samples::<i16>() {
+            let sample =
+                sample.map_err(|err| format!("Failed to read file {}: {}", path.display(), err))?;
+            write_sample(&mut writer, &destination, sample)?;
+            joined += 1;
+        }
+    }
+    info!("Joined {} samples in {}", joined, dir.display());
+
+    finalize_track(writer, &destination)
+}
+
+/// Mixes two mono tracks into one with normalized loudness. Each track
+/// starts after its delay in `delays_ms`, the shorter one is padded with
+/// silence.
+pub fn mix_tracks(
+    first: &Path,
+    second: &Path,
+    delays_ms: [i64; 2],
+    destination: &Path,
+) -> Result<(), String> {
+    let mut first = PaddedSamples::open(first, delays_ms[0])?;
+    let mut second = PaddedSamples::open(second, delays_ms[1])?;
+    let frames = first.frames.max(second.frames);
+    let mut writer = create_track(destination, 1)?;
+
+    let mut loudness = Loudness::new();
+    let mut mixed = Vec::with_capacity(LOUDNESS_BLOCK);
+    let mut normalized = Vec::with_capacity(LOUDNESS_BLOCK);
+    for frame in 0..frames {
+        let a = first.next_sample()? as f32;
+        let b = second.next_sample()? as f32;
+        mixed.push((a + b) / 2.0 / 32_768.0);
+        if mixed.len() == LOUDNESS_BLOCK || frame + 1 == frames {
+            loudness.process(&mixed, &mut normalized);
+            mixed.clear();
+            if frame + 1 == frames {
+                loudness.flush(&mut normalized);
+            }
+            for sample in normalized.drain(..) {
+                write_sample(&mut writer, destination, to_i16(sample))?;
+            }
+        }
+    }
+
+    finalize_track(writer, destination)
+}
+
+/// Puts two mono tracks on the left and right channel of one file, lined up
+/// like [`mix_tracks`] does.
+pub fn stereo_tracks(
+    left: &Path,
+    right: &Path,
+    delays_ms: [i64; 2],
+    destination: &Path,
+) -> Result<(), String> {
+    let mut left = PaddedSamples::open(left, delays_ms[0])?;
+    let mut right = PaddedSamples::open(right, delays_ms[1])?;
+    let frames = left.frames.max(right.frames);
+    let mut writer = create_track(destination, 2)?;
+
+    for _ in 0..frames {
+        write_sample(&mut writer, destination, left.next_sample()?)?;
+        write_sample(&mut writer, destination, right.next_sample()?)?;
+    }
+
+    finalize_track(writer, destination)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(sample_rate: u32, seconds: f32) -> Vec<f32> {
+        (0..(sample_rate as f32 * seconds) as usize)
+            .map(|i| (i as f32 * 440.0 * std::f32::consts::TAU / sample_rate as f32).sin() * 0.1)
+            .collect()
+    }
+
+    fn read_samples(path: &Path) -> Result<Vec<i16>, String> {
+        open_track(path)?
+            .into_samples()
+            .collect::<Result<_, _>>()
+            .map_err(|err| format!("Failed to read file {}: {}", path.display(), err))
+    }
+
+    #[test]
+    fn resampling_keeps_the_duration() {
+        let input = sine(48_000, 1.5);
+        let mut resample = Resample::new(48_000).unwrap();
+        let mut output = Vec::new();
+        for chunk in input.chunks(480) {
+            resample.process(chunk, &mut output).unwrap();
+        }
+        resample.finish(&mut output).unwrap();
+
+        assert_eq!(output.len(), 24_000);
+    }
+
+    #[test]
+    fn silence_is_not_boosted() {
+        let mut loudness = Loudness::new();
+        let mut output = Vec::new();
+        loudness.process(&[0.001; 16_000], &mut output);
+        loudness.flush(&mut output);
+
+        assert_eq!(output.len(), 16_000);
+        assert!(output.iter().all(|sample| (sample - 0.001).abs() < 1e-6));
+    }
+
+    #[test]
+    fn writes_complete_segments_and_joins_them() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path();
+        let format = StreamFormat {
+            sample_rate: 44_100,
+            channels: 2,
+            sample_format: "s16le",
+        };
+        let mut writer = SegmentWriter::new(dir, format).unwrap();
+        let samples: Vec<i16> = sine(44_100, 7.0)
+            .into_iter()
+            .flat_map(|sample| [to_i16(sample); 2])
+            .collect();
+        let mut bytes = vec![0; samples.len() * 2];
+        LittleEndian::write_i16_into(&samples, &mut bytes);
+        // Chunks that split frames in half
+        for chunk in bytes.chunks(1001) {
+            writer.write(chunk).unwrap();
+        }
+        assert_eq!(writer.recorded_ms(), 7_000);
+        writer.finish().unwrap();
+
+        let segment_list = std::fs::read_to_string(dir.join(SEGMENT_LIST)).unwrap();
+        assert_eq!(
+            segment_list.lines().collect::<Vec<_>>(),
+            [
+                "audio_recording_000.wav",
+                "audio_recording_001.wav",
+                "audio_recording_002.wav"
+            ]
+        );
+        concat_segments(dir).unwrap();
+        assert_eq!(
+            read_samples(&dir.join("combined.wav")).unwrap().len(),
+            112_000
+        );
+    }
+
+    #[test]
+    fn repairs_a_segment_cut_off_mid_write() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path();
+        let path = dir.join(segment_name(0));
+        write_samples(&path, 1, &[1, 2, 3, 4]).unwrap();
+        // Drop the last sample and a half, and stale the header
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes.truncate(bytes.len() - 3);
+        bytes[4..8].copy_from_slice(&[0; 4]);
+        std::fs::write(&path, bytes).unwrap();
+
+        assert!(repair_segment(&path).unwrap());
+        assert_eq!(read_samples(&path).unwrap(), [1, 2]);
+    }
+
+    #[test]
+    fn mixing_pads_the_shorter_track() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path();
+        // Quiet enough that normalizing leaves the mix alone
+        write_samples(&dir.join("a.wav"), 1, &[10, 10, 10]).unwrap();
+        write_samples(&dir.join("b.wav"), 1, &[30]).unwrap();
+
+        mix_tracks(
+            &dir.join("a.wav"),
+            &dir.join("b.wav"),
+            [0, 0],
+            &dir.join("mono.wav"),
+        )
+        .unwrap();
+        assert_eq!(read_samples(&dir.join("mono.wav")).unwrap(), [20, 5, 5]);
+
+        stereo_tracks(
+            &dir.join("a.wav"),
+            &dir.join("b.wav"),
+            [0, 0],
+            &dir.join("stereo.wav"),
+        )
+        .unwrap();
+        let stereo: Vec<i16> = WavReader::open(dir.join("stereo.wav"))
+            .unwrap()
+            .samples::<i16>()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(stereo, [10, 30, 10, 0, 10, 0]);
+    }
+
+    #[test]
+    fn delayed_track_starts_later() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path();
+        write_samples(&dir.join("a.wav"), 1, &[10, 10, 10]).unwrap();
+        write_samples(&dir.join("b.wav"), 1, &[30]).unwrap();
+
+        stereo_tracks(
+            &dir.join("a.wav"),
+            &dir.join("b.wav"),
+            [0, 1],
+            &dir.join("stereo.wav"),
+        )
+        .unwrap();
+        let stereo: Vec<i16> = WavReader::open(dir.join("stereo.wav"))
+            .unwrap()
+            .samples::<i16>()
+            .map(Result::unwrap)
+            .collect();
+        // One millisecond is 16 frames
+        assert_eq!(stereo.len(), 2 * 17);
+        assert_eq!(&stereo[..6], [10, 0, 10, 0, 10, 0]);
+        assert_eq!(&stereo[32..], [0, 30]);
+    }
+}
@@ -0,0 +1,26 @@
+use entity::action_item::{self, ActionItemStatus};
+use service::{Mutation, Query};
+
+use crate::AppState;
+
+#[tauri::command]
+pub async fn get_action_items(
+    state: tauri::State<'_, AppState>,
+    conversation_id: i32,
+) -> Result<Vec<action_item::Model>, String> {
+    Query::find_action_items_by_conversation_id(&state.db, conversation_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Ticks an action item off, or reopens it.
+#[tauri::command]
+pub async fn set_action_item_status(
+    state: tauri::State<'_, AppState>,
+    action_item_id: i32,
+    status: ActionItemStatus,
+) -> Result<action_item::Model, String> {
+    Mutation::update_action_item_status(&state.db, action_item_id, status)
+        .await
+        .map_err(|e| e.to_string())
+}
@@ -1,35 +1,25 @@
-use std::fs::read_to_string;
 use std::sync::Arc;
 
-use entity::conversation::{self, Model as ConversationModel};
+use entity::conversation;
 use log::info;
-use service::{sea_orm::TryIntoModel, Mutation, Query};
+use service::{sea_orm::TryIntoModel, Mutation, Query, SearchHit};
 
-use crate::{recorder::RecordingState, summarize::SummaryJSON, AppState};
+use crate::{
+    model_registry::ModelRegistry,
+    summarize::{ActionItem, SummaryJSON},
+    transcribe::TranscriptionOptions,
+    AppState,
+};
 
 #[tauri::command]
 pub async fn get_conversation(
     state: tauri::State<'_, AppState>,
     conversation_id: i32,
-) -> Result<conversation::Model, ()> {
-    let result: Option<ConversationModel> =
-        Query::find_conversation_by_id(&state.db, conversation_id)
-            .await
-            .expect("Cannot find posts in page");
-
-    let conversation = match result {
-        Some(conversation) => {
-            // Use your conversation model here
-            conversation
-        }
-        None => {
-            // Handle the case where conversation was not found
-            eprintln!("Conversation not found");
-            return Err(());
-        }
-    };
-
-    Ok(conversation)
+) -> Result<conversation::Model, String> {
+    Query::find_conversation_by_id(&state.db, conversation_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("Conversation not found".to_string())
 }
 
 #[tauri::command]
@@ -37,30 +27,52 @@ pub async fn get_conversations(
     state: tauri::State<'_, AppState>,
     page: u64,
     items_per_page: u64,
-) -> Result<(Vec<conversation::Model>, u64), ()> {
+) -> Result<(Vec<conversation::Model>, u64), String> {
     info!("getting conversations...");
-    let (conversations, num_pages) =
-        Query::find_conversations_in_page(&state.db, page, items_per_page)
-            .await
-            .expect("Cannot find posts in page");
+    Query::find_conversations_in_page(&state.db, page, items_per_page)
+        .await
+        .map_err(|e| e.to_string())
+}
 
-    Ok((conversations, num_pages))
+#[tauri::command]
+pub async fn search_conversations(
+    state: tauri::State<'_, AppState>,
+    query: String,
+    page: u64,
+    items_per_page: u64,
+) -> Result<(Vec<SearchHit>, u64), String> {
+    Query::search_conversations(&state.db, &query, page, items_per_page)
+        .await
+        .map_err(|e| e.to_string())
 }
 
+/// Creates a conversation. A title the user typed is kept, an empty one gets
+/// replaced by a generated title once the conversation is transcribed.
 #[tauri::command]
 pub async fn create_conversation(
     state: tauri::State<'_, AppState>,
     form: conversation::Model,
 ) -> Result<conversation::Model, String> {
-    let _ = &state.db;
+    let title = form.title.trim().to_string();
+    let form = if title.is_empty() {
+        conversation::Model {
+            title: "New Conversation".to_string(),
+            title_edited: false,
+            ..form
+        }
+    } else {
+        conversation::Model {
+            title,
+            title_edited: true,
+            ..form
+        }
+    };
 
-    let conversation = Mutation::create_conversation(&state.db, form)
+    Mutation::create_conversation(&state.db, form)
         .await
-        .expect("could not insert post");
-
-    Ok(conversation
+        .map_err(|e| e.to_string())?
         .try_into_model()
-        .expect("could not turn result into a model"))
+        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -68,38 +80,125 @@ pub async fn delete_conversation(
     state: tauri::State<'_, AppState>,
     conversation_id: i32,
 ) -> Result<u64, String> {
-    let _ = &state.db;
-
     let result = Mutation::delete_conversation(&state.db, conversation_id)
         .await
-        .expect("could not delete conversation");
+        .map_err(|e| e.to_string())?;
 
     Ok(result.rows_affected)
 }
 
+/// Renames a conversation. Renamed conversations keep their title even when
+/// the pipeline generates one.
 #[tauri::command]
-pub async fn get_summary_for_converstation(
-    state: tauri::State<'_, Arc<tauri::async_runtime::Mutex<RecordingState>>>,
+pub async fn rename_conversation(
+    state: tauri::State<'_, AppState>,
     conversation_id: i32,
-) -> Result<SummaryJSON, String> {
-    let state_guard = state.lock().await;
-    let data_dir = match &state_guard.data_dir {
-        Some(dir) => dir,
-        None => return Err("Data directory not set".to_string()),
+    title: String,
+) -> Result<conversation::Model, String> {
+    let title = title.trim().to_string();
+    if title.is_empty() {
+        return Err("Title cannot be empty".to_string());
+    }
+
+    let conversation = Query::find_conversation_by_id(&state.db, conversation_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("Conversation not found".to_string())?;
+
+    Mutation::update_conversation_by_id(
+        &state.db,
+        conversation_id,
+        conversation::Model {
+            title,
+            title_edited: true,
+            ..conversation
+        },
+    )
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// Sets how the conversation is transcribed. A `language` of `None` or
+/// `"auto"` lets whisper detect it; a `beam_size` of `None` decodes greedily.
+/// Another language than English or translating needs a multilingual model.
+#[tauri::command]
+pub async fn update_transcription_options(
+    state: tauri::State<'_, AppState>,
+    registry: tauri::State<'_, Arc<ModelRegistry>>,
+    conversation_id: i32,
+    language: Option<String>,
+    translate: bool,
+    initial_prompt: Option<String>,
+    beam_size: Option<i32>,
+    temperature: Option<f64>,
+) -> Result<conversation::Model, String> {
+    let language = language
+        .map(|language| language.trim().to_lowercase())
+        .filter(|language| !language.is_empty() && language != "auto");
+    if let Some(language) = &language {
+        if whisper_rs::get_lang_id(language).is_none() {
+            return Err(format!("Unsupported language {}", language));
+        }
+    }
+    if let Some(temperature) = temperature {
+        if !(0.0..=1.0).contains(&temperature) {
+            return Err("Temperature must be between 0 and 1".to_string());
+        }
+    }
+
+    let conversation = Query::find_conversation_by_id(&state.db, conversation_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("Conversation not found".to_string())?;
+    let conversation = conversation::Model {
+        language,
+        translate,
+        initial_prompt,
+        beam_size: beam_size.filter(|beam_size| *beam_size > 1),
+        temperature,
+        ..conversation
     };
+    let model = registry
+        .resolve_model(&state.db, Some(conversation_id))
+        .await?;
+    TranscriptionOptions::from(&conversation).check_model(&model)?;
 
-    let path = data_dir
-        .join("chunks/audio")
-        .join(conversation_id.to_string())
-        .join("summary.json");
+    Mutation::update_conversation_transcription_options(&state.db, conversation_id, conversation)
+        .await
+        .map_err(|e| e.to_string())
+}
 
-    let content = read_to_string(&path)
-        .map_err(|err| format!("Failed to read file {}: {}", path.display(), err))?;
+#[tauri::command]
+pub async fn get_summary_for_converstation(
+    state: tauri::State<'_, AppState>,
+    conversation_id: i32,
+) -> Result<SummaryJSON, String> {
+    let summary = Query::find_current_summary_by_conversation_id(&state.db, conversation_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("Summary not found".to_string())?;
+    let action_items = Query::find_action_items_by_conversation_id(&state.db, conversation_id)
+        .await
+        .map_err(|e| e.to_string())?;
 
-    let json_content: SummaryJSON = serde_json::from_str(&content)
-        .map_err(|err| format!("Failed to parse JSON in file {}: {}", path.display(), err))?;
+    let chunks = match &summary.chunks {
+        Some(chunks) => serde_json::from_str(chunks).map_err(|e| e.to_string())?,
+        None => Vec::new(),
+    };
 
-    Ok(json_content)
+    Ok(SummaryJSON {
+        result: summary.content,
+        action_items: action_items
+            .into_iter()
+            .map(|item| ActionItem {
+                title: item.title,
+                assignee: item.assignee,
+                due_date: item.due_date,
+                source_ms: item.source_ms,
+            })
+            .collect(),
+        chunks,
+    })
 }
 
 use tauri::Manager;
@@ -0,0 +1,45 @@
+use service::Query;
+
+use crate::{
+    jobs::recording_dir,
+    manifest::RecordingManifest,
+    speakers::SpeakerNames,
+    subtitles::{cues_from_segments, render, SubtitleFormat},
+    AppState,
+};
+
+#[tauri::command]
+pub async fn export_transcript(
+    handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    conversation_id: i32,
+    format: SubtitleFormat,
+    path: Option<String>,
+) -> Result<String, String> {
+    let mut segments =
+        Query::find_transcript_segments_by_conversation_id(&state.db, conversation_id)
+            .await
+            .map_err(|e| e.to_string())?;
+
+    if segments.is_empty() {
+        return Err(format!(
+            "Conversation {} has no transcript to export",
+            conversation_id
+        ));
+    }
+
+    // Cues follow the conversation, gaps where the recording was paused included
+    RecordingManifest::load_conversation_times(
+        &recording_dir(&handle, conversation_id)?,
+        &mut segments,
+    )?;
+    let speakers = SpeakerNames::load(&state.db, conversation_id).await?;
+    let content = render(&cues_from_segments(&segments, &speakers), format);
+
+    if let Some(path) = path {
+        std::fs::write(&path, &content)
+            .map_err(|err| format!("Failed to write file {}: {}", path, err))?;
+    }
+
+    Ok(content)
+}
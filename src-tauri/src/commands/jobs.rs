@@ -0,0 +1,14 @@
+use entity::job;
+use service::Query;
+
+use crate::AppState;
+
+#[tauri::command]
+pub async fn get_conversation_jobs(
+    state: tauri::State<'_, AppState>,
+    conversation_id: i32,
+) -> Result<Vec<job::Model>, String> {
+    Query::find_jobs_by_conversation_id(&state.db, conversation_id)
+        .await
+        .map_err(|e| e.to_string())
+}
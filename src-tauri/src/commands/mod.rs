@@ -1,4 +1,9 @@
+pub mod action_items;
 pub mod conversation;
 pub mod devices;
+pub mod export;
+pub mod jobs;
 pub mod recording;
+pub mod summaries;
+pub mod summary_templates;
 pub mod window;
@@ -1,3 +1,5 @@
+use crate::jobs::recording_dir;
+use crate::manifest::RecordingManifest;
 use crate::recorder::RecordingState;
 use std::sync::Arc;
 use tauri::{async_runtime::Mutex, State};
@@ -8,3 +10,33 @@ pub async fn is_recording(state: State<'_, Arc<Mutex<RecordingState>>>) -> Resul
 
     Ok(guard.media_process.is_some())
 }
+
+#[tauri::command]
+pub async fn is_recording_paused(
+    state: State<'_, Arc<Mutex<RecordingState>>>,
+) -> Result<bool, String> {
+    let guard = state.lock().await;
+
+    Ok(guard
+        .media_process
+        .as_ref()
+        .is_some_and(|media_process| media_process.is_paused()))
+}
+
+/// Maps positions in a recording's audio, like the times of its transcript
+/// segments, to the time since the recording started, counting its pauses.
+#[tauri::command]
+pub async fn get_conversation_times(
+    handle: tauri::AppHandle,
+    conversation_id: i32,
+    audio_ms: Vec<i64>,
+) -> Result<Vec<i64>, String> {
+    let manifest = RecordingManifest::load(&recording_dir(&handle, conversation_id)?)?;
+    Ok(match manifest {
+        Some(manifest) => audio_ms
+            .into_iter()
+            .map(|audio_ms| manifest.conversation_ms(audio_ms))
+            .collect(),
+        None => audio_ms,
+    })
+}
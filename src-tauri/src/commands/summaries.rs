@@ -0,0 +1,64 @@
+use entity::job::{self, JobKind};
+use entity::summary;
+use service::{Mutation, Query};
+
+use crate::jobs::JobQueue;
+use crate::recorder::{index_current_summary, SummaryVersionOptions};
+use crate::AppState;
+
+/// Queues summarizing the stored transcript again, with a template and a
+/// model other than the configured ones if given. The result is kept as a
+/// new version once the returned job is done.
+#[tauri::command]
+pub async fn regenerate_summary(
+    state: tauri::State<'_, AppState>,
+    queue: tauri::State<'_, JobQueue>,
+    conversation_id: i32,
+    template_id: Option<i32>,
+    model: Option<String>,
+) -> Result<job::Model, String> {
+    if let Some(template_id) = template_id {
+        Query::find_summary_template_by_id(&state.db, template_id)
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or(format!("Summary template {} not found", template_id))?;
+    }
+    let model = model
+        .map(|model| model.trim().to_string())
+        .filter(|model| !model.is_empty());
+
+    let params = serde_json::to_string(&SummaryVersionOptions { template_id, model })
+        .map_err(|e| e.to_string())?;
+    queue
+        .enqueue_with_params(
+            &state.db,
+            conversation_id,
+            JobKind::Resummarize,
+            Some(params),
+        )
+        .await
+}
+
+/// Every summary version of a conversation, newest first.
+#[tauri::command]
+pub async fn list_summaries(
+    state: tauri::State<'_, AppState>,
+    conversation_id: i32,
+) -> Result<Vec<summary::Model>, String> {
+    Query::find_summaries_by_conversation_id(&state.db, conversation_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Makes a version the one shown and searched for its conversation.
+#[tauri::command]
+pub async fn pin_summary(
+    state: tauri::State<'_, AppState>,
+    summary_id: i32,
+) -> Result<summary::Model, String> {
+    let summary = Mutation::pin_summary(&state.db, summary_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    index_current_summary(&state.db, summary.conversation_id).await?;
+    Ok(summary)
+}
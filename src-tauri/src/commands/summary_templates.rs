@@ -0,0 +1,117 @@
+use entity::summary_template;
+use service::{Mutation, Query};
+
+use crate::AppState;
+
+/// Checks a template before it is stored. The prompt has to include the
+/// transcript and the schema, if any, has to be a JSON object.
+fn validate_template(form: &summary_template::Model) -> Result<(), String> {
+    if form.name.trim().is_empty() {
+        return Err("Template name cannot be empty".to_string());
+    }
+    if !form.prompt.contains("{{transcript}}") {
+        return Err("Template prompt must contain {{transcript}}".to_string());
+    }
+    if let Some(schema) = &form.output_schema {
+        let schema: serde_json::Value =
+            serde_json::from_str(schema).map_err(|e| format!("Invalid output schema: {}", e))?;
+        if !schema.is_object() {
+            return Err("Output schema must be a JSON object".to_string());
+        }
+    }
+    Ok(())
+}
+
+fn normalize_template(form: summary_template::Model) -> summary_template::Model {
+    summary_template::Model {
+        name: form.name.trim().to_string(),
+        output_schema: form
+            .output_schema
+            .filter(|schema| !schema.trim().is_empty()),
+        ..form
+    }
+}
+
+#[tauri::command]
+pub async fn get_summary_templates(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<summary_template::Model>, String> {
+    Query::find_summary_templates(&state.db)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn create_summary_template(
+    state: tauri::State<'_, AppState>,
+    form: summary_template::Model,
+) -> Result<summary_template::Model, String> {
+    let form = normalize_template(form);
+    validate_template(&form)?;
+    let is_default = form.is_default;
+
+    let template = Mutation::create_summary_template(&state.db, form)
+        .await
+        .map_err(|e| e.to_string())?;
+    if !is_default {
+        return Ok(template);
+    }
+    Mutation::set_default_summary_template(&state.db, template.id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn update_summary_template(
+    state: tauri::State<'_, AppState>,
+    template_id: i32,
+    form: summary_template::Model,
+) -> Result<summary_template::Model, String> {
+    let form = normalize_template(form);
+    validate_template(&form)?;
+    let is_default = form.is_default;
+
+    let template = Mutation::update_summary_template(&state.db, template_id, form)
+        .await
+        .map_err(|e| e.to_string())?;
+    if !is_default || template.is_default {
+        return Ok(template);
+    }
+    Mutation::set_default_summary_template(&state.db, template.id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Makes a template the one used when no template is chosen.
+#[tauri::command]
+pub async fn set_default_summary_template(
+    state: tauri::State<'_, AppState>,
+    template_id: i32,
+) -> Result<summary_template::Model, String> {
+    Mutation::set_default_summary_template(&state.db, template_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Deletes a user template. Built-in templates and the default one are kept.
+#[tauri::command]
+pub async fn delete_summary_template(
+    state: tauri::State<'_, AppState>,
+    template_id: i32,
+) -> Result<u64, String> {
+    let template = Query::find_summary_template_by_id(&state.db, template_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("Summary template not found".to_string())?;
+    if template.builtin {
+        return Err("Built-in templates cannot be deleted".to_string());
+    }
+    if template.is_default {
+        return Err("Choose another default template first".to_string());
+    }
+
+    let result = Mutation::delete_summary_template(&state.db, template_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(result.rows_affected)
+}
@@ -0,0 +1,573 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::f32::consts::PI;
+use std::path::Path;
+
+use entity::speaker::{self, SpeakerSource};
+use entity::transcript_segment;
+use hound::{SampleFormat, WavReader};
+use log::info;
+use service::{Mutation, Query};
+use tauri::{AppHandle, Manager, State};
+
+use crate::manifest::RecordingManifest;
+use crate::subtitles::speaker_label;
+use crate::transcribe::{separate_tracks, MIC_SPEAKER, SYSTEM_SPEAKER};
+use crate::AppState;
+
+const FRAME_MS: i64 = 25;
+// Frames quieter than this are pauses and say nothing about the voice
+const SILENCE_RMS: f32 = 0.01;
+// How much louder one track has to be for a segment to be attributed to it
+const TRACK_DOMINANCE: f32 = 2.0;
+// Largest spectral distance at which two clusters are taken to be one voice.
+// On 3 s stretches of the speech in src/samples/a13.wav, one voice merges
+// from about 2.0, and the same voice sped up by a quarter stays apart up to
+// 3.0.
+const MERGE_THRESHOLD: f32 = 2.5;
+// Centre frequencies of the filter bank, covering the range of the voice
+const BANDS_HZ: [f32; 12] = [
+    150.0, 250.0, 350.0, 500.0, 700.0, 900.0, 1200.0, 1600.0, 2000.0, 2600.0, 3300.0, 4000.0,
+];
+
+/// A recorded track decoded to mono samples.
+pub struct Track {
+    samples: Vec<f32>,
+    sample_rate: u32,
+}
+
+impl Track {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let mut reader = WavReader::open(path)
+            .map_err(|err| format!("Failed to open file {}: {}", path.display(), err))?;
+        let spec = reader.spec();
+        let interleaved: Vec<f32> = match spec.sample_format {
+            SampleFormat::Int => {
+                let scale = (1_i64 << (spec.bits_per_sample - 1)) as f32;
+                reader
+                    .samples::<i32>()
+                    .map(|sample| sample.map(|sample| sample as f32 / scale))
+                    .collect::<Result<_, _>>()
+            }
+            SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>(),
+        }
+        .map_err(|e| e.to_string())?;
+
+        let channels = spec.channels.max(1) as usize;
+        let samples = interleaved
+            .chunks(channels)
+            .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+            .collect();
+
+        Ok(Track {
+            samples,
+            sample_rate: spec.sample_rate,
+        })
+    }
+
+    pub fn slice(&self, start_ms: i64, end_ms: i64) -> &[f32] {
+        let index = |ms: i64| {
+            ((ms.max(0) as u64 * self.sample_rate as u64 / 1000) as usize).min(self.samples.len())
+        };
+        &self.samples[index(start_ms)..index(end_ms.max(start_ms))]
+    }
+}
+
+fn rms(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    (samples.iter().map(|x| x * x).sum::<f32>() / samples.len() as f32).sqrt()
+}
+
+/// Power of a single frequency in `frame`.
+fn goertzel_power(frame: &[f32], frequency: f32, sample_rate: u32) -> f32 {
+    let coefficient = 2.0 * (2.0 * PI * frequency / sample_rate as f32).cos();
+    let (mut previous, mut before_previous) = (0.0, 0.0);
+    for sample in frame {
+        let current = sample + coefficient * previous - before_previous;
+        before_previous = previous;
+        previous = current;
+    }
+    previous * previous + before_previous * before_previous
+        - coefficient * previous * before_previous
+}
+
+/// The average spectral shape of the voiced frames of an utterance, and how
+/// many frames it was taken from. Loudness is removed so that the same voice
+/// matches at any volume. `None` when nothing was voiced.
+pub fn voice_features(samples: &[f32], sample_rate: u32) -> Option<(Vec<f32>, usize)> {
+    let frame_len = (sample_rate as i64 * FRAME_MS / 1000) as usize;
+    if frame_len == 0 {
+        return None;
+    }
+
+    let mut sums = vec![0.0; BANDS_HZ.len()];
+    let mut frames = 0;
+    for frame in samples.chunks_exact(frame_len) {
+        if rms(frame) < SILENCE_RMS {
+            continue;
+        }
+        let log_powers: Vec<f32> = BANDS_HZ
+            .iter()
+            .map(|band| (goertzel_power(frame, *band, sample_rate) + 1e-9).ln())
+            .collect();
+        let mean = log_powers.iter().sum::<f32>() / log_powers.len() as f32;
+        for (sum, log_power) in sums.iter_mut().zip(log_powers) {
+            *sum += log_power - mean;
+        }
+        frames += 1;
+    }
+
+    if frames == 0 {
+        return None;
+    }
+    Some((
+        sums.into_iter().map(|sum| sum / frames as f32).collect(),
+        frames,
+    ))
+}
+
+/// Attributes an utterance to the microphone or the system audio when one of
+/// them is clearly louder.
+pub fn track_source(mic: &[f32], system: &[f32]) -> SpeakerSource {
+    let (mic, system) = (rms(mic), rms(system));
+    if mic > system * TRACK_DOMINANCE {
+        SpeakerSource::Me
+    } else if system > mic * TRACK_DOMINANCE {
+        SpeakerSource::Them
+    } else {
+        SpeakerSource::Unknown
+    }
+}
+
+/// One transcript segment as seen by the clustering.
+pub struct Utterance {
+    pub source: SpeakerSource,
+    pub features: Option<(Vec<f32>, usize)>,
+}
+
+pub struct Diarization {
+    /// The source of every speaker, by speaker index.
+    pub speakers: Vec<SpeakerSource>,
+    /// The speaker index of every utterance.
+    pub assignments: Vec<i32>,
+}
+
+struct Cluster {
+    first: usize,
+    source: SpeakerSource,
+    centroid: Vec<f32>,
+    weight: usize,
+}
+
+/// Voices on the microphone and on system audio are never the same person.
+fn compatible(a: SpeakerSource, b: SpeakerSource) -> bool {
+    a == b || a == SpeakerSource::Unknown || b == SpeakerSource::Unknown
+}
+
+fn distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| (a - b) * (a - b))
+        .sum::<f32>()
+        .sqrt()
+}
+
+/// A pair of clusters that may be merged, ordered so that the closest pair
+/// comes out of a `BinaryHeap` first. The generations tell whether either
+/// cluster changed since the distance was measured.
+struct Candidate {
+    distance: f32,
+    pair: (usize, usize),
+    generations: (usize, usize),
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .distance
+            .total_cmp(&self.distance)
+            .then_with(|| other.pair.cmp(&self.pair))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+/// Groups utterances into speakers by agglomerative clustering of their
+/// voice features, never merging across the microphone and system audio.
+/// Utterances without features join the speaker before them. Speakers are
+/// numbered in order of first appearance.
+pub fn cluster_utterances(utterances: &[Utterance], threshold: f32) -> Diarization {
+    let mut clusters: Vec<Option<Cluster>> = Vec::new();
+    let mut cluster_of: Vec<Option<usize>> = vec![None; utterances.len()];
+    for (i, utterance) in utterances.iter().enumerate() {
+        if let Some((features, weight)) = &utterance.features {
+            cluster_of[i] = Some(clusters.len());
+            clusters.push(Some(Cluster {
+                first: i,
+                source: utterance.source,
+                centroid: features.clone(),
+                weight: *weight,
+            }));
+        }
+    }
+
+    let n = clusters.len();
+    // Bumped whenever a cluster changes, which makes its candidates stale
+    let mut generations = vec![0; n];
+    // Pairs further apart than the threshold are left out, they only come
+    // closer when one of them is merged, which measures them again
+    let candidate = |clusters: &[Option<Cluster>], generations: &[usize], i: usize, j: usize| {
+        let (Some(a), Some(b)) = (&clusters[i], &clusters[j]) else {
+            return None;
+        };
+        if !compatible(a.source, b.source) {
+            return None;
+        }
+        let distance = distance(&a.centroid, &b.centroid);
+        (distance <= threshold).then_some(Candidate {
+            distance,
+            pair: (i, j),
+            generations: (generations[i], generations[j]),
+        })
+    };
+    let mut candidates = BinaryHeap::new();
+    for i in 0..n {
+        for j in i + 1..n {
+            candidates.extend(candidate(&clusters, &generations, i, j));
+        }
+    }
+
+    // `merged_into[j]` is the cluster `j` was merged into, if any
+    let mut merged_into: Vec<Option<usize>> = vec![None; n];
+    while let Some(closest) = candidates.pop() {
+        let (keep, remove) = closest.pair;
+        if clusters[keep].is_none()
+            || clusters[remove].is_none()
+            || closest.generations != (generations[keep], generations[remove])
+        {
+            continue;
+        }
+
+        let removed = clusters[remove]
+            .take()
+            .expect("distances only cover live clusters");
+        let kept = clusters[keep]
+            .as_mut()
+            .expect("distances only cover live clusters");
+        let weight = kept.weight + removed.weight;
+        for (a, b) in kept.centroid.iter_mut().zip(&removed.centroid) {
+            *a = (*a * kept.weight as f32 + b * removed.weight as f32) / weight as f32;
+        }
+        kept.weight = weight;
+        kept.first = kept.first.min(removed.first);
+        if kept.source == SpeakerSource::Unknown {
+            kept.source = removed.source;
+        }
+        merged_into[remove] = Some(keep);
+        generations[keep] += 1;
+
+        for other in (0..n).filter(|other| *other != keep) {
+            let (low, high) = (keep.min(other), keep.max(other));
+            candidates.extend(candidate(&clusters, &generations, low, high));
+        }
+    }
+
+    let resolve = |mut cluster: usize| {
+        while let Some(target) = merged_into[cluster] {
+            cluster = target;
+        }
+        cluster
+    };
+
+    // Fill in the utterances without features from their neighbours
+    let mut resolved: Vec<Option<usize>> = cluster_of
+        .iter()
+        .map(|cluster| cluster.map(resolve))
+        .collect();
+    for i in 1..resolved.len() {
+        if resolved[i].is_none() {
+            resolved[i] = resolved[i - 1];
+        }
+    }
+    let first_known = resolved.iter().flatten().next().copied();
+
+    let mut live: Vec<(usize, SpeakerSource, usize)> = clusters
+        .iter()
+        .enumerate()
+        .filter_map(|(index, cluster)| {
+            cluster
+                .as_ref()
+                .map(|cluster| (cluster.first, cluster.source, index))
+        })
+        .collect();
+    live.sort_by_key(|(first, _, _)| *first);
+
+    let mut speakers: Vec<SpeakerSource> = live.iter().map(|(_, source, _)| *source).collect();
+    let assignments = resolved
+        .into_iter()
+        .map(|cluster| match cluster.or(first_known) {
+            Some(cluster) => live
+                .iter()
+                .position(|(_, _, index)| *index == cluster)
+                .unwrap_or(0) as i32,
+            None => 0,
+        })
+        .collect();
+    if speakers.is_empty() && !utterances.is_empty() {
+        speakers.push(SpeakerSource::Unknown);
+    }
+
+    Diarization {
+        speakers,
+        assignments,
+    }
+}
+
+/// Diarizes a recording from its mixed audio, using the separate microphone
+/// and system audio tracks where the recorder left them.
+pub fn diarize(
+    recording_dir: &Path,
+    segments: &[transcript_segment::Model],
+) -> Result<Diarization, String> {
+    let combined = Track::open(&recording_dir.join("combined.wav"))?;
+    let mic = Track::open(&recording_dir.join("input").join("combined.wav")).ok();
+    let system = Track::open(&recording_dir.join("output").join("combined.wav")).ok();
+    // The mix delays each track to line them up
+    let [mic_delay_ms, system_delay_ms] = RecordingManifest::load_track_delays_ms(recording_dir)?;
+
+    let utterances: Vec<Utterance> = segments
+        .iter()
+        .map(|segment| {
+            let (start_ms, end_ms) = (segment.start_ms, segment.end_ms);
+            let source = match (&mic, &system) {
+                (Some(mic), Some(system)) => track_source(
+                    mic.slice(start_ms - mic_delay_ms, end_ms - mic_delay_ms),
+                    system.slice(start_ms - system_delay_ms, end_ms - system_delay_ms),
+                ),
+                _ => SpeakerSource::Unknown,
+            };
+            // The dominant track has the voice without the other side bleeding in
+            let (track, delay_ms) = match (source, &mic, &system) {
+                (SpeakerSource::Me, Some(mic), _) => (mic, mic_delay_ms),
+                (SpeakerSource::Them, _, Some(system)) => (system, system_delay_ms),
+                _ => (&combined, 0),
+            };
+            Utterance {
+                source,
+                features: voice_features(
+                    track.slice(start_ms - delay_ms, end_ms - delay_ms),
+                    track.sample_rate,
+                ),
+            }
+        })
+        .collect();
+
+    Ok(cluster_utterances(&utterances, MERGE_THRESHOLD))
+}
+
+fn new_speaker(
+    conversation_id: i32,
+    speaker_index: i32,
+    name: String,
+    source: SpeakerSource,
+) -> speaker::Model {
+    speaker::Model {
+        id: 0,
+        conversation_id,
+        speaker_index,
+        name,
+        source,
+        created_at: String::new(),
+        updated_at: String::new(),
+    }
+}
+
+/// Assigns the transcript segments of a conversation to speakers and stores
+/// the speakers.
+pub async fn diarize_conversation(
+    handle: AppHandle,
+    conversation_id: i32,
+    recording_dir: &Path,
+) -> Result<(), String> {
+    let app_state: State<AppState> = handle.state();
+    if separate_tracks(recording_dir)?.is_some() {
+        // The track a segment was transcribed from already tells who spoke
+        let speakers = vec![
+            new_speaker(
+                conversation_id,
+                MIC_SPEAKER,
+                "You".to_string(),
+                SpeakerSource::Me,
+            ),
+            new_speaker(
+                conversation_id,
+                SYSTEM_SPEAKER,
+                "Remote".to_string(),
+                SpeakerSource::Them,
+            ),
+        ];
+        return Mutation::create_speakers(&app_state.db, conversation_id, speakers)
+            .await
+            .map_err(|e| e.to_string());
+    }
+
+    let segments =
+        Query::find_transcript_segments_by_conversation_id(&app_state.db, conversation_id)
+            .await
+            .map_err(|e| e.to_string())?;
+    if segments.is_empty() {
+        return Ok(());
+    }
+
+    let recording_dir = recording_dir.to_path_buf();
+    let diarized_segments = segments.clone();
+    let diarization =
+        tauri::async_runtime::spawn_blocking(move || diarize(&recording_dir, &diarized_segments))
+            .await
+            .map_err(|e| e.to_string())??;
+    info!(
+        "Found {} speakers in conversation {}",
+        diarization.speakers.len(),
+        conversation_id
+    );
+
+    let reassigned = segments
+        .iter()
+        .zip(&diarization.assignments)
+        .filter(|(segment, speaker)| segment.speaker != **speaker)
+        .map(|(segment, speaker)| (segment.id, *speaker))
+        .collect();
+    Mutation::update_transcript_segment_speakers(&app_state.db, reassigned)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let speakers = diarization
+        .speakers
+        .into_iter()
+        .enumerate()
+        .map(|(index, source)| {
+            new_speaker(
+                conversation_id,
+                index as i32,
+                speaker_label(index as i32),
+                source,
+            )
+        })
+        .collect();
+    Mutation::create_speakers(&app_state.db, conversation_id, speakers)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/samples/a13.wav");
+
+    fn tone(frequency: f32, ms: usize) -> Vec<f32> {
+        (0..16 * ms)
+            .map(|i| 0.5 * (2.0 * PI * frequency * i as f32 / 16_000.0).sin())
+            .collect()
+    }
+
+    fn utterance(source: SpeakerSource, frequency: f32) -> Utterance {
+        Utterance {
+            source,
+            features: voice_features(&tone(frequency, 500), 16_000),
+        }
+    }
+
+    #[test]
+    fn louder_track_decides_the_source() {
+        let loud = tone(200.0, 100);
+        let quiet: Vec<f32> = loud.iter().map(|x| x * 0.1).collect();
+
+        assert_eq!(track_source(&loud, &quiet), SpeakerSource::Me);
+        assert_eq!(track_source(&quiet, &loud), SpeakerSource::Them);
+        assert_eq!(track_source(&loud, &loud), SpeakerSource::Unknown);
+    }
+
+    #[test]
+    fn silence_has_no_features() {
+        assert!(voice_features(&[0.0; 16_000], 16_000).is_none());
+    }
+
+    #[test]
+    fn similar_voices_cluster_and_tracks_stay_apart() {
+        let utterances = vec![
+            utterance(SpeakerSource::Them, 250.0),
+            utterance(SpeakerSource::Them, 2000.0),
+            Utterance {
+                source: SpeakerSource::Them,
+                features: None,
+            },
+            utterance(SpeakerSource::Them, 250.0),
+            // Sounds like the first voice, but is on the microphone
+            utterance(SpeakerSource::Me, 250.0),
+        ];
+
+        let diarization = cluster_utterances(&utterances, MERGE_THRESHOLD);
+
+        assert_eq!(diarization.assignments, vec![0, 1, 1, 0, 2]);
+        assert_eq!(
+            diarization.speakers,
+            vec![SpeakerSource::Them, SpeakerSource::Them, SpeakerSource::Me]
+        );
+    }
+
+    /// Plays `samples` faster, which raises the pitch and the formants the
+    /// way a different voice would.
+    fn faster(samples: &[f32], ratio: f32) -> Vec<f32> {
+        (0..((samples.len() - 1) as f32 / ratio) as usize)
+            .map(|i| {
+                let position = i as f32 * ratio;
+                let index = position as usize;
+                let fraction = position - index as f32;
+                samples[index] * (1.0 - fraction) + samples[index + 1] * fraction
+            })
+            .collect()
+    }
+
+    #[test]
+    fn threshold_tells_real_voices_apart() {
+        let sample = Track::open(Path::new(SAMPLE)).unwrap();
+        let other_voice = faster(&sample.samples, 1.25);
+        let window = 3 * sample.sample_rate as usize;
+
+        let utterances: Vec<Utterance> = sample
+            .samples
+            .chunks(window)
+            .chain(other_voice.chunks_exact(window))
+            .map(|samples| Utterance {
+                source: SpeakerSource::Unknown,
+                features: voice_features(samples, sample.sample_rate),
+            })
+            .collect();
+        let first_voice = sample.samples.chunks(window).count();
+
+        let diarization = cluster_utterances(&utterances, MERGE_THRESHOLD);
+
+        assert_eq!(diarization.speakers.len(), 2);
+        assert!(diarization.assignments[..first_voice]
+            .iter()
+            .all(|speaker| *speaker == 0));
+        assert!(diarization.assignments[first_voice..]
+            .iter()
+            .all(|speaker| *speaker == 1));
+    }
+}
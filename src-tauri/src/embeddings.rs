@@ -0,0 +1,419 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use entity::embedding;
+use entity::job::{JobKind, JobStatus};
+use llama_cpp::EmbeddingsParams;
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use service::{sea_orm::DatabaseConnection, Mutation, Query};
+use tauri::{AppHandle, Manager, State};
+
+use crate::jobs::JobQueue;
+use crate::llm::LlamaCppBackend;
+use crate::speakers::SpeakerNames;
+use crate::summarize::chunk_segments;
+use crate::AppState;
+
+/// Setting key holding the JSON-encoded [`EmbeddingSettings`].
+pub const EMBEDDING_SETTING: &str = "embedding_backend";
+
+// Small chunks keep a hit pointing close to where the topic came up
+const EMBEDDING_CHUNK_TOKENS: usize = 200;
+const EMBEDDING_CHUNK_MAX_DURATION_MS: i64 = 2 * 60 * 1000;
+
+/// A model that turns text into vectors for semantic search.
+#[async_trait]
+pub trait EmbeddingBackend: Send + Sync {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, String>;
+}
+
+/// Which embedding model to use, stored as JSON under [`EMBEDDING_SETTING`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EmbeddingSettings {
+    Ollama {
+        host: String,
+        port: u16,
+        model: String,
+    },
+    LlamaCpp {
+        model_path: PathBuf,
+    },
+}
+
+impl Default for EmbeddingSettings {
+    fn default() -> Self {
+        EmbeddingSettings::Ollama {
+            host: "http://localhost".to_string(),
+            port: 11434,
+            model: "nomic-embed-text".to_string(),
+        }
+    }
+}
+
+impl EmbeddingSettings {
+    /// The name vectors are tagged with.
+    pub fn model_name(&self) -> String {
+        match self {
+            EmbeddingSettings::Ollama { model, .. } => model.clone(),
+            EmbeddingSettings::LlamaCpp { model_path } => model_path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        }
+    }
+
+    pub async fn load(db: &DatabaseConnection) -> Result<Self, String> {
+        match Query::find_setting(db, EMBEDDING_SETTING)
+            .await
+            .map_err(|e| e.to_string())?
+        {
+            Some(value) => serde_json::from_str(&value).map_err(|e| e.to_string()),
+            None => Ok(EmbeddingSettings::default()),
+        }
+    }
+}
+
+/// Uses Ollama's `/api/embed` endpoint, which embeds a batch per request.
+pub struct OllamaEmbeddings {
+    client: reqwest::Client,
+    url: String,
+    model: String,
+}
+
+impl OllamaEmbeddings {
+    pub fn new(host: String, port: u16, model: String) -> Self {
+        OllamaEmbeddings {
+            client: reqwest::Client::new(),
+            url: format!("{}:{}/api/embed", host.trim_end_matches('/'), port),
+            model,
+        }
+    }
+}
+
+#[async_trait]
+impl EmbeddingBackend for OllamaEmbeddings {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, String> {
+        let response = self
+            .client
+            .post(&self.url)
+            .json(&json!({ "model": self.model, "input": texts }))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("Ollama embedding request failed: {}", e))?;
+        let mut response: Value = response.json().await.map_err(|e| e.to_string())?;
+
+        serde_json::from_value(response["embeddings"].take())
+            .map_err(|e| format!("Unexpected embedding response: {}", e))
+    }
+}
+
+#[async_trait]
+impl EmbeddingBackend for LlamaCppBackend {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, String> {
+        // Inference is CPU bound, keep it off the async runtime
+        tokio::task::block_in_place(|| {
+            self.model()?
+                .embeddings(texts, EmbeddingsParams::default())
+                .map_err(|e| format!("Failed to embed: {}", e))
+        })
+    }
+}
+
+/// Builds the embedding backend selected in settings, keeping a llama.cpp
+/// model loaded between calls.
+#[derive(Default)]
+pub struct EmbeddingBackends {
+    llama_cpp: Mutex<Option<Arc<LlamaCppBackend>>>,
+}
+
+impl EmbeddingBackends {
+    pub fn build(&self, settings: EmbeddingSettings) -> Arc<dyn EmbeddingBackend> {
+        match settings {
+            EmbeddingSettings::Ollama { host, port, model } => {
+                Arc::new(OllamaEmbeddings::new(host, port, model))
+            }
+            EmbeddingSettings::LlamaCpp { model_path } => {
+                let mut cached = self.llama_cpp.lock().expect("llama backend lock poisoned");
+                match cached.as_ref() {
+                    Some(backend) if backend.model_path() == model_path => backend.clone(),
+                    _ => {
+                        let backend = Arc::new(LlamaCppBackend::new(model_path));
+                        *cached = Some(backend.clone());
+                        backend
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Scales `vector` to unit length, so similarity is a dot product.
+fn normalize(mut vector: Vec<f32>) -> Vec<f32> {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|x| *x /= norm);
+    }
+    vector
+}
+
+pub fn encode_vector(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+pub fn decode_vector(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect()
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+/// Chunks the transcript of a conversation, embeds the chunks and replaces
+/// the conversation's stored vectors.
+pub async fn embed_conversation(handle: AppHandle, conversation_id: i32) -> Result<(), String> {
+    let app_state: State<AppState> = handle.state();
+    let segments =
+        Query::find_transcript_segments_by_conversation_id(&app_state.db, conversation_id)
+            .await
+            .map_err(|e| e.to_string())?;
+    let speakers = SpeakerNames::load(&app_state.db, conversation_id).await?;
+    let chunks = chunk_segments(
+        &segments,
+        &speakers,
+        EMBEDDING_CHUNK_TOKENS,
+        EMBEDDING_CHUNK_MAX_DURATION_MS,
+    );
+    if chunks.is_empty() {
+        return Ok(());
+    }
+
+    let settings = EmbeddingSettings::load(&app_state.db).await?;
+    let backends: State<EmbeddingBackends> = handle.state();
+    let backend = backends.build(settings.clone());
+
+    info!(
+        "Embedding {} chunks of conversation {}",
+        chunks.len(),
+        conversation_id
+    );
+    let texts: Vec<String> = chunks.iter().map(|chunk| chunk.text.clone()).collect();
+    let vectors = backend.embed(&texts).await?;
+    if vectors.len() != chunks.len() {
+        return Err(format!(
+            "Expected {} embeddings, got {}",
+            chunks.len(),
+            vectors.len()
+        ));
+    }
+
+    let model = settings.model_name();
+    let embeddings = chunks
+        .into_iter()
+        .zip(vectors)
+        .map(|(chunk, vector)| embedding::Model {
+            id: 0,
+            conversation_id,
+            segment_id: chunk.first_segment_id,
+            start_ms: chunk.start_ms,
+            end_ms: chunk.end_ms,
+            text: chunk.text,
+            model: model.clone(),
+            vector: encode_vector(&normalize(vector)),
+            created_at: String::new(),
+        })
+        .collect();
+
+    Mutation::create_embeddings(&app_state.db, conversation_id, embeddings)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SemanticHit {
+    pub conversation_id: i32,
+    pub title: String,
+    pub segment_id: i32,
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub text: String,
+    /// Cosine similarity to the query, higher is closer.
+    pub score: f32,
+}
+
+/// The `k` stored chunks closest to `query`, best first.
+pub fn rank_embeddings(
+    query: &[f32],
+    embeddings: Vec<embedding::Model>,
+    k: usize,
+) -> Vec<(embedding::Model, f32)> {
+    let mut scored: Vec<(embedding::Model, f32)> = embeddings
+        .into_iter()
+        .map(|embedding| {
+            let score = dot(query, &decode_vector(&embedding.vector));
+            (embedding, score)
+        })
+        .collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scored.truncate(k);
+    scored
+}
+
+/// Finds the transcript passages closest in meaning to `query` across all
+/// conversations. Only conversations embedded with the current model are searched.
+#[tauri::command]
+pub async fn semantic_search(
+    state: State<'_, AppState>,
+    backends: State<'_, EmbeddingBackends>,
+    query: String,
+    k: usize,
+) -> Result<Vec<SemanticHit>, String> {
+    let query = query.trim().to_string();
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let settings = EmbeddingSettings::load(&state.db).await?;
+    let backend = backends.build(settings.clone());
+    let query_vector = backend
+        .embed(&[query])
+        .await?
+        .pop()
+        .map(normalize)
+        .ok_or("No embedding returned for the query".to_string())?;
+
+    let embeddings = Query::find_embeddings_by_model(&state.db, &settings.model_name())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut hits = Vec::new();
+    for (embedding, score) in rank_embeddings(&query_vector, embeddings, k) {
+        let title = Query::find_conversation_by_id(&state.db, embedding.conversation_id)
+            .await
+            .map_err(|e| e.to_string())?
+            .map(|conversation| conversation.title)
+            .unwrap_or_default();
+        hits.push(SemanticHit {
+            conversation_id: embedding.conversation_id,
+            title,
+            segment_id: embedding.segment_id,
+            start_ms: embedding.start_ms,
+            end_ms: embedding.end_ms,
+            text: embedding.text,
+            score,
+        });
+    }
+
+    Ok(hits)
+}
+
+/// Queues embedding for every transcribed conversation that has no chunks
+/// embedded with the current model, such as conversations from before
+/// semantic search or from before the model changed. Returns how many were
+/// queued.
+#[tauri::command]
+pub async fn embed_missing_conversations(
+    state: State<'_, AppState>,
+    queue: State<'_, JobQueue>,
+) -> Result<usize, String> {
+    let settings = EmbeddingSettings::load(&state.db).await?;
+    let conversation_ids =
+        Query::find_conversation_ids_without_embeddings(&state.db, &settings.model_name())
+            .await
+            .map_err(|e| e.to_string())?;
+
+    let mut queued = 0;
+    for conversation_id in conversation_ids {
+        let jobs = Query::find_jobs_by_conversation_id(&state.db, conversation_id)
+            .await
+            .map_err(|e| e.to_string())?;
+        let already_queued = jobs.iter().any(|job| {
+            job.kind == JobKind::Embed
+                && matches!(job.status, JobStatus::Pending | JobStatus::Running)
+        });
+        if !already_queued {
+            queue
+                .enqueue(&state.db, conversation_id, JobKind::Embed)
+                .await?;
+            queued += 1;
+        }
+    }
+
+    info!("Queued embedding of {} conversations", queued);
+    Ok(queued)
+}
+
+#[tauri::command]
+pub async fn get_embedding_settings(
+    state: State<'_, AppState>,
+) -> Result<EmbeddingSettings, String> {
+    EmbeddingSettings::load(&state.db).await
+}
+
+/// Changes the embedding model. Conversations embedded with another model
+/// drop out of semantic search until they are embedded again.
+#[tauri::command]
+pub async fn set_embedding_settings(
+    state: State<'_, AppState>,
+    settings: EmbeddingSettings,
+) -> Result<(), String> {
+    if let EmbeddingSettings::LlamaCpp { model_path } = &settings {
+        if !model_path.exists() {
+            return Err(format!("Model {} doesn't exist", model_path.display()));
+        }
+    }
+
+    let value = serde_json::to_string(&settings).map_err(|e| e.to_string())?;
+    Mutation::set_setting(&state.db, EMBEDDING_SETTING, value)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn embedding(id: i32, vector: &[f32]) -> embedding::Model {
+        embedding::Model {
+            id,
+            conversation_id: 1,
+            segment_id: id,
+            start_ms: 0,
+            end_ms: 0,
+            text: String::new(),
+            model: "test".to_string(),
+            vector: encode_vector(&normalize(vector.to_vec())),
+            created_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn vectors_survive_encoding() {
+        let vector = vec![0.5, -1.25, 3.0];
+
+        assert_eq!(decode_vector(&encode_vector(&vector)), vector);
+    }
+
+    #[test]
+    fn closest_embeddings_rank_first() {
+        let embeddings = vec![
+            embedding(1, &[0.0, 1.0]),
+            embedding(2, &[1.0, 0.1]),
+            embedding(3, &[-1.0, 0.0]),
+        ];
+
+        let ranked = rank_embeddings(&normalize(vec![2.0, 0.0]), embeddings, 2);
+
+        let ids: Vec<i32> = ranked.iter().map(|(embedding, _)| embedding.id).collect();
+        assert_eq!(ids, vec![2, 1]);
+        assert!(ranked[0].1 > 0.99);
+    }
+}
@@ -0,0 +1,219 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use entity::{conversation, job::JobKind, speaker};
+use log::{error, info};
+use service::{
+    sea_orm::{DatabaseConnection, TryIntoModel},
+    Mutation,
+};
+use tauri::async_runtime::Mutex;
+use tauri::State;
+use tokio::process::Command;
+use uuid::Uuid;
+
+use crate::jobs::{next_kinds, JobQueue};
+use crate::recorder::RecordingState;
+use crate::subtitles::{parse_srt, segments_from_cues, speaker_label, speakers_from_cues, Cue};
+use crate::utils::ffmpeg_path_as_str;
+use crate::AppState;
+
+/// Decodes any file ffmpeg understands into the 16 kHz mono PCM that whisper expects.
+async fn transcode_to_wav(source: &Path, destination: &Path) -> Result<(), String> {
+    // Recording doesn't need ffmpeg, so it is only fetched for the first import
+    tauri::async_runtime::spawn_blocking(ffmpeg_sidecar::download::auto_download)
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|err| format!("Failed to download ffmpeg: {}", err))?;
+    let ffmpeg_binary_path_str = ffmpeg_path_as_str()?;
+
+    let args = vec![
+        "-y",
+        "-i",
+        source.to_str().ok_or("Invalid source path")?,
+        "-vn",
+        "-ar",
+        "16000",
+        "-ac",
+        "1",
+        "-c:a",
+        "pcm_s16le",
+        destination.to_str().ok_or("Invalid destination path")?,
+    ];
+
+    info!("FFmpeg args: {:?}", args);
+
+    let output = Command::new(ffmpeg_binary_path_str)
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "FFmpeg failed to decode {}: {}",
+            source.display(),
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn import_audio_file(
+    state: State<'_, AppState>,
+    job_queue: State<'_, JobQueue>,
+    recording_state: State<'_, Arc<Mutex<RecordingState>>>,
+    path: String,
+    title: Option<String>,
+    transcript_path: Option<String>,
+) -> Result<conversation::Model, String> {
+    let source = PathBuf::from(&path);
+    if !source.exists() {
+        return Err(format!("File {} does not exist", source.display()));
+    }
+
+    let data_dir = recording_state
+        .lock()
+        .await
+        .data_dir
+        .clone()
+        .ok_or("Data directory not set".to_string())?;
+
+    // Everything that can fail on the files happens before the conversation
+    // is created, so a bad file leaves nothing behind
+    let cues = match &transcript_path {
+        Some(transcript_path) => {
+            let content = std::fs::read_to_string(transcript_path)
+                .map_err(|err| format!("Failed to read file {}: {}", transcript_path, err))?;
+            Some(parse_srt(&content)?)
+        }
+        None => None,
+    };
+    let import_dir = data_dir.join("chunks/import");
+    std::fs::create_dir_all(&import_dir).map_err(|e| e.to_string())?;
+    let wav_path = import_dir.join(format!("{}.wav", Uuid::new_v4()));
+    if let Err(err) = transcode_to_wav(&source, &wav_path).await {
+        let _ = std::fs::remove_file(&wav_path);
+        return Err(err);
+    }
+
+    // A title picked on import is kept; the file name may be replaced later
+    let title_edited = title.is_some();
+    let title = title.unwrap_or_else(|| {
+        source
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "Imported Conversation".to_string())
+    });
+
+    let conversation = Mutation::create_conversation(
+        &state.db,
+        conversation::Model {
+            title,
+            title_edited,
+            id: 0,
+            created_at: String::new(),
+            updated_at: String::new(),
+            transcription_model: None,
+            language: None,
+            translate: false,
+            initial_prompt: None,
+            beam_size: None,
+            temperature: None,
+            detected_language: None,
+            recovered: false,
+        },
+    )
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|conversation| conversation.try_into_model().map_err(|e| e.to_string()));
+    let conversation = match conversation {
+        Ok(conversation) => conversation,
+        Err(err) => {
+            let _ = std::fs::remove_file(&wav_path);
+            return Err(err);
+        }
+    };
+
+    let recording_dir = data_dir
+        .join("chunks/audio")
+        .join(conversation.id.to_string());
+    if let Err(err) = store_import(
+        &state.db,
+        &job_queue,
+        conversation.id,
+        &wav_path,
+        &recording_dir,
+        cues,
+    )
+    .await
+    {
+        let _ = std::fs::remove_file(&wav_path);
+        let _ = std::fs::remove_dir_all(&recording_dir);
+        if let Err(err) = Mutation::delete_conversation(&state.db, conversation.id).await {
+            error!(
+                "Failed to delete conversation {} of a failed import: {}",
+                conversation.id, err
+            );
+        }
+        return Err(err);
+    }
+
+    Ok(conversation)
+}
+
+/// Moves the transcoded audio into the conversation's recording directory
+/// and queues the work on it.
+async fn store_import(
+    db: &DatabaseConnection,
+    job_queue: &JobQueue,
+    conversation_id: i32,
+    wav_path: &Path,
+    recording_dir: &Path,
+    cues: Option<Vec<Cue>>,
+) -> Result<(), String> {
+    std::fs::create_dir_all(recording_dir).map_err(|e| e.to_string())?;
+    std::fs::rename(wav_path, recording_dir.join("combined.wav")).map_err(|e| e.to_string())?;
+
+    // An existing SRT transcript replaces the whisper pass
+    let Some(cues) = cues else {
+        job_queue
+            .enqueue(db, conversation_id, JobKind::Transcribe)
+            .await?;
+        return Ok(());
+    };
+
+    Mutation::create_transcript_segments(
+        db,
+        conversation_id,
+        segments_from_cues(&cues, conversation_id),
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+
+    // Speakers named in the file keep their names, there is nothing to diarize
+    let speakers = speakers_from_cues(&cues)
+        .into_iter()
+        .enumerate()
+        .map(|(index, name)| speaker::Model {
+            id: 0,
+            conversation_id,
+            speaker_index: index as i32,
+            name: name.unwrap_or_else(|| speaker_label(index as i32)),
+            source: speaker::SpeakerSource::Unknown,
+            created_at: String::new(),
+            updated_at: String::new(),
+        })
+        .collect();
+    Mutation::create_speakers(db, conversation_id, speakers)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // The file has speakers already, carry on where diarization would
+    for kind in next_kinds(JobKind::Diarize) {
+        job_queue.enqueue(db, conversation_id, *kind).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,350 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use entity::job::{self, JobKind, JobStatus};
+use log::{error, info};
+use service::{sea_orm::DatabaseConnection, Mutation, Query};
+use tauri::{AppHandle, Manager};
+use tokio::sync::Notify;
+
+use crate::diarization::diarize_conversation;
+use crate::embeddings::embed_conversation;
+use crate::llm::SummaryBackends;
+use crate::manifest::RecordingManifest;
+use crate::model_registry::ModelRegistry;
+use crate::recorder::{
+    combine_segments, concat_segments, create_summary_version, stereo_segments,
+    summarize_conversation, SummaryVersionOptions,
+};
+use crate::speakers::SpeakerNames;
+use crate::summarize::{chunk_segments, generate_title, CHUNK_TOKEN_BUDGET};
+use crate::transcribe::{
+    separate_tracks, transcribe_tracks, transcribe_wav_file, TranscriptionOptions,
+};
+use crate::transcription_engine::TranscriptionEngine;
+use crate::AppState;
+
+const MAX_ATTEMPTS: i32 = 3;
+/// The wait before the first retry of a failed job.
+const RETRY_DELAY_MS: i64 = 30_000;
+
+/// Post-processing queue backed by the `job` table. Jobs survive restarts:
+/// anything left running when the app exited is picked up again by the worker.
+#[derive(Default)]
+pub struct JobQueue {
+    notify: Arc<Notify>,
+}
+
+impl JobQueue {
+    pub fn new() -> Self {
+        JobQueue {
+            notify: Arc::new(Notify::new()),
+        }
+    }
+
+    pub async fn enqueue(
+        &self,
+        db: &DatabaseConnection,
+        conversation_id: i32,
+        kind: JobKind,
+    ) -> Result<job::Model, String> {
+        self.enqueue_with_params(db, conversation_id, kind, None)
+            .await
+    }
+
+    /// Enqueues a job with JSON `params` for the kinds that take options.
+    pub async fn enqueue_with_params(
+        &self,
+        db: &DatabaseConnection,
+        conversation_id: i32,
+        kind: JobKind,
+        params: Option<String>,
+    ) -> Result<job::Model, String> {
+        let job = Mutation::create_job(db, conversation_id, kind, params)
+            .await
+            .map_err(|e| e.to_string())?;
+        info!(
+            "Enqueued {:?} job {} for conversation {}",
+            kind, job.id, conversation_id
+        );
+        self.notify.notify_one();
+        Ok(job)
+    }
+
+    /// Requeues interrupted jobs and starts the worker.
+    pub async fn start(&self, handle: AppHandle) -> Result<(), String> {
+        let app_state: tauri::State<AppState> = handle.state();
+        let result = Mutation::reset_running_jobs(&app_state.db)
+            .await
+            .map_err(|e| e.to_string())?;
+        if result.rows_affected > 0 {
+            info!("Resuming {} interrupted jobs", result.rows_affected);
+        }
+
+        let notify = self.notify.clone();
+        tauri::async_runtime::spawn(async move {
+            loop {
+                let app_state: tauri::State<AppState> = handle.state();
+                let now_ms = chrono::Utc::now().timestamp_millis();
+                match Query::find_next_pending_job(&app_state.db, now_ms).await {
+                    Ok(Some(job)) => process_job(&handle, job).await,
+                    Ok(None) => wait_for_jobs(&app_state.db, &notify).await,
+                    Err(err) => {
+                        error!("Failed to fetch next job: {}", err);
+                        notify.notified().await;
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
+
+/// Waits until a job is enqueued or a failed job is due to be retried.
+async fn wait_for_jobs(db: &DatabaseConnection, notify: &Notify) {
+    match Query::find_next_job_run_after(db).await {
+        Ok(Some(run_after)) => {
+            let delay_ms = run_after - chrono::Utc::now().timestamp_millis();
+            let delay = Duration::from_millis(delay_ms.max(0) as u64);
+            let _ = tokio::time::timeout(delay, notify.notified()).await;
+        }
+        Ok(None) => notify.notified().await,
+        Err(err) => {
+            error!("Failed to fetch next retry: {}", err);
+            notify.notified().await;
+        }
+    }
+}
+
+/// How long to wait before another attempt at a job that failed `attempts`
+/// times, doubling with every attempt.
+fn retry_delay_ms(attempts: i32) -> i64 {
+    RETRY_DELAY_MS << (attempts - 1).clamp(0, 16)
+}
+
+/// The jobs that follow `kind` in the post-recording pipeline. Embedding
+/// only needs the transcript and speakers, so it doesn't wait on the LLM.
+pub fn next_kinds(kind: JobKind) -> &'static [JobKind] {
+    match kind {
+        JobKind::Concat => &[JobKind::Mix],
+        JobKind::Mix => &[JobKind::Transcribe],
+        JobKind::Transcribe => &[JobKind::Diarize],
+        JobKind::Diarize => &[JobKind::Summarize, JobKind::Embed],
+        JobKind::Summarize => &[JobKind::Title],
+        JobKind::Resummarize | JobKind::Title | JobKind::Embed => &[],
+    }
+}
+
+async fn process_job(handle: &AppHandle, job: job::Model) {
+    let app_state: tauri::State<AppState> = handle.state();
+    let db = &app_state.db;
+
+    let job = match Mutation::start_job(db, job.id).await {
+        Ok(job) => job,
+        Err(err) => {
+            error!("Failed to start job {}: {}", job.id, err);
+            return;
+        }
+    };
+
+    info!(
+        "Running {:?} job {} for conversation {} (attempt {})",
+        job.kind, job.id, job.conversation_id, job.attempts
+    );
+
+    // Run on a separate task so a panic in the pipeline fails the job
+    // instead of taking down the worker.
+    let result = tauri::async_runtime::spawn(run_job(
+        handle.clone(),
+        job.conversation_id,
+        job.kind,
+        job.params.clone(),
+    ))
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|result| result);
+
+    match result {
+        Ok(()) => {
+            if let Err(err) = Mutation::update_job_status(db, job.id, JobStatus::Done, None).await {
+                error!("Failed to complete job {}: {}", job.id, err);
+                return;
+            }
+            let queue: tauri::State<JobQueue> = handle.state();
+            for &kind in next_kinds(job.kind) {
+                if let Err(err) = queue.enqueue(db, job.conversation_id, kind).await {
+                    error!("Failed to enqueue {:?} job: {}", kind, err);
+                }
+            }
+        }
+        Err(err) => {
+            error!("{:?} job {} failed: {}", job.kind, job.id, err);
+            let updated = if job.attempts < MAX_ATTEMPTS {
+                let delay_ms = retry_delay_ms(job.attempts);
+                info!("Retrying job {} in {} s", job.id, delay_ms / 1000);
+                let run_after = chrono::Utc::now().timestamp_millis() + delay_ms;
+                Mutation::retry_job(db, job.id, err, run_after).await
+            } else {
+                Mutation::update_job_status(db, job.id, JobStatus::Failed, Some(err)).await
+            };
+            if let Err(err) = updated {
+                error!("Failed to update job {}: {}", job.id, err);
+            }
+        }
+    }
+}
+
+pub fn recording_dir(handle: &AppHandle, conversation_id: i32) -> Result<PathBuf, String> {
+    Ok(handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| e.to_string())?
+        .join("chunks/audio")
+        .join(conversation_id.to_string()))
+}
+
+async fn run_job(
+    handle: AppHandle,
+    conversation_id: i32,
+    kind: JobKind,
+    params: Option<String>,
+) -> Result<(), String> {
+    let recording_dir = recording_dir(&handle, conversation_id)?;
+    let app_state: tauri::State<AppState> = handle.state();
+
+    match kind {
+        JobKind::Concat => {
+            concat_segments(&recording_dir.join("input")).await?;
+            concat_segments(&recording_dir.join("output")).await?;
+        }
+        JobKind::Mix => {
+            combine_segments(&recording_dir).await?;
+            if separate_tracks(&recording_dir)?.is_some() {
+                stereo_segments(&recording_dir).await?;
+            }
+        }
+        JobKind::Transcribe => {
+            let conversation = Query::find_conversation_by_id(&app_state.db, conversation_id)
+                .await
+                .map_err(|e| e.to_string())?
+                .ok_or("Conversation not found".to_string())?;
+            let combined_audio_file = recording_dir.join("combined.wav");
+            let registry: tauri::State<Arc<ModelRegistry>> = handle.state();
+            let model = registry
+                .resolve_model(&app_state.db, Some(conversation_id))
+                .await?;
+            let options = TranscriptionOptions {
+                tdrz: model.tdrz,
+                ..TranscriptionOptions::from(&conversation)
+            };
+            options.check_model(&model)?;
+            let engine: tauri::State<Arc<TranscriptionEngine>> = handle.state();
+            let mut whisper_state = engine.inner().clone().acquire_for(model.path).await?;
+            let tracks = separate_tracks(&recording_dir)?;
+            let delays_ms = RecordingManifest::load_track_delays_ms(&recording_dir)?;
+            let (segments, detected_language) =
+                tauri::async_runtime::spawn_blocking(move || match tracks {
+                    Some((mic, system)) => transcribe_tracks(
+                        &mut whisper_state,
+                        &mic,
+                        &system,
+                        delays_ms,
+                        conversation_id,
+                        &options,
+                    ),
+                    None => transcribe_wav_file(
+                        &mut whisper_state,
+                        &combined_audio_file,
+                        conversation_id,
+                        &options,
+                    ),
+                })
+                .await
+                .map_err(|e| e.to_string())??;
+            Mutation::create_transcript_segments(&app_state.db, conversation_id, segments)
+                .await
+                .map_err(|e| e.to_string())?;
+            Mutation::update_conversation_detected_language(
+                &app_state.db,
+                conversation_id,
+                detected_language,
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        }
+        JobKind::Diarize => {
+            diarize_conversation(handle.clone(), conversation_id, &recording_dir).await?;
+        }
+        JobKind::Summarize => {
+            summarize_conversation(handle.clone(), conversation_id).await?;
+        }
+        JobKind::Resummarize => {
+            let options: SummaryVersionOptions = match params {
+                Some(params) => serde_json::from_str(&params).map_err(|e| e.to_string())?,
+                None => SummaryVersionOptions::default(),
+            };
+            create_summary_version(handle.clone(), conversation_id, options).await?;
+        }
+        JobKind::Title => {
+            let conversation = Query::find_conversation_by_id(&app_state.db, conversation_id)
+                .await
+                .map_err(|e| e.to_string())?
+                .ok_or("Conversation not found".to_string())?;
+            if conversation.title_edited {
+                return Ok(());
+            }
+
+            // The summary fits a prompt where a long transcript wouldn't
+            let summary =
+                Query::find_current_summary_by_conversation_id(&app_state.db, conversation_id)
+                    .await
+                    .map_err(|e| e.to_string())?;
+            let text = match summary {
+                Some(summary) => summary.content,
+                None => {
+                    let segments = Query::find_transcript_segments_by_conversation_id(
+                        &app_state.db,
+                        conversation_id,
+                    )
+                    .await
+                    .map_err(|e| e.to_string())?;
+                    let speakers = SpeakerNames::load(&app_state.db, conversation_id).await?;
+                    chunk_segments(&segments, &speakers, CHUNK_TOKEN_BUDGET, i64::MAX)
+                        .into_iter()
+                        .next()
+                        .map(|chunk| chunk.text)
+                        .unwrap_or_default()
+                }
+            };
+            let backends: tauri::State<SummaryBackends> = handle.state();
+            let backend = backends.current(&app_state.db).await?;
+            let title = generate_title(backend.as_ref(), &text).await?;
+
+            // The user may have renamed the conversation in the meantime
+            let conversation = Query::find_conversation_by_id(&app_state.db, conversation_id)
+                .await
+                .map_err(|e| e.to_string())?
+                .ok_or("Conversation not found".to_string())?;
+            if conversation.title_edited {
+                return Ok(());
+            }
+            Mutation::update_conversation_by_id(
+                &app_state.db,
+                conversation_id,
+                entity::conversation::Model {
+                    title,
+                    ..conversation
+                },
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        }
+        JobKind::Embed => {
+            embed_conversation(handle.clone(), conversation_id).await?;
+        }
+    }
+
+    Ok(())
+}
@@ -1,24 +1,31 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod ask;
 mod audio;
 mod commands;
-mod device_listener;
+mod diarization;
+mod embeddings;
+mod import;
+mod jobs;
+mod live_transcribe;
+mod llm;
+mod manifest;
 mod media;
+mod model_registry;
 mod recorder;
+mod speakers;
+mod subtitles;
 mod summarize;
+#[cfg(test)]
+mod test_utils;
 mod transcribe;
+mod transcription_engine;
 mod utils;
 mod window;
 
-use audio::macos::aggregate_device::{
-    create_input_aggregate_device, create_output_aggregate_device,
-};
-use audio::macos::helpers::{check_device_exists, get_device_uid};
-use coreaudio::audio_unit::macos_helpers::{
-    get_default_device_id, get_device_id_from_name, get_device_name,
-};
-use coreaudio_sys::{AudioDeviceID, AudioObjectID};
+use ask::ask_conversation;
+use audio::AudioCaptureBackend;
 use log::{error, info};
 use migration::Migrator;
 use migration::MigratorTrait;
@@ -34,25 +41,52 @@ use tauri::Manager;
 use tauri::WindowEvent;
 use tauri_plugin_log::{Target, TargetKind};
 use tauri_plugin_positioner::WindowExt;
-use transcribe::{get_complete_transcription, get_real_time_transcription};
-use uuid::Uuid;
+use transcribe::{
+    get_complete_transcription, get_real_time_transcription, get_track_mode,
+    get_transcript_segments, set_track_mode, whisper_model_path,
+};
+use transcription_engine::TranscriptionEngine;
 use window::setup_windows;
 
-use crate::device_listener::ActiveListener;
-use crate::recorder::{RecordingOptions, _start_recording, _stop_recording};
+use crate::recorder::{
+    _start_recording, _stop_recording, recover_interrupted_recordings, RecordingOptions,
+};
 use commands::{
+    action_items::{get_action_items, set_action_item_status},
     conversation::{
         create_conversation, delete_conversation, get_conversation, get_conversations,
-        get_summary_for_converstation, open_conversation,
+        get_summary_for_converstation, open_conversation, rename_conversation,
+        search_conversations, update_transcription_options,
     },
     devices::{
         enumerate_audio_input_devices, enumerate_audio_output_devices, set_input_device_name,
         set_output_device_name,
     },
-    recording::is_recording,
+    export::export_transcript,
+    jobs::get_conversation_jobs,
+    recording::{get_conversation_times, is_recording, is_recording_paused},
+    summaries::{list_summaries, pin_summary, regenerate_summary},
+    summary_templates::{
+        create_summary_template, delete_summary_template, get_summary_templates,
+        set_default_summary_template, update_summary_template,
+    },
 };
+use embeddings::{
+    embed_missing_conversations, get_embedding_settings, semantic_search, set_embedding_settings,
+    EmbeddingBackends,
+};
+use import::import_audio_file;
+use jobs::JobQueue;
+use llm::{get_llm_settings, set_llm_settings, SummaryBackends};
 use media::set_target_output_device;
-use recorder::{delete_recording_data, start_recording, stop_recording, RecordingState};
+use model_registry::{
+    list_models, set_active_model, set_conversation_model, verify_model, ModelRegistry,
+};
+use recorder::{
+    delete_recording_data, finalize_recording, pause_recording, resume_recording, start_recording,
+    stop_recording, RecordingState,
+};
+use speakers::{get_speakers, rename_speaker};
 
 use std::sync::{atomic::AtomicBool, Arc};
 
@@ -62,17 +96,12 @@ struct AppState {
 }
 
 struct DeviceState {
-    active_listener: ActiveListener,
-    selected_input_name: Option<String>,
-    selected_output_name: Option<String>,
-    input_device_id: Option<AudioDeviceID>,
-    aggregate_device_id: Option<AudioDeviceID>,
-    tap_id: Option<AudioObjectID>,
-    output_device_id: Option<AudioDeviceID>,
+    backend: Box<dyn AudioCaptureBackend>,
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    // Tells when the microphone starts being used, so recording can follow
     let (tx, mut rx) = tokio::sync::watch::channel(false);
 
     std::panic::set_hook(Box::new(|info| {
@@ -80,39 +109,6 @@ pub fn run() {
         error!("Panicked: {:?}", info);
     }));
 
-    let device_id = get_default_device_id(true).expect("Failed to get default device");
-    let default_output_device_id =
-        get_default_device_id(false).expect("Failed to get the default output device");
-    let default_input_name =
-        get_device_name(device_id).expect("Failed to get the default device name");
-    let default_output_name =
-        get_device_name(default_output_device_id).expect("Failed to get the default device name");
-
-    let device_id = get_default_device_id(false).expect("failed to get default device");
-    let device_uid = get_device_uid(device_id).expect("failed to get device uid");
-    let aggregate_device_result =
-        create_output_aggregate_device(&device_uid, "Platy Speaker", &Uuid::new_v4().to_string())
-            .expect("failed to create aggregate device");
-
-    let device_exists = check_device_exists("Platy Microphone");
-    if !device_exists {
-        info!("Aggregate microphone device not found, creating one");
-        create_input_aggregate_device("BuiltInMicrophoneDevice")
-            .expect("failed to create aggregate device");
-    } else {
-        info!("Aggregate microphone already exists");
-    }
-
-    let input_device_id =
-        get_device_id_from_name("Platy Microphone", true).expect("Platy Microphone doesn't exist");
-
-    let mut listener = ActiveListener::new(tx);
-    listener
-        .register(input_device_id)
-        .expect("Failed to register listener");
-
-    ffmpeg_sidecar::download::auto_download().expect("Failed to download ffmpeg");
-
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
@@ -162,15 +158,33 @@ pub fn run() {
             let state = AppState { db };
             app.manage(state);
 
-            let device_state = DeviceState {
-                selected_input_name: Some(default_input_name),
-                selected_output_name: Some(default_output_name),
-                active_listener: listener,
-                tap_id: Some(aggregate_device_result.tap_id),
-                input_device_id: Some(input_device_id),
-                aggregate_device_id: Some(aggregate_device_result.aggregate_device_id),
-                output_device_id: Some(device_id),
-            };
+            let registry = ModelRegistry::new(
+                data_directory_clone.join("models"),
+                whisper_model_path(handle),
+            );
+            let engine = TranscriptionEngine::new(1, 1);
+            app.manage(Arc::new(registry));
+            app.manage(Arc::new(engine));
+
+            app.manage(SummaryBackends::default());
+            app.manage(EmbeddingBackends::default());
+
+            app.manage(JobQueue::new());
+            let job_queue: tauri::State<JobQueue> = app.state();
+            async_runtime::block_on(job_queue.start(app.handle().clone()))
+                .expect("Failed to start job queue");
+            if let Err(err) = async_runtime::block_on(recover_interrupted_recordings(app.handle()))
+            {
+                error!("Failed to recover interrupted recordings: {}", err);
+            }
+
+            // Without audio capture the rest of the app still works, there is
+            // just nothing to record
+            let backend = audio::default_backend(tx).unwrap_or_else(|err| {
+                error!("Failed to set up audio capture: {}", err);
+                Box::new(audio::UnavailableBackend::new(err))
+            });
+            let device_state = DeviceState { backend };
             app.manage(Arc::new(tauri::async_runtime::Mutex::new(device_state)));
 
             let _app_handle = app.handle().clone();
@@ -199,9 +213,18 @@ pub fn run() {
                                 &app_state.db,
                                 entity::conversation::Model {
                                     title: "New Conversation".to_string(),
+                                    title_edited: false,
                                     id: 0,
                                     created_at: String::new(),
                                     updated_at: String::new(),
+                                    transcription_model: None,
+                                    language: None,
+                                    translate: false,
+                                    initial_prompt: None,
+                                    beam_size: None,
+                                    temperature: None,
+                                    detected_language: None,
+                                    recovered: false,
                                 },
                             )
                             .await
@@ -210,6 +233,7 @@ pub fn run() {
                             .expect("Failed to convert active model into model");
 
                             _start_recording(
+                                _app_handle.clone(),
                                 recording_state.clone(),
                                 device_state.clone(),
                                 RecordingOptions {
@@ -253,21 +277,58 @@ pub fn run() {
         .invoke_handler(tauri::generate_handler![
             start_recording,
             stop_recording,
+            pause_recording,
+            resume_recording,
             get_real_time_transcription,
             get_complete_transcription,
+            get_track_mode,
+            set_track_mode,
+            get_transcript_segments,
+            list_models,
+            verify_model,
+            set_active_model,
+            set_conversation_model,
+            get_llm_settings,
+            set_llm_settings,
+            export_transcript,
             delete_recording_data,
+            finalize_recording,
             enumerate_audio_input_devices,
             enumerate_audio_output_devices,
             set_target_output_device,
             get_conversation,
             get_conversations,
+            search_conversations,
+            update_transcription_options,
             create_conversation,
             delete_conversation,
+            rename_conversation,
             set_input_device_name,
             set_output_device_name,
             get_summary_for_converstation,
+            regenerate_summary,
+            list_summaries,
+            pin_summary,
+            ask_conversation,
+            semantic_search,
+            embed_missing_conversations,
+            get_embedding_settings,
+            set_embedding_settings,
+            get_speakers,
+            rename_speaker,
+            get_summary_templates,
+            create_summary_template,
+            update_summary_template,
+            set_default_summary_template,
+            delete_summary_template,
+            import_audio_file,
+            get_conversation_jobs,
+            get_action_items,
+            set_action_item_status,
             open_conversation,
             is_recording,
+            is_recording_paused,
+            get_conversation_times,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
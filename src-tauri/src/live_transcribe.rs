@@ -0,0 +1,308 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::time::Duration;
+
+use hound::WavReader;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use service::Query;
+use tauri::{AppHandle, Emitter, Manager};
+use whisper_rs::WhisperState;
+
+use crate::audio::pipeline::SEGMENT_MS;
+use crate::model_registry::ModelRegistry;
+use crate::transcribe::{TranscriptionJSON, TranscriptionOptions};
+use crate::transcription_engine::TranscriptionEngine;
+use crate::AppState;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+// Keep the prompt well below whisper's 224 token limit
+const MAX_PROMPT_CHARS: usize = 600;
+// Chunks whose peak never exceeds this are treated as silence
+const SILENCE_THRESHOLD: i16 = 300;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiveSegment {
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiveTranscriptionEvent {
+    pub conversation_id: u32,
+    /// Either `input` (microphone) or `output` (system audio).
+    pub track: String,
+    pub chunk: usize,
+    pub segments: Vec<LiveSegment>,
+}
+
+/// Starts one live transcriber per recorded track. Each one follows the
+/// `segment_list.txt` written by the recorder and transcribes every chunk as
+/// soon as it is complete.
+pub fn start_live_transcription(
+    handle: AppHandle,
+    conversation_id: u32,
+    recording_dir: PathBuf,
+    shutdown_flag: Arc<AtomicBool>,
+) {
+    for track in ["input", "output"] {
+        let handle = handle.clone();
+        let recording_dir = recording_dir.clone();
+        let shutdown_flag = shutdown_flag.clone();
+        tauri::async_runtime::spawn(async move {
+            if let Err(err) = transcribe_track(
+                handle,
+                conversation_id,
+                &recording_dir,
+                track,
+                shutdown_flag,
+            )
+            .await
+            {
+                error!("Live transcription of {} track failed: {}", track, err);
+            }
+        });
+    }
+}
+
+async fn transcribe_track(
+    handle: AppHandle,
+    conversation_id: u32,
+    recording_dir: &Path,
+    track: &str,
+    shutdown_flag: Arc<AtomicBool>,
+) -> Result<(), String> {
+    let chunks_dir = recording_dir.join(track);
+    let live_dir = recording_dir.join("live");
+    std::fs::create_dir_all(&live_dir).map_err(|e| e.to_string())?;
+
+    let engine: tauri::State<Arc<TranscriptionEngine>> = handle.state();
+    let engine = engine.inner().clone();
+
+    let app_state: tauri::State<AppState> = handle.state();
+    let options = Query::find_conversation_by_id(&app_state.db, conversation_id as i32)
+        .await
+        .map_err(|e| e.to_string())?
+        .map(|conversation| TranscriptionOptions::from(&conversation))
+        .unwrap_or_default();
+    let registry: tauri::State<Arc<ModelRegistry>> = handle.state();
+    let model_path = registry
+        .resolve_model(&app_state.db, Some(conversation_id as i32))
+        .await?
+        .path;
+
+    let mut processed = 0;
+    let mut offset_ms: i64 = 0;
+    let mut prompt = String::new();
+
+    loop {
+        // Segments are only listed once they are complete, so one more pass
+        // after shutdown picks up the final chunk.
+        let finished = shutdown_flag.load(Ordering::SeqCst);
+
+        let segment_files = read_segment_list(&chunks_dir.join("segment_list.txt"));
+        for segment_file in segment_files.iter().skip(processed) {
+            let chunk = processed;
+            processed += 1;
+
+            let (samples, duration_ms) = match read_chunk(&chunks_dir.join(segment_file)) {
+                Ok(chunk) => chunk,
+                Err(err) => {
+                    // Keep the later chunks in place, assuming this one was full length
+                    error!("Failed to read chunk {}: {}", segment_file, err);
+                    offset_ms += SEGMENT_MS;
+                    continue;
+                }
+            };
+            let chunk_offset_ms = offset_ms;
+            offset_ms += duration_ms;
+
+            let Some(samples) = samples else {
+                continue;
+            };
+
+            // A chunk that fails is left out of the live transcript, the
+            // full transcription after the recording still has it
+            let mut whisper_state = match engine.clone().acquire_live_for(model_path.clone()).await
+            {
+                Ok(whisper_state) => whisper_state,
+                Err(err) => {
+                    error!("Failed to load model for chunk {}: {}", segment_file, err);
+                    continue;
+                }
+            };
+            let chunk_prompt = prompt.clone();
+            let chunk_options = options.clone();
+            let transcribed = tauri::async_runtime::spawn_blocking(move || {
+                transcribe_chunk(
+                    &mut whisper_state,
+                    &samples,
+                    &chunk_options,
+                    &chunk_prompt,
+                    chunk_offset_ms,
+                )
+            })
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|segments| segments);
+            let segments = match transcribed {
+                Ok(segments) => segments,
+                Err(err) => {
+                    error!("Failed to transcribe chunk {}: {}", segment_file, err);
+                    continue;
+                }
+            };
+
+            if segments.is_empty() {
+                continue;
+            }
+
+            for segment in &segments {
+                prompt.push_str(&segment.text);
+            }
+            if prompt.len() > MAX_PROMPT_CHARS {
+                let mut cut = prompt.len() - MAX_PROMPT_CHARS;
+                while !prompt.is_char_boundary(cut) {
+                    cut += 1;
+                }
+                prompt.drain(..cut);
+            }
+
+            if let Err(err) = write_chunk_json(&live_dir, chunk_offset_ms, track, &segments) {
+                error!("Failed to save chunk {}: {}", segment_file, err);
+            }
+
+            let event = LiveTranscriptionEvent {
+                conversation_id,
+                track: track.to_string(),
+                chunk,
+                segments,
+            };
+            if let Err(err) = handle.emit("live-transcription", event) {
+                error!("Failed to emit live transcription: {}", err);
+            }
+        }
+
+        if finished {
+            break;
+        }
+
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+
+    info!("Live transcription of {} track finished", track);
+    Ok(())
+}
+
+fn read_segment_list(segment_list_path: &Path) -> Vec<String> {
+    std::fs::read_to_string(segment_list_path)
+        .unwrap_or_default()
+        .lines()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// Returns the chunk's samples (or `None` when it is silent) and its duration.
+fn read_chunk(path: &Path) -> Result<(Option<Vec<f32>>, i64), String> {
+    let mut reader = WavReader::open(path).map_err(|e| e.to_string())?;
+    let spec = reader.spec();
+    let original_samples: Vec<i16> = reader
+        .samples::<i16>()
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let duration_ms = original_samples.len() as i64 * 1000
+        / (spec.sample_rate as i64 * spec.channels.max(1) as i64);
+
+    let peak = original_samples
+        .iter()
+        .map(|sample| sample.saturating_abs())
+        .max()
+        .unwrap_or(0);
+    if peak < SILENCE_THRESHOLD {
+        return Ok((None, duration_ms));
+    }
+
+    let mut samples = vec![0.0f32; original_samples.len()];
+    whisper_rs::convert_integer_to_float_audio(&original_samples, &mut samples)
+        .map_err(|e| e.to_string())?;
+
+    Ok((Some(samples), duration_ms))
+}
+
+fn transcribe_chunk(
+    state: &mut WhisperState,
+    samples: &[f32],
+    options: &TranscriptionOptions,
+    prompt: &str,
+    offset_ms: i64,
+) -> Result<Vec<LiveSegment>, String> {
+    // Live chunks always decode greedily to keep up with the recording
+    let greedy = TranscriptionOptions {
+        beam_size: None,
+        ..options.clone()
+    };
+    let mut params = greedy.full_params();
+    // The rolling context follows the conversation's own vocabulary
+    let prompt = match options.initial_prompt.as_deref() {
+        Some(vocabulary) if !vocabulary.trim().is_empty() => format!("{} {}", vocabulary, prompt),
+        _ => prompt.to_string(),
+    };
+    if !prompt.trim().is_empty() {
+        params.set_initial_prompt(&prompt);
+    }
+    params.set_print_progress(false);
+    params.set_print_realtime(false);
+    params.set_suppress_blank(true);
+
+    state.full(params, samples).map_err(|e| e.to_string())?;
+
+    let num_segments = state.full_n_segments().map_err(|e| e.to_string())?;
+    let mut segments = Vec::with_capacity(num_segments as usize);
+    for i in 0..num_segments {
+        let text = state.full_get_segment_text(i).map_err(|e| e.to_string())?;
+        if text.trim().is_empty() {
+            continue;
+        }
+        // whisper timestamps are in centiseconds
+        let start = state.full_get_segment_t0(i).map_err(|e| e.to_string())?;
+        let end = state.full_get_segment_t1(i).map_err(|e| e.to_string())?;
+        segments.push(LiveSegment {
+            start_ms: offset_ms + start * 10,
+            end_ms: offset_ms + end * 10,
+            text,
+        });
+    }
+
+    Ok(segments)
+}
+
+/// Chunk files are named by their offset so that sorting them by name
+/// interleaves both tracks in time order.
+fn write_chunk_json(
+    live_dir: &Path,
+    offset_ms: i64,
+    track: &str,
+    segments: &[LiveSegment],
+) -> Result<(), String> {
+    let transcription = TranscriptionJSON {
+        full_text: vec![segments
+            .iter()
+            .map(|segment| segment.text.as_str())
+            .collect::<String>()],
+    };
+    let json_string = serde_json::to_string_pretty(&transcription).map_err(|e| e.to_string())?;
+
+    let path = live_dir.join(format!("{:010}_{}.json", offset_ms, track));
+    let mut file = File::create(&path)
+        .map_err(|err| format!("Failed to create file {}: {}", path.display(), err))?;
+    file.write_all(json_string.as_bytes())
+        .map_err(|err| format!("Failed to write file {}: {}", path.display(), err))
+}
@@ -0,0 +1,662 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use llama_cpp::{standard_sampler::StandardSampler, LlamaModel, LlamaParams, SessionParams};
+use log::info;
+use ollama_rs::{
+    generation::{completion::request::GenerationRequest, parameters::FormatType},
+    Ollama,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use service::{sea_orm::DatabaseConnection, Mutation, Query};
+use tauri::State;
+
+use crate::AppState;
+
+/// Setting key holding the JSON-encoded [`LlmSettings`].
+pub const LLM_SETTING: &str = "llm_backend";
+
+/// Stands in for the API key in the settings shown to the user. Saving
+/// settings with it keeps the stored key.
+pub const REDACTED_API_KEY: &str = "********";
+
+const LLAMA_CPP_CONTEXT_SIZE: u32 = 8192;
+const LLAMA_CPP_MAX_TOKENS: usize = 2048;
+
+/// A language model that summaries, titles and action items are generated with.
+#[async_trait]
+pub trait SummaryBackend: Send + Sync {
+    /// Completes `prompt`. When a JSON `schema` is given the response must be
+    /// a JSON document that adheres to it.
+    async fn complete(&self, prompt: &str, schema: Option<&Value>) -> Result<String, String>;
+
+    /// Completes `prompt`, passing the response to `on_token` piece by piece
+    /// as it is generated. Backends that can't stream pass it in one piece.
+    async fn complete_streaming(
+        &self,
+        prompt: &str,
+        on_token: &(dyn Fn(&str) + Send + Sync),
+    ) -> Result<String, String> {
+        let response = self.complete(prompt, None).await?;
+        on_token(&response);
+        Ok(response)
+    }
+}
+
+/// Which backend to use, stored as JSON under the [`LLM_SETTING`] key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LlmSettings {
+    Ollama {
+        host: String,
+        port: u16,
+        model: String,
+    },
+    LlamaCpp {
+        model_path: PathBuf,
+    },
+    /// Any server implementing the OpenAI chat completions API.
+    OpenAi {
+        base_url: String,
+        api_key: Option<String>,
+        model: String,
+    },
+}
+
+impl Default for LlmSettings {
+    fn default() -> Self {
+        LlmSettings::Ollama {
+            host: "http://localhost".to_string(),
+            port: 11434,
+            model: "llama3:latest".to_string(),
+        }
+    }
+}
+
+impl LlmSettings {
+    /// The model summaries are attributed to.
+    pub fn model_name(&self) -> String {
+        match self {
+            LlmSettings::Ollama { model, .. } | LlmSettings::OpenAi { model, .. } => model.clone(),
+            LlmSettings::LlamaCpp { model_path } => model_path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// The same backend with another model; a GGUF path for llama.cpp.
+    pub fn with_model(self, new_model: String) -> Self {
+        match self {
+            LlmSettings::Ollama { host, port, .. } => LlmSettings::Ollama {
+                host,
+                port,
+                model: new_model,
+            },
+            LlmSettings::LlamaCpp { .. } => LlmSettings::LlamaCpp {
+                model_path: PathBuf::from(new_model),
+            },
+            LlmSettings::OpenAi {
+                base_url, api_key, ..
+            } => LlmSettings::OpenAi {
+                base_url,
+                api_key,
+                model: new_model,
+            },
+        }
+    }
+
+    /// The settings with the API key hidden, to be shown.
+    pub fn redacted(self) -> Self {
+        match self {
+            LlmSettings::OpenAi {
+                base_url,
+                api_key,
+                model,
+            } => LlmSettings::OpenAi {
+                base_url,
+                api_key: api_key.map(|_| REDACTED_API_KEY.to_string()),
+                model,
+            },
+            settings => settings,
+        }
+    }
+
+    pub async fn load(db: &DatabaseConnection) -> Result<Self, String> {
+        match Query::find_setting(db, LLM_SETTING)
+            .await
+            .map_err(|e| e.to_string())?
+        {
+            Some(value) => serde_json::from_str(&value).map_err(|e| e.to_string()),
+            None => Ok(LlmSettings::default()),
+        }
+    }
+}
+
+/// Appends the JSON schema instructions for backends without native support.
+fn prompt_with_schema(prompt: &str, schema: Option<&Value>) -> Result<String, String> {
+    let Some(schema) = schema else {
+        return Ok(prompt.to_string());
+    };
+    let schema = serde_json::to_string_pretty(schema).map_err(|e| e.to_string())?;
+
+    Ok(format!(
+        "{}
+
+        You must format your output as a JSON value that adheres to a given \"JSON Schema\" instance.
+        \"JSON Schema\" is a declarative language that allows you to annotate and validate JSON documents.
+        For example, the example \"JSON Schema\" instance {{\"properties\": {{\"foo\": {{\"description\": \"a list of test words\", \"type\": \"array\", \"items\": {{\"type\": \"string\"}}}}}}, \"required\": [\"foo\"]}}
+        would match an object with one required property, \"foo\". The \"type\" property specifies \"foo\" must be an \"array\", and the \"description\" property semantically describes it as \"a list of test words\". The items within \"foo\" must be strings.
+        Thus, the object {{\"foo\": [\"bar\", \"baz\"]}} is a well-formatted instance of this example \"JSON Schema\". The object {{\"properties\": {{\"foo\": [\"bar\", \"baz\"]}}}} is not well-formatted.
+        Your output will be parsed and type-checked according to the provided schema instance, so make sure all fields in your output match the schema exactly and there are no trailing commas!
+        Here is the JSON Schema instance your output must adhere to:
+        ```json
+        {}
+        ```",
+        prompt, schema
+    ))
+}
+
+/// Cuts a JSON document out of a response that may wrap it in prose or a
+/// markdown code block.
+pub fn json_from_response(response: &str) -> &str {
+    match (response.find('{'), response.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &response[start..=end],
+        _ => response,
+    }
+}
+
+pub struct OllamaBackend {
+    ollama: Ollama,
+    model: String,
+}
+
+impl OllamaBackend {
+    pub fn new(host: String, port: u16, model: String) -> Self {
+        OllamaBackend {
+            ollama: Ollama::new(host, port),
+            model,
+        }
+    }
+}
+
+#[async_trait]
+impl SummaryBackend for OllamaBackend {
+    async fn complete(&self, prompt: &str, schema: Option<&Value>) -> Result<String, String> {
+        let mut request =
+            GenerationRequest::new(self.model.clone(), prompt_with_schema(prompt, schema)?);
+        if schema.is_some() {
+            request = request.format(FormatType::Json);
+        }
+
+        let res = self
+            .ollama
+            .generate(request)
+            .await
+            .map_err(|e| format!("Ollama request failed: {}", e))?;
+        Ok(res.response)
+    }
+
+    async fn complete_streaming(
+        &self,
+        prompt: &str,
+        on_token: &(dyn Fn(&str) + Send + Sync),
+    ) -> Result<String, String> {
+        let request = GenerationRequest::new(self.model.clone(), prompt.to_string());
+        let mut stream = self
+            .ollama
+            .generate_stream(request)
+            .await
+            .map_err(|e| format!("Ollama request failed: {}", e))?;
+
+        let mut response = String::new();
+        while let Some(parts) = stream.next().await {
+            let parts = parts.map_err(|e| format!("Ollama stream failed: {:?}", e))?;
+            for part in parts {
+                on_token(&part.response);
+                response.push_str(&part.response);
+            }
+        }
+        Ok(response)
+    }
+}
+
+/// Runs a GGUF model in-process. The model is loaded on first use and kept.
+pub struct LlamaCppBackend {
+    model_path: PathBuf,
+    model: Mutex<Option<LlamaModel>>,
+}
+
+impl LlamaCppBackend {
+    pub fn new(model_path: PathBuf) -> Self {
+        LlamaCppBackend {
+            model_path,
+            model: Mutex::new(None),
+        }
+    }
+
+    pub fn model_path(&self) -> &Path {
+        &self.model_path
+    }
+
+    pub fn model(&self) -> Result<LlamaModel, String> {
+        let mut model = self.model.lock().expect("llama model lock poisoned");
+        if let Some(model) = model.as_ref() {
+            return Ok(model.clone());
+        }
+
+        info!("Loading llama model {}", self.model_path.display());
+        let loaded = LlamaModel::load_from_file(&self.model_path, LlamaParams::default())
+            .map_err(|e| format!("Failed to load model: {}", e))?;
+        *model = Some(loaded.clone());
+        Ok(loaded)
+    }
+
+    fn complete_blocking(&self, prompt: &str, on_token: &dyn Fn(&str)) -> Result<String, String> {
+        let model = self.model()?;
+        let mut session = model
+            .create_session(SessionParams {
+                n_ctx: LLAMA_CPP_CONTEXT_SIZE,
+                ..Default::default()
+            })
+            .map_err(|e| format!("Failed to create llama session: {}", e))?;
+        session
+            .advance_context(prompt)
+            .map_err(|e| format!("Failed to evaluate prompt: {}", e))?;
+
+        let completion = session
+            .start_completing_with(StandardSampler::default(), LLAMA_CPP_MAX_TOKENS)
+            .map_err(|e| format!("Failed to start completion: {}", e))?;
+        let mut response = String::new();
+        for token in completion.into_strings() {
+            on_token(&token);
+            response.push_str(&token);
+        }
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl SummaryBackend for LlamaCppBackend {
+    async fn complete(&self, prompt: &str, schema: Option<&Value>) -> Result<String, String> {
+        let prompt = prompt_with_schema(prompt, schema)?;
+        // Inference is CPU bound, keep it off the async runtime
+        tokio::task::block_in_place(|| self.complete_blocking(&prompt, &|_| {}))
+    }
+
+    async fn complete_streaming(
+        &self,
+        prompt: &str,
+        on_token: &(dyn Fn(&str) + Send + Sync),
+    ) -> Result<String, String> {
+        tokio::task::block_in_place(|| self.complete_blocking(prompt, on_token))
+    }
+}
+
+/// Talks to an OpenAI-compatible `/chat/completions` endpoint, such as
+/// llama.cpp's server, LM Studio or vLLM.
+pub struct OpenAiBackend {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+    model: String,
+}
+
+impl OpenAiBackend {
+    pub fn new(base_url: String, api_key: Option<String>, model: String) -> Self {
+        OpenAiBackend {
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            model,
+        }
+    }
+}
+
+impl OpenAiBackend {
+    async fn send(&self, body: &Value) -> Result<reqwest::Response, String> {
+        let mut request = self
+            .client
+            .post(format!("{}/chat/completions", self.base_url))
+            .json(body);
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+
+        request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("Completion request failed: {}", e))
+    }
+}
+
+/// A line of a streamed chat completion, sent as server-sent events.
+#[derive(Debug, PartialEq)]
+enum StreamLine {
+    Token(String),
+    Done,
+    /// Blank lines, comments and deltas without content.
+    Skip,
+}
+
+fn parse_stream_line(line: &str) -> Result<StreamLine, String> {
+    let Some(data) = line.strip_prefix("data:") else {
+        return Ok(StreamLine::Skip);
+    };
+    let data = data.trim();
+    if data == "[DONE]" {
+        return Ok(StreamLine::Done);
+    }
+
+    let event: Value = serde_json::from_str(data)
+        .map_err(|e| format!("Failed to parse completion stream: {}", e))?;
+    Ok(event["choices"][0]["delta"]["content"]
+        .as_str()
+        .filter(|token| !token.is_empty())
+        .map_or(StreamLine::Skip, |token| {
+            StreamLine::Token(token.to_string())
+        }))
+}
+
+#[async_trait]
+impl SummaryBackend for OpenAiBackend {
+    async fn complete(&self, prompt: &str, schema: Option<&Value>) -> Result<String, String> {
+        let mut body = json!({
+            "model": self.model,
+            "messages": [{ "role": "user", "content": prompt_with_schema(prompt, schema)? }],
+        });
+        if schema.is_some() {
+            body["response_format"] = json!({ "type": "json_object" });
+        }
+
+        let response: Value = self
+            .send(&body)
+            .await?
+            .json()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        response["choices"][0]["message"]["content"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or("Completion response has no content".to_string())
+    }
+
+    async fn complete_streaming(
+        &self,
+        prompt: &str,
+        on_token: &(dyn Fn(&str) + Send + Sync),
+    ) -> Result<String, String> {
+        let body = json!({
+            "model": self.model,
+            "messages": [{ "role": "user", "content": prompt }],
+            "stream": true,
+        });
+        let mut stream = self.send(&body).await?;
+
+        let mut response = String::new();
+        // Events can be split across chunks, only complete lines are parsed
+        let mut pending: Vec<u8> = Vec::new();
+        'stream: while let Some(chunk) = stream
+            .chunk()
+            .await
+            .map_err(|e| format!("Completion stream failed: {}", e))?
+        {
+            pending.extend_from_slice(&chunk);
+            while let Some(end) = pending.iter().position(|byte| *byte == b'\n') {
+                let line: Vec<u8> = pending.drain(..=end).collect();
+                match parse_stream_line(String::from_utf8_lossy(&line).trim_end())? {
+                    StreamLine::Token(token) => {
+                        on_token(&token);
+                        response.push_str(&token);
+                    }
+                    StreamLine::Done => break 'stream,
+                    StreamLine::Skip => {}
+                }
+            }
+        }
+        Ok(response)
+    }
+}
+
+/// Builds the backend selected in settings. The llama.cpp backend is kept
+/// between calls so its model stays loaded.
+#[derive(Default)]
+pub struct SummaryBackends {
+    llama_cpp: Mutex<Option<Arc<LlamaCppBackend>>>,
+}
+
+impl SummaryBackends {
+    pub async fn current(
+        &self,
+        db: &DatabaseConnection,
+    ) -> Result<Arc<dyn SummaryBackend>, String> {
+        Ok(self.build(LlmSettings::load(db).await?))
+    }
+
+    pub fn build(&self, settings: LlmSettings) -> Arc<dyn SummaryBackend> {
+        match settings {
+            LlmSettings::Ollama { host, port, model } => {
+                Arc::new(OllamaBackend::new(host, port, model))
+            }
+            LlmSettings::LlamaCpp { model_path } => {
+                let mut cached = self.llama_cpp.lock().expect("llama backend lock poisoned");
+                match cached.as_ref() {
+                    Some(backend) if backend.model_path == model_path => backend.clone(),
+                    _ => {
+                        let backend = Arc::new(LlamaCppBackend::new(model_path));
+                        *cached = Some(backend.clone());
+                        backend
+                    }
+                }
+            }
+            LlmSettings::OpenAi {
+                base_url,
+                api_key,
+                model,
+            } => Arc::new(OpenAiBackend::new(base_url, api_key, model)),
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn get_llm_settings(state: State<'_, AppState>) -> Result<LlmSettings, String> {
+    Ok(LlmSettings::load(&state.db).await?.redacted())
+}
+
+#[tauri::command]
+pub async fn set_llm_settings(
+    state: State<'_, AppState>,
+    mut settings: LlmSettings,
+) -> Result<(), String> {
+    if let LlmSettings::LlamaCpp { model_path } = &settings {
+        if !model_path.exists() {
+            return Err(format!("Model {} doesn't exist", model_path.display()));
+        }
+    }
+    // The key was shown redacted, keep the one that is stored
+    if let LlmSettings::OpenAi { api_key, .. } = &mut settings {
+        if api_key.as_deref() == Some(REDACTED_API_KEY) {
+            *api_key = match LlmSettings::load(&state.db).await? {
+                LlmSettings::OpenAi { api_key, .. } => api_key,
+                _ => None,
+            };
+        }
+    }
+
+    let value = serde_json::to_string(&settings).map_err(|e| e.to_string())?;
+    Mutation::set_setting(&state.db, LLM_SETTING, value)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    use super::*;
+
+    /// Serves one request with `response` and returns the request it got.
+    fn stub_server(response: String) -> (String, std::thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 4096];
+            // Read the headers, then as much body as they announce
+            loop {
+                let read = stream.read(&mut buffer).unwrap();
+                request.extend_from_slice(&buffer[..read]);
+                let text = String::from_utf8_lossy(&request);
+                if let Some(headers_end) = text.find("\r\n\r\n") {
+                    let content_length = text[..headers_end]
+                        .lines()
+                        .find_map(|line| {
+                            line.to_lowercase()
+                                .strip_prefix("content-length:")
+                                .map(|length| length.trim().parse::<usize>().unwrap())
+                        })
+                        .unwrap_or(0);
+                    if request.len() >= headers_end + 4 + content_length {
+                        break;
+                    }
+                }
+                if read == 0 {
+                    break;
+                }
+            }
+            stream.write_all(response.as_bytes()).unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        (base_url, server)
+    }
+
+    fn http_response(status: &str, content_type: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {}\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+            status,
+            content_type,
+            body.len(),
+            body
+        )
+    }
+
+    #[tokio::test]
+    async fn openai_completions_are_read_from_the_first_choice() {
+        let body =
+            r#"{"choices":[{"message":{"role":"assistant","content":"{\"title\":\"Budget\"}"}}]}"#;
+        let (base_url, server) = stub_server(http_response("200 OK", "application/json", body));
+        let backend = OpenAiBackend::new(
+            format!("{}/v1/", base_url),
+            Some("secret".to_string()),
+            "local".to_string(),
+        );
+
+        let response = backend
+            .complete("Title this", Some(&json!({ "type": "object" })))
+            .await
+            .unwrap();
+
+        assert_eq!(response, r#"{"title":"Budget"}"#);
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /v1/chat/completions "));
+        assert!(request
+            .to_lowercase()
+            .contains("authorization: bearer secret"));
+        assert!(request.contains(r#""response_format":{"type":"json_object"}"#));
+    }
+
+    #[tokio::test]
+    async fn openai_errors_are_reported() {
+        let (base_url, server) = stub_server(http_response(
+            "401 Unauthorized",
+            "application/json",
+            r#"{"error":"bad key"}"#,
+        ));
+        let backend = OpenAiBackend::new(base_url, None, "local".to_string());
+
+        let err = backend.complete("Hello", None).await.unwrap_err();
+
+        assert!(err.starts_with("Completion request failed"));
+        assert!(!server
+            .join()
+            .unwrap()
+            .to_lowercase()
+            .contains("authorization"));
+    }
+
+    #[tokio::test]
+    async fn openai_streams_are_passed_on_token_by_token() {
+        let body = [
+            r#"data: {"choices":[{"delta":{"role":"assistant"}}]}"#,
+            r#"data: {"choices":[{"delta":{"content":"Hel"}}]}"#,
+            r#"data: {"choices":[{"delta":{"content":"lo"}}]}"#,
+            "data: [DONE]",
+        ]
+        .map(|line| format!("{}\n\n", line))
+        .concat();
+        let (base_url, server) = stub_server(http_response("200 OK", "text/event-stream", &body));
+        let backend = OpenAiBackend::new(base_url, None, "local".to_string());
+        let tokens = Mutex::new(Vec::new());
+
+        let response = backend
+            .complete_streaming("Hello", &|token: &str| {
+                tokens.lock().unwrap().push(token.to_string())
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(response, "Hello");
+        assert_eq!(*tokens.lock().unwrap(), vec!["Hel", "lo"]);
+        assert!(server.join().unwrap().contains(r#""stream":true"#));
+    }
+
+    #[test]
+    fn api_keys_are_redacted() {
+        let settings = LlmSettings::OpenAi {
+            base_url: "http://localhost:8080/v1".to_string(),
+            api_key: Some("secret".to_string()),
+            model: "local".to_string(),
+        };
+
+        let json = serde_json::to_string(&settings.redacted()).unwrap();
+
+        assert!(!json.contains("secret"));
+        assert!(json.contains(REDACTED_API_KEY));
+    }
+
+    #[test]
+    fn stream_lines_carry_tokens_until_done() {
+        let lines = [
+            ": keep-alive",
+            "",
+            r#"data: {"choices":[{"delta":{"role":"assistant"}}]}"#,
+            r#"data: {"choices":[{"delta":{"content":"Hello"}}]}"#,
+            "data: [DONE]",
+        ];
+
+        let parsed: Vec<StreamLine> = lines
+            .iter()
+            .map(|line| parse_stream_line(line).unwrap())
+            .collect();
+
+        assert_eq!(
+            parsed,
+            vec![
+                StreamLine::Skip,
+                StreamLine::Skip,
+                StreamLine::Skip,
+                StreamLine::Token("Hello".to_string()),
+                StreamLine::Done,
+            ]
+        );
+        assert!(parse_stream_line("data: {not json").is_err());
+    }
+}
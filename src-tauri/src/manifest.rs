@@ -0,0 +1,292 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use entity::transcript_segment;
+use serde::{Deserialize, Serialize};
+
+use crate::transcribe::TrackMode;
+
+/// The journal of a recording, kept next to its chunks so that a recording
+/// interrupted by a crash can be finalized at the next start.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordingStatus {
+    Recording,
+    Stopped,
+    /// The app quit while recording, the recording was finalized from the
+    /// segments on disk.
+    Recovered,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Track {
+    Input,
+    Output,
+}
+
+impl Track {
+    /// The directory of the track's segments in the recording directory.
+    pub fn dir_name(self) -> &'static str {
+        match self {
+            Track::Input => "input",
+            Track::Output => "output",
+        }
+    }
+}
+
+/// A stretch of the conversation that was left out of the recording.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pause {
+    /// Where in the recorded audio the pause falls.
+    pub audio_ms: i64,
+    pub started_at: String,
+    /// `None` while the recording is still paused.
+    pub duration_ms: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordingManifest {
+    pub conversation_id: u32,
+    pub status: RecordingStatus,
+    pub started_at: String,
+    pub stopped_at: Option<String>,
+    pub input_device: String,
+    pub output_device: String,
+    /// Complete segments of the microphone track, in order.
+    pub input_segments: Vec<String>,
+    /// Complete segments of the system audio track, in order.
+    pub output_segments: Vec<String>,
+    #[serde(default)]
+    pub pauses: Vec<Pause>,
+    /// The track mode when the recording started, changing the setting
+    /// afterwards doesn't change how it is transcribed.
+    #[serde(default)]
+    pub track_mode: TrackMode,
+    /// When the first audio of the microphone track arrived, in ms after the
+    /// recording started. Devices don't start at the same moment.
+    #[serde(default)]
+    pub input_start_ms: Option<i64>,
+    /// When the first audio of the system audio track arrived.
+    #[serde(default)]
+    pub output_start_ms: Option<i64>,
+}
+
+impl RecordingManifest {
+    pub fn new(
+        conversation_id: u32,
+        input_device: String,
+        output_device: String,
+        track_mode: TrackMode,
+    ) -> Self {
+        RecordingManifest {
+            conversation_id,
+            status: RecordingStatus::Recording,
+            started_at: chrono::Utc::now().to_rfc3339(),
+            stopped_at: None,
+            input_device,
+            output_device,
+            input_segments: Vec::new(),
+            output_segments: Vec::new(),
+            pauses: Vec::new(),
+            track_mode,
+            input_start_ms: None,
+            output_start_ms: None,
+        }
+    }
+
+    pub fn load(recording_dir: &Path) -> Result<Option<Self>, String> {
+        let path = recording_dir.join(MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(&path)
+            .map_err(|err| format!("Failed to read file {}: {}", path.display(), err))?;
+        serde_json::from_str(&content)
+            .map(Some)
+            .map_err(|err| format!("Failed to parse file {}: {}", path.display(), err))
+    }
+
+    /// Writes the manifest to a temporary file first, so that a crash never
+    /// leaves half of it behind.
+    pub fn save(&self, recording_dir: &Path) -> Result<(), String> {
+        let path = recording_dir.join(MANIFEST_FILE);
+        let temporary_path = path.with_extension("json.tmp");
+        let json_string = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+
+        let mut file = File::create(&temporary_path).map_err(|err| {
+            format!(
+                "Failed to create file {}: {}",
+                temporary_path.display(),
+                err
+            )
+        })?;
+        file.write_all(json_string.as_bytes())
+            .and_then(|_| file.sync_all())
+            .map_err(|err| format!("Failed to write file {}: {}", temporary_path.display(), err))?;
+        std::fs::rename(&temporary_path, &path)
+            .map_err(|err| format!("Failed to write file {}: {}", path.display(), err))
+    }
+
+    pub fn segments_mut(&mut self, track: Track) -> &mut Vec<String> {
+        match track {
+            Track::Input => &mut self.input_segments,
+            Track::Output => &mut self.output_segments,
+        }
+    }
+
+    pub fn start_ms_mut(&mut self, track: Track) -> &mut Option<i64> {
+        match track {
+            Track::Input => &mut self.input_start_ms,
+            Track::Output => &mut self.output_start_ms,
+        }
+    }
+
+    /// How long the microphone and the system audio track, in that order,
+    /// started after the first of them. Joining the tracks delays each by
+    /// its own, which lines them up.
+    pub fn track_delays_ms(&self) -> [i64; 2] {
+        let input = self.input_start_ms.unwrap_or(0);
+        let output = self.output_start_ms.unwrap_or(0);
+        let first = input.min(output);
+        [input - first, output - first]
+    }
+
+    /// The track delays of the recording in `recording_dir`, none when it
+    /// has no manifest.
+    pub fn load_track_delays_ms(recording_dir: &Path) -> Result<[i64; 2], String> {
+        Ok(RecordingManifest::load(recording_dir)?
+            .map_or([0, 0], |manifest| manifest.track_delays_ms()))
+    }
+
+    /// Maps a position in the recorded audio to the time since the
+    /// recording started, counting the pauses up to it. Transcript segments
+    /// are stored with their times in the audio.
+    pub fn conversation_ms(&self, audio_ms: i64) -> i64 {
+        audio_ms + self.paused_ms(|pause_ms| pause_ms <= audio_ms)
+    }
+
+    fn paused_ms(&self, counted: impl Fn(i64) -> bool) -> i64 {
+        self.pauses
+            .iter()
+            .filter(|pause| counted(pause.audio_ms))
+            .filter_map(|pause| pause.duration_ms)
+            .sum()
+    }
+
+    /// Moves the times of transcript segments to the conversation. A pause
+    /// right where a segment ends comes after it.
+    pub fn to_conversation_times(&self, segments: &mut [transcript_segment::Model]) {
+        for segment in segments {
+            let end_ms = segment.end_ms;
+            segment.start_ms = self.conversation_ms(segment.start_ms);
+            segment.end_ms = end_ms + self.paused_ms(|pause_ms| pause_ms < end_ms);
+        }
+    }
+
+    /// Moves the times of the transcript segments of the recording in
+    /// `recording_dir` to the conversation, a recording without a manifest
+    /// has no pauses.
+    pub fn load_conversation_times(
+        recording_dir: &Path,
+        segments: &mut [transcript_segment::Model],
+    ) -> Result<(), String> {
+        if let Some(manifest) = RecordingManifest::load(recording_dir)? {
+            manifest.to_conversation_times(segments);
+        }
+        Ok(())
+    }
+
+    pub fn finish(&mut self, status: RecordingStatus) {
+        self.status = status;
+        self.stopped_at = Some(chrono::Utc::now().to_rfc3339());
+    }
+}
+
+/// A manifest that both tracks of a running recording update.
+#[derive(Clone)]
+pub struct Journal {
+    recording_dir: PathBuf,
+    manifest: Arc<Mutex<RecordingManifest>>,
+}
+
+impl Journal {
+    pub fn create(recording_dir: &Path, manifest: RecordingManifest) -> Result<Self, String> {
+        manifest.save(recording_dir)?;
+        Ok(Journal {
+            recording_dir: recording_dir.to_path_buf(),
+            manifest: Arc::new(Mutex::new(manifest)),
+        })
+    }
+
+    /// Changes the manifest and writes it out right away. When writing fails
+    /// the change is kept, and the next update writes it along.
+    pub fn update(&self, change: impl FnOnce(&mut RecordingManifest)) -> Result<(), String> {
+        let mut manifest = self
+            .manifest
+            .lock()
+            .map_err(|_| "Recording manifest is poisoned".to_string())?;
+        change(&mut manifest);
+        manifest.save(&self.recording_dir)
+    }
+
+    /// Reads from the manifest as it is now.
+    pub fn read<T>(&self, read: impl FnOnce(&RecordingManifest) -> T) -> Result<T, String> {
+        let manifest = self
+            .manifest
+            .lock()
+            .map_err(|_| "Recording manifest is poisoned".to_string())?;
+        Ok(read(&manifest))
+    }
+
+    pub fn add_segment(&self, track: Track, segment: &str) -> Result<(), String> {
+        self.update(|manifest| manifest.segments_mut(track).push(segment.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::segment;
+
+    #[test]
+    fn pauses_shift_the_time_after_them() {
+        let mut manifest =
+            RecordingManifest::new(1, String::new(), String::new(), TrackMode::Mixed);
+        for (audio_ms, duration_ms) in [(1_000, Some(500)), (3_000, Some(2_000)), (4_000, None)] {
+            manifest.pauses.push(Pause {
+                audio_ms,
+                started_at: String::new(),
+                duration_ms,
+            });
+        }
+
+        assert_eq!(manifest.conversation_ms(999), 999);
+        assert_eq!(manifest.conversation_ms(1_000), 1_500);
+        assert_eq!(manifest.conversation_ms(3_500), 5_500);
+        assert_eq!(manifest.conversation_ms(4_500), 6_500);
+    }
+
+    #[test]
+    fn segments_are_moved_past_the_pauses_before_them() {
+        let mut manifest =
+            RecordingManifest::new(1, String::new(), String::new(), TrackMode::Mixed);
+        manifest.pauses.push(Pause {
+            audio_ms: 2_000,
+            started_at: String::new(),
+            duration_ms: Some(60_000),
+        });
+        let mut segments = vec![
+            segment(1, 0, 2_000, 0, "Before the break."),
+            segment(2, 2_000, 3_000, 0, "After the break."),
+        ];
+
+        manifest.to_conversation_times(&mut segments);
+
+        assert_eq!((segments[0].start_ms, segments[0].end_ms), (0, 2_000));
+        assert_eq!((segments[1].start_ms, segments[1].end_ms), (62_000, 63_000));
+    }
+}
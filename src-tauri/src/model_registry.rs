@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use service::{sea_orm::DatabaseConnection, Mutation, Query};
+use sha2::{Digest, Sha256};
+use tauri::State;
+
+use crate::AppState;
+
+/// Setting key for the model used by conversations without their own choice.
+pub const ACTIVE_MODEL_SETTING: &str = "transcription_model";
+
+const REGISTRY_FILE: &str = "registry.json";
+
+/// Magic number at the start of whisper.cpp ggml model files.
+const GGML_MAGIC: u32 = 0x6767_6d6c;
+/// English-only whisper models have one token less than multilingual ones.
+const MULTILINGUAL_VOCAB_SIZE: i32 = 51_865;
+
+/// whisper.cpp models fine-tuned for tinydiarize. The model file doesn't
+/// tell, so they are known by name.
+const TDRZ_MODELS: &[&str] = &["ggml-small.en-tdrz.bin"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelInfo {
+    pub name: String,
+    pub path: PathBuf,
+    /// `ggml` or `gguf`.
+    pub format: String,
+    /// `.en` models only transcribe English.
+    pub multilingual: bool,
+    /// Whether the model emits tinydiarize speaker turns.
+    pub tdrz: bool,
+    pub size_bytes: u64,
+    pub sha256: Option<String>,
+    pub expected_sha256: Option<String>,
+    /// `None` until both checksums are known.
+    pub verified: Option<bool>,
+    /// The model that ships with the app rather than one in the models directory.
+    pub bundled: bool,
+}
+
+fn checksum_matches(sha256: &Option<String>, expected_sha256: &Option<String>) -> Option<bool> {
+    match (sha256, expected_sha256) {
+        (Some(actual), Some(expected)) => Some(actual.eq_ignore_ascii_case(expected)),
+        _ => None,
+    }
+}
+
+/// Whisper models available to the app: every ggml/gguf file in the app-data
+/// `models/` directory plus the bundled model. Metadata is kept in
+/// `models/registry.json` so checksums only have to be computed once.
+pub struct ModelRegistry {
+    models_dir: PathBuf,
+    bundled_model_path: PathBuf,
+}
+
+impl ModelRegistry {
+    pub fn new(models_dir: PathBuf, bundled_model_path: PathBuf) -> Self {
+        ModelRegistry {
+            models_dir,
+            bundled_model_path,
+        }
+    }
+
+    pub fn list(&self) -> Result<Vec<ModelInfo>, String> {
+        std::fs::create_dir_all(&self.models_dir).map_err(|e| e.to_string())?;
+
+        let recorded = self.load()?;
+        let mut models = Vec::new();
+
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(&self.models_dir)
+            .map_err(|e| e.to_string())?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file() && model_format(path).is_some())
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            models.push(self.describe(&path, false, &recorded)?);
+        }
+
+        if self.bundled_model_path.exists() {
+            models.push(self.describe(&self.bundled_model_path, true, &recorded)?);
+        }
+
+        let changed = models.len() != recorded.len()
+            || models
+                .iter()
+                .any(|model| recorded.get(&model.name) != Some(model));
+        if changed {
+            self.save(&models)?;
+        }
+        Ok(models)
+    }
+
+    pub fn find(&self, name: &str) -> Result<ModelInfo, String> {
+        self.list()?
+            .into_iter()
+            .find(|model| model.name == name)
+            .ok_or_else(|| format!("Model {} not found", name))
+    }
+
+    /// Hashes the model file and records the checksum. When `expected_sha256`
+    /// is given it is stored and compared against on every later listing.
+    pub fn verify(&self, name: &str, expected_sha256: Option<String>) -> Result<ModelInfo, String> {
+        let mut model = self.find(name)?;
+
+        info!("Computing checksum of {}", model.path.display());
+        model.sha256 = Some(sha256_file(&model.path)?);
+        if let Some(expected) = expected_sha256 {
+            model.expected_sha256 = Some(expected.trim().to_lowercase());
+        }
+        model.verified = checksum_matches(&model.sha256, &model.expected_sha256);
+
+        let mut models = self.list()?;
+        if let Some(entry) = models.iter_mut().find(|entry| entry.name == model.name) {
+            *entry = model.clone();
+        }
+        self.save(&models)?;
+
+        Ok(model)
+    }
+
+    fn describe(
+        &self,
+        path: &Path,
+        bundled: bool,
+        recorded: &HashMap<String, ModelInfo>,
+    ) -> Result<ModelInfo, String> {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .ok_or("Invalid model path".to_string())?;
+        let size_bytes = std::fs::metadata(path).map_err(|e| e.to_string())?.len();
+
+        // What was recorded is only trusted as long as the file hasn't changed size
+        let unchanged = recorded
+            .get(&name)
+            .filter(|previous| previous.size_bytes == size_bytes);
+        let (sha256, multilingual) = match unchanged {
+            Some(previous) => (previous.sha256.clone(), previous.multilingual),
+            None => (None, read_multilingual(path)?),
+        };
+        let expected_sha256 = recorded
+            .get(&name)
+            .and_then(|previous| previous.expected_sha256.clone());
+
+        Ok(ModelInfo {
+            format: model_format(path).unwrap_or("ggml").to_string(),
+            multilingual,
+            tdrz: TDRZ_MODELS.contains(&name.as_str()),
+            name,
+            path: path.to_path_buf(),
+            size_bytes,
+            verified: checksum_matches(&sha256, &expected_sha256),
+            sha256,
+            expected_sha256,
+            bundled,
+        })
+    }
+
+    fn load(&self) -> Result<HashMap<String, ModelInfo>, String> {
+        let path = self.models_dir.join(REGISTRY_FILE);
+        if !path.exists() {
+            return Ok(HashMap::new());
+        }
+
+        let content = std::fs::read_to_string(&path)
+            .map_err(|err| format!("Failed to read file {}: {}", path.display(), err))?;
+        let models: Vec<ModelInfo> = serde_json::from_str(&content)
+            .map_err(|err| format!("Failed to parse JSON in file {}: {}", path.display(), err))?;
+
+        Ok(models
+            .into_iter()
+            .map(|model| (model.name.clone(), model))
+            .collect())
+    }
+
+    fn save(&self, models: &[ModelInfo]) -> Result<(), String> {
+        let path = self.models_dir.join(REGISTRY_FILE);
+        let json_string = serde_json::to_string_pretty(models).map_err(|e| e.to_string())?;
+        std::fs::write(&path, json_string)
+            .map_err(|err| format!("Failed to write file {}: {}", path.display(), err))
+    }
+
+    /// Picks the model for a conversation: its own choice, then the global
+    /// setting, then the bundled model.
+    pub async fn resolve_model(
+        &self,
+        db: &DatabaseConnection,
+        conversation_id: Option<i32>,
+    ) -> Result<ModelInfo, String> {
+        let mut name = None;
+        if let Some(conversation_id) = conversation_id {
+            name = Query::find_conversation_by_id(db, conversation_id)
+                .await
+                .map_err(|e| e.to_string())?
+                .and_then(|conversation| conversation.transcription_model);
+        }
+        if name.is_none() {
+            name = Query::find_setting(db, ACTIVE_MODEL_SETTING)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+
+        match name {
+            Some(name) => self.find(&name),
+            None => self
+                .list()?
+                .into_iter()
+                .find(|model| model.bundled)
+                .ok_or_else(|| {
+                    format!(
+                        "Bundled model {} not found",
+                        self.bundled_model_path.display()
+                    )
+                }),
+        }
+    }
+}
+
+fn model_format(path: &Path) -> Option<&'static str> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("bin") => Some("ggml"),
+        Some("gguf") => Some("gguf"),
+        _ => None,
+    }
+}
+
+/// Whether the model transcribes other languages than English, from the
+/// vocabulary size in its header. Models without a ggml header are assumed
+/// to be multilingual, as whisper.cpp does.
+fn read_multilingual(path: &Path) -> Result<bool, String> {
+    let mut file = File::open(path)
+        .map_err(|err| format!("Failed to open file {}: {}", path.display(), err))?;
+    // The magic number followed by the vocabulary size
+    let mut header = [0u8; 8];
+    if file.read_exact(&mut header).is_err() {
+        return Ok(true);
+    }
+
+    let magic = u32::from_le_bytes(header[0..4].try_into().unwrap());
+    let vocab_size = i32::from_le_bytes(header[4..8].try_into().unwrap());
+    Ok(magic != GGML_MAGIC || vocab_size >= MULTILINGUAL_VOCAB_SIZE)
+}
+
+fn sha256_file(path: &Path) -> Result<String, String> {
+    let file = File::open(path)
+        .map_err(|err| format!("Failed to open file {}: {}", path.display(), err))?;
+    let mut reader = BufReader::new(file);
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 1024 * 1024];
+    loop {
+        let read = reader.read(&mut buffer).map_err(|e| e.to_string())?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[tauri::command]
+pub async fn list_models(
+    registry: State<'_, Arc<ModelRegistry>>,
+) -> Result<Vec<ModelInfo>, String> {
+    registry.list()
+}
+
+#[tauri::command]
+pub async fn verify_model(
+    registry: State<'_, Arc<ModelRegistry>>,
+    name: String,
+    expected_sha256: Option<String>,
+) -> Result<ModelInfo, String> {
+    let registry = registry.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || registry.verify(&name, expected_sha256))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Sets the model used by every conversation that has no model of its own.
+#[tauri::command]
+pub async fn set_active_model(
+    state: State<'_, AppState>,
+    registry: State<'_, Arc<ModelRegistry>>,
+    name: String,
+) -> Result<ModelInfo, String> {
+    let model = registry.find(&name)?;
+    Mutation::set_setting(&state.db, ACTIVE_MODEL_SETTING, model.name.clone())
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(model)
+}
+
+/// Sets the model for one conversation, or clears it with `None`.
+#[tauri::command]
+pub async fn set_conversation_model(
+    state: State<'_, AppState>,
+    registry: State<'_, Arc<ModelRegistry>>,
+    conversation_id: i32,
+    name: Option<String>,
+) -> Result<(), String> {
+    if let Some(name) = &name {
+        registry.find(name)?;
+    }
+    Mutation::update_conversation_transcription_model(&state.db, conversation_id, name)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
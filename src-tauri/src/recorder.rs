@@ -1,27 +1,34 @@
-use coreaudio_sys::AudioObjectID;
-use log::info;
+use entity::action_item::{self, ActionItemStatus};
+use entity::job::JobKind;
+use entity::summary;
+use log::{error, info};
 use serde::{Deserialize, Serialize};
+use service::{sea_orm::DatabaseConnection, Mutation, Query};
 use std::fs::File;
-use std::io::{self, ErrorKind, Write};
+use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
 use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
 };
-use std::time::Duration;
 use tauri::async_runtime::Mutex;
-use tauri::State;
-use tokio::process::Command;
+use tauri::{Manager, State};
 
 // Removed unused imports
 // use mac_notification_sys::{get_bundle_identifier_or_default, send_notification, set_application};
 // use crate::commands::conversation;
 // use crate::summarize::{generate_action_items, generate_title, summarize};
+use crate::audio::pipeline::{self, segment_name, SEGMENT_LIST};
+use crate::audio::AudioCaptureBackend;
+use crate::jobs::{recording_dir, JobQueue};
+use crate::live_transcribe::start_live_transcription;
+use crate::llm::{LlmSettings, SummaryBackends};
+use crate::manifest::{Journal, RecordingManifest, RecordingStatus, Track};
 use crate::media::MediaRecorder;
-use crate::summarize::summarize_and_write;
-use crate::transcribe::{load_transcription, transcribe_wav_file_and_write};
-use crate::utils::ffmpeg_path_as_str;
-use crate::DeviceState;
+use crate::speakers::SpeakerNames;
+use crate::summarize::{extract_action_items, summarize_transcript, SummaryPrompt};
+use crate::transcribe::TrackMode;
+use crate::{AppState, DeviceState};
 
 pub struct RecordingState {
     pub media_process: Option<MediaRecorder>,
@@ -40,13 +47,14 @@ pub struct RecordingOptions {
 }
 
 pub async fn _start_recording(
+    handle: tauri::AppHandle,
     state: State<'_, Arc<tauri::async_runtime::Mutex<RecordingState>>>,
     device_state: State<'_, Arc<tauri::async_runtime::Mutex<DeviceState>>>,
     options: RecordingOptions,
     conversation_id: u32,
 ) -> Result<(), String> {
     let mut state_guard = state.lock().await;
-    let device_state_guard = device_state.lock().await;
+    let mut device_state_guard = device_state.lock().await;
     // send_notification("Platy", None, "Starting recording", None).unwrap();
 
     let shutdown_flag = Arc::new(AtomicBool::new(false));
@@ -67,16 +75,49 @@ pub async fn _start_recording(
     let audio_input_chunks_dir = output_dir.join("input");
     let audio_output_chunks_dir = output_dir.join("output");
 
+    // Recovery finalizes interrupted recordings at startup, one still marked
+    // as recording would be lost
+    let manifest = RecordingManifest::load(&output_dir)?;
+    if manifest
+        .as_ref()
+        .is_some_and(|manifest| manifest.status == RecordingStatus::Recording)
+    {
+        return Err(format!(
+            "Conversation {} has an unfinished recording",
+            conversation_id
+        ));
+    }
+    // Starting over would delete the audio already recorded. Recordings from
+    // before the manifest only have their mix.
+    if manifest.is_some() || output_dir.join("combined.wav").exists() {
+        return Err(format!(
+            "Conversation {} already has a recording",
+            conversation_id
+        ));
+    }
+
     clean_and_create_dir(&output_dir)?;
     clean_and_create_dir(&audio_input_chunks_dir)?;
     clean_and_create_dir(&audio_output_chunks_dir)?;
 
+    let app_state: State<AppState> = handle.state();
+    let track_mode = TrackMode::load(&app_state.db).await?;
+    let journal = Journal::create(
+        &output_dir,
+        RecordingManifest::new(
+            conversation_id,
+            options.audio_input_name.clone(),
+            options.audio_output_name.clone(),
+            track_mode,
+        ),
+    )?;
+
     let media_recording_preparation = prepare_media_recording(
         &options,
         &audio_input_chunks_dir,
         &audio_output_chunks_dir,
-        device_state_guard.input_device_id,
-        device_state_guard.aggregate_device_id,
+        device_state_guard.backend.as_mut(),
+        journal,
     );
     let media_recording_result = media_recording_preparation
         .await
@@ -87,155 +128,92 @@ pub async fn _start_recording(
     state_guard.shutdown_flag = shutdown_flag.clone();
     state_guard.audio_uploading_finished = Arc::new(AtomicBool::new(false));
 
-    // let audio_upload = start_transcription_loop(
-    //     audio_input_chunks_dir,
-    //     audio_output_chunks_dir,
-    //     shutdown_flag.clone(),
-    //     state_guard.audio_uploading_finished.clone(),
-    // );
-
     drop(state_guard);
 
-    info!("Starting upload loops...");
+    info!("Starting live transcription...");
+
+    start_live_transcription(handle, conversation_id, output_dir, shutdown_flag);
 
-    // match tokio::try_join!(audio_upload) {
-    //     Ok(_) => {
-    //         println!("Both upload loops completed successfully.");
-    //     }
-    //     Err(e) => {
-    //         eprintln!("An error occurred: {}", e);
-    //     }
-    // }
     Ok(())
 }
 
 #[tauri::command]
 pub async fn start_recording(
+    handle: tauri::AppHandle,
     state: State<'_, Arc<tauri::async_runtime::Mutex<RecordingState>>>,
     device_state: State<'_, Arc<tauri::async_runtime::Mutex<DeviceState>>>,
     options: RecordingOptions,
     conversation_id: u32,
 ) -> Result<(), String> {
-    _start_recording(state, device_state, options, conversation_id).await
+    _start_recording(handle, state, device_state, options, conversation_id).await
 }
-use tokio::io::AsyncBufReadExt;
-
-async fn concat_segments(
-    audio_chunks_dir: &PathBuf,
-) -> Result<tokio::process::Child, std::io::Error> {
-    let ffmpeg_binary_path_str = ffmpeg_path_as_str().unwrap().to_owned();
-
-    let segment_list_path = audio_chunks_dir.join("segment_list.txt");
-
-    // Read each line (segment file path) from the segment list file
-    let segment_files: Vec<String> = match std::fs::read_to_string(&segment_list_path) {
-        Ok(content) => Some(
-            content
-                .lines()
-                .map(|s| s.trim().to_string())
-                .collect::<Vec<String>>(),
-        ),
-        Err(e) => {
-            info!("Failed to read segment list: {}", e);
-            None
-        }
-    }
-    .expect("Failed to read segment list. This should never happen. Please report this bug.");
 
-    // Ensure there are segments to combine
-    if segment_files.is_empty() {
-        info!("No segments found to combine.");
-    }
-
-    let concat_file_path = audio_chunks_dir.join("concat.txt").clone();
-    let combined_output_file_path = audio_chunks_dir.join("combined.wav");
-
-    write_concat_file(&concat_file_path, &segment_files).expect("error writing concat file");
-
-    let args = vec![
-        "-f",
-        "concat",
-        "-safe",
-        "0",
-        "-i",
-        concat_file_path.to_str().unwrap(),
-        "-c",
-        "copy",
-        combined_output_file_path.to_str().unwrap(),
-    ];
-
-    // Print the generated args for debugging
-    info!("FFmpeg args: {:?}", args);
-
-    let mut process = Command::new(ffmpeg_binary_path_str).args(args).spawn()?;
-
-    if let Some(process_stderr) = process.stderr.take() {
-        tokio::spawn(async move {
-            use tokio::io::BufReader;
-
-            let mut process_reader = BufReader::new(process_stderr).lines();
-            while let Ok(Some(line)) = process_reader.next_line().await {
-                info!("FFmpeg process STDERR: {}", line);
-            }
-        });
-    }
+/// Stops capturing without ending the conversation, see `resume_recording`.
+#[tauri::command]
+pub async fn pause_recording(state: State<'_, Arc<Mutex<RecordingState>>>) -> Result<(), String> {
+    let mut guard = state.lock().await;
+    guard
+        .media_process
+        .as_mut()
+        .ok_or("No recording is running")?
+        .pause_media_recording()
+}
 
-    process.wait().await?;
-    Ok(process)
+#[tauri::command]
+pub async fn resume_recording(state: State<'_, Arc<Mutex<RecordingState>>>) -> Result<(), String> {
+    let mut guard = state.lock().await;
+    guard
+        .media_process
+        .as_mut()
+        .ok_or("No recording is running")?
+        .resume_media_recording()
 }
 
-//ffmpeg -i stream1_combined.wav -i stream2_combined.wav -filter_complex "[0:a][1:a]amerge=inputs=2,pan=mono|c0=.5*c0+.5*c1[aout]" -map "[aout]" -c:a pcm_s16le output_mono.wav
-async fn combine_segments(
-    audio_chunks_dir: &PathBuf,
-) -> Result<tokio::process::Child, std::io::Error> {
-    let ffmpeg_binary_path_str = ffmpeg_path_as_str().unwrap().to_owned();
+/// Joins the segments of one track into its `combined.wav`.
+pub async fn concat_segments(audio_chunks_dir: &Path) -> Result<(), String> {
+    let audio_chunks_dir = audio_chunks_dir.to_path_buf();
+    tauri::async_runtime::spawn_blocking(move || pipeline::concat_segments(&audio_chunks_dir))
+        .await
+        .map_err(|e| e.to_string())?
+}
 
+/// Mixes both tracks into the mono `combined.wav` that gets transcribed.
+pub async fn combine_segments(audio_chunks_dir: &Path) -> Result<(), String> {
     let input_concat_file = audio_chunks_dir.join("input").join("combined.wav");
     let output_concat_file = audio_chunks_dir.join("output").join("combined.wav");
     let combined_output_file_path = audio_chunks_dir.join("combined.wav");
-
-    let args = vec![
-        "-i",
-        input_concat_file.to_str().unwrap(),
-        "-i",
-        output_concat_file.to_str().unwrap(),
-        "-filter_complex",
-        "[0:a][1:a]amerge=inputs=2,pan=mono|c0=.5*c0+.5*c1[aout]",
-        "-map",
-        "[aout]",
-        "-c:a",
-        "pcm_s16le",
-        combined_output_file_path.to_str().unwrap(),
-    ];
-
-    // Print the generated args for debugging
-    info!("FFmpeg args: {:?}", args);
-
-    let mut process = Command::new(ffmpeg_binary_path_str).args(args).spawn()?;
-
-    if let Some(process_stderr) = process.stderr.take() {
-        tokio::spawn(async move {
-            use tokio::io::BufReader;
-
-            let mut process_reader = BufReader::new(process_stderr).lines();
-            while let Ok(Some(line)) = process_reader.next_line().await {
-                info!("FFmpeg process STDERR: {}", line);
-            }
-        });
-    }
-
-    process.wait().await?;
-    Ok(process)
+    let delays_ms = RecordingManifest::load_track_delays_ms(audio_chunks_dir)?;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        pipeline::mix_tracks(
+            &input_concat_file,
+            &output_concat_file,
+            delays_ms,
+            &combined_output_file_path,
+        )
+    })
+    .await
+    .map_err(|e| e.to_string())?
 }
 
-fn write_concat_file(concat_file_path: &PathBuf, segment_files: &Vec<String>) -> io::Result<()> {
-    let mut output_file = File::create(concat_file_path)?;
-    for segment_file in segment_files {
-        output_file
-            .write_all(format!("file '{}'\n", segment_file).as_bytes())
-            .expect("error writing file");
-    }
-    Ok(())
+/// Keeps both tracks for playback in one file, the microphone on the left
+/// channel and system audio on the right.
+pub async fn stereo_segments(audio_chunks_dir: &Path) -> Result<(), String> {
+    let input_concat_file = audio_chunks_dir.join("input").join("combined.wav");
+    let output_concat_file = audio_chunks_dir.join("output").join("combined.wav");
+    let stereo_output_file_path = audio_chunks_dir.join("stereo.wav");
+    let delays_ms = RecordingManifest::load_track_delays_ms(audio_chunks_dir)?;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        pipeline::stereo_tracks(
+            &input_concat_file,
+            &output_concat_file,
+            delays_ms,
+            &stereo_output_file_path,
+        )
+    })
+    .await
+    .map_err(|e| e.to_string())?
 }
 
 pub async fn _stop_recording(
@@ -277,45 +255,174 @@ pub async fn _stop_recording(
     //     tokio::time::sleep(Duration::from_millis(50)).await;
     // }
 
-    let data_dir = guard.data_dir.clone();
-    let recording_dir = data_dir
-        .expect("no data directory")
-        .join("chunks/audio")
-        .join(conversation_id.to_string());
-    let input_dir = recording_dir.join("input");
-    let output_dir = recording_dir.join("output");
-    concat_segments(&input_dir)
-        .await
-        .map_err(|e| e.to_string())?;
-    concat_segments(&output_dir)
-        .await
-        .map_err(|e| e.to_string())?;
-    combine_segments(&recording_dir)
+    drop(guard);
+
+    // Concatenation, mixing, transcription and summarization run on the job
+    // queue so they survive an app restart.
+    let app_state: State<AppState> = handle.state();
+    let job_queue: State<JobQueue> = handle.state();
+    job_queue
+        .enqueue(&app_state.db, conversation_id as i32, JobKind::Concat)
+        .await?;
+
+    info!("All recordings stopped, post-processing queued.");
+
+    Ok(())
+}
+
+/// Summarizes the stored transcript of a conversation and extracts its
+/// action items. The action items replace the open ones and the summary
+/// version is saved last, so a retried job doesn't leave a duplicate version.
+pub async fn summarize_conversation(
+    handle: tauri::AppHandle,
+    conversation_id: i32,
+) -> Result<(), String> {
+    let app_state: State<AppState> = handle.state();
+    let mut segments =
+        Query::find_transcript_segments_by_conversation_id(&app_state.db, conversation_id)
+            .await
+            .map_err(|e| e.to_string())?;
+    if segments.is_empty() {
+        return Err("Conversation has no transcript".to_string());
+    }
+    // Prompts, citations and action items follow the conversation, pauses included
+    RecordingManifest::load_conversation_times(
+        &recording_dir(&handle, conversation_id)?,
+        &mut segments,
+    )?;
+    let conversation = Query::find_conversation_by_id(&app_state.db, conversation_id)
         .await
-        .map_err(|e| e.to_string())?;
-    tokio::time::sleep(Duration::from_millis(50)).await;
+        .map_err(|e| e.to_string())?
+        .ok_or("Conversation not found".to_string())?;
+    let date = conversation.date();
+    let speakers = SpeakerNames::load(&app_state.db, conversation_id).await?;
+    let backends: State<SummaryBackends> = handle.state();
+    let backend = backends.current(&app_state.db).await?;
+    let action_items = extract_action_items(backend.as_ref(), &segments, &speakers, date).await?;
+    Mutation::create_action_items(
+        &app_state.db,
+        conversation_id,
+        action_items
+            .into_iter()
+            .map(|item| action_item::Model {
+                id: 0,
+                conversation_id,
+                title: item.title,
+                assignee: item.assignee,
+                due_date: item.due_date,
+                status: ActionItemStatus::Open,
+                source_ms: item.source_ms,
+                created_at: String::new(),
+                updated_at: String::new(),
+            })
+            .collect(),
+    )
+    .await
+    .map_err(|e| e.to_string())?;
 
-    info!("combined segments..");
+    create_summary_version(handle, conversation_id, SummaryVersionOptions::default()).await?;
+    Ok(())
+}
 
-    let combined_audio_file = recording_dir.join("combined.wav");
-    let transcription_output_file = recording_dir.join("transcription.json");
-    let summary_output_file = recording_dir.join("summary.json");
-    transcribe_wav_file_and_write(handle, &combined_audio_file, &transcription_output_file)
-        .map_err(|e| e.to_string())?;
-    let transcription = load_transcription(transcription_output_file)
+/// How a summary version is generated, the defaults from settings if unset.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SummaryVersionOptions {
+    pub template_id: Option<i32>,
+    /// Another model of the configured backend.
+    pub model: Option<String>,
+}
+
+/// Summarizes the stored transcript into a new summary version with the given
+/// template, or the default one, and optionally another model than the one in
+/// settings. Earlier versions are kept.
+pub async fn create_summary_version(
+    handle: tauri::AppHandle,
+    conversation_id: i32,
+    options: SummaryVersionOptions,
+) -> Result<summary::Model, String> {
+    let SummaryVersionOptions { template_id, model } = options;
+    let app_state: State<AppState> = handle.state();
+    let template = match template_id {
+        Some(template_id) => Some(
+            Query::find_summary_template_by_id(&app_state.db, template_id)
+                .await
+                .map_err(|e| e.to_string())?
+                .ok_or(format!("Summary template {} not found", template_id))?,
+        ),
+        None => Query::find_default_summary_template(&app_state.db)
+            .await
+            .map_err(|e| e.to_string())?,
+    };
+    let prompt = match &template {
+        Some(template) => SummaryPrompt::from_template(template)?,
+        None => SummaryPrompt::default(),
+    };
+
+    let conversation = Query::find_conversation_by_id(&app_state.db, conversation_id)
         .await
-        .expect("Failed to load transcription");
-    summarize_and_write(
-        transcription.full_text.join(" CHANGE_SPEAKER_TOKEN "),
-        &summary_output_file,
+        .map_err(|e| e.to_string())?
+        .ok_or("Conversation not found".to_string())?;
+    let date = conversation.date();
+    let mut segments =
+        Query::find_transcript_segments_by_conversation_id(&app_state.db, conversation_id)
+            .await
+            .map_err(|e| e.to_string())?;
+    if segments.is_empty() {
+        return Err("Conversation has no transcript".to_string());
+    }
+    // Prompts, citations and action items follow the conversation, pauses included
+    RecordingManifest::load_conversation_times(
+        &recording_dir(&handle, conversation_id)?,
+        &mut segments,
+    )?;
+    let speakers = SpeakerNames::load(&app_state.db, conversation_id).await?;
+
+    let mut settings = LlmSettings::load(&app_state.db).await?;
+    if let Some(model) = model {
+        settings = settings.with_model(model);
+    }
+    let backends: State<SummaryBackends> = handle.state();
+    let backend = backends.build(settings.clone());
+
+    let (content, chunks) =
+        summarize_transcript(backend.as_ref(), &segments, &speakers, &prompt, date).await?;
+    let chunks = serde_json::to_string(&chunks).map_err(|e| e.to_string())?;
+    let summary = Mutation::create_summary(
+        &app_state.db,
+        summary::Model {
+            id: 0,
+            conversation_id,
+            content,
+            chunks: Some(chunks),
+            template_id: prompt.template_id,
+            prompt: Some(prompt.prompt),
+            model: Some(settings.model_name()),
+            pinned: false,
+            created_at: String::new(),
+        },
     )
     .await
-    .expect("Couldn't generate summary");
+    .map_err(|e| e.to_string())?;
 
-    // let action_items = generate_action_items(&summary);
-    // let title = generate_title(&summary);
-    info!("All recordings and uploads stopped.");
+    // A pinned version stays the one that is searched
+    index_current_summary(&app_state.db, conversation_id).await?;
+
+    Ok(summary)
+}
 
+/// Points the search index at the summary shown for the conversation.
+pub async fn index_current_summary(
+    db: &DatabaseConnection,
+    conversation_id: i32,
+) -> Result<(), String> {
+    let summary = Query::find_current_summary_by_conversation_id(db, conversation_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    if let Some(summary) = summary {
+        Mutation::index_conversation_summary(db, conversation_id, &summary.content)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
     Ok(())
 }
 
@@ -370,8 +477,8 @@ async fn prepare_media_recording(
     options: &RecordingOptions,
     audio_input_chunks_dir: &Path,
     audio_output_chunks_dir: &Path,
-    audio_input_id: Option<AudioObjectID>,
-    output_device_id: Option<AudioObjectID>,
+    backend: &mut dyn AudioCaptureBackend,
+    journal: Journal,
 ) -> Result<MediaRecorder, String> {
     let mut media_recorder = MediaRecorder::new();
     media_recorder
@@ -379,9 +486,122 @@ async fn prepare_media_recording(
             options.clone(),
             audio_input_chunks_dir,
             audio_output_chunks_dir,
-            audio_input_id,
-            output_device_id,
+            backend,
+            journal,
         )
         .await?;
     Ok(media_recorder)
 }
+
+/// Finalizes recordings that were still running when the app quit from the
+/// segments already on disk, and queues their post-processing.
+pub async fn recover_interrupted_recordings(handle: &tauri::AppHandle) -> Result<(), String> {
+    let recordings_dir = handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| e.to_string())?
+        .join("chunks/audio");
+    let Ok(entries) = std::fs::read_dir(&recordings_dir) else {
+        return Ok(());
+    };
+
+    for entry in entries.flatten() {
+        let recording_dir = entry.path();
+        if let Err(err) = recover_recording(handle, &recording_dir, false).await {
+            error!(
+                "Failed to recover recording {}: {}",
+                recording_dir.display(),
+                err
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Finalizes an interrupted recording. With `force`, a partial segment that
+/// can't be repaired is left out instead of failing the recovery.
+async fn recover_recording(
+    handle: &tauri::AppHandle,
+    recording_dir: &Path,
+    force: bool,
+) -> Result<(), String> {
+    let Some(mut manifest) = RecordingManifest::load(recording_dir)? else {
+        return Ok(());
+    };
+    if manifest.status != RecordingStatus::Recording {
+        return Ok(());
+    }
+    info!(
+        "Recovering the recording of conversation {} started at {}",
+        manifest.conversation_id, manifest.started_at
+    );
+
+    for track in [Track::Input, Track::Output] {
+        let track_dir = recording_dir.join(track.dir_name());
+        let segments = manifest.segments_mut(track);
+
+        // The segment that was being written when the app quit
+        let partial_segment = segment_name(segments.len());
+        let partial_segment_path = track_dir.join(&partial_segment);
+        if partial_segment_path.exists() {
+            match pipeline::repair_segment(&partial_segment_path) {
+                Ok(true) => segments.push(partial_segment),
+                Ok(false) => {}
+                Err(err) if force => {
+                    error!("Leaving out {}: {}", partial_segment_path.display(), err)
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        // Segments are journaled before they are listed, the list may be
+        // one behind
+        let segment_list: String = segments
+            .iter()
+            .map(|segment| format!("{}\n", segment))
+            .collect();
+        std::fs::write(track_dir.join(SEGMENT_LIST), segment_list)
+            .map_err(|err| format!("Failed to write segment list: {}", err))?;
+    }
+
+    let app_state: State<AppState> = handle.state();
+    let conversation_id = manifest.conversation_id as i32;
+    let conversation = Query::find_conversation_by_id(&app_state.db, conversation_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    if conversation.is_some() {
+        Mutation::mark_conversation_recovered(&app_state.db, conversation_id)
+            .await
+            .map_err(|e| e.to_string())?;
+        let job_queue: State<JobQueue> = handle.state();
+        job_queue
+            .enqueue(&app_state.db, conversation_id, JobKind::Concat)
+            .await?;
+    }
+
+    manifest.finish(RecordingStatus::Recovered);
+    manifest.save(recording_dir)
+}
+
+/// Finalizes a recording that recovery at startup failed on, which would
+/// otherwise stay unfinished. `delete_recording_data` discards it instead.
+#[tauri::command]
+pub async fn finalize_recording(
+    handle: tauri::AppHandle,
+    state: State<'_, Arc<Mutex<RecordingState>>>,
+    conversation_id: u32,
+) -> Result<(), String> {
+    let guard = state.lock().await;
+    if guard.media_process.is_some() && guard.conversation_id == Some(conversation_id) {
+        return Err("The recording is still running".to_string());
+    }
+    let recording_dir = guard
+        .data_dir
+        .as_ref()
+        .ok_or("Data directory is not set in the recording state".to_string())?
+        .join("chunks/audio")
+        .join(conversation_id.to_string());
+    drop(guard);
+
+    recover_recording(&handle, &recording_dir, true).await
+}
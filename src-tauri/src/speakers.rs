@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+
+use entity::job::JobKind;
+use entity::speaker;
+use service::{sea_orm::DatabaseConnection, Mutation, Query};
+use tauri::State;
+
+use crate::jobs::JobQueue;
+use crate::recorder::SummaryVersionOptions;
+use crate::subtitles::speaker_label;
+use crate::AppState;
+
+/// Display names of a conversation's speakers, by the `speaker` of their
+/// transcript segments. Speakers without a row keep their default label.
+#[derive(Debug, Clone, Default)]
+pub struct SpeakerNames(HashMap<i32, String>);
+
+impl SpeakerNames {
+    pub fn from_speakers(speakers: &[speaker::Model]) -> Self {
+        SpeakerNames(
+            speakers
+                .iter()
+                .map(|speaker| (speaker.speaker_index, speaker.name.clone()))
+                .collect(),
+        )
+    }
+
+    pub async fn load(db: &DatabaseConnection, conversation_id: i32) -> Result<Self, String> {
+        let speakers = Query::find_speakers_by_conversation_id(db, conversation_id)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(SpeakerNames::from_speakers(&speakers))
+    }
+
+    pub fn label(&self, speaker: i32) -> String {
+        self.0
+            .get(&speaker)
+            .cloned()
+            .unwrap_or_else(|| speaker_label(speaker))
+    }
+}
+
+#[tauri::command]
+pub async fn get_speakers(
+    state: State<'_, AppState>,
+    conversation_id: i32,
+) -> Result<Vec<speaker::Model>, String> {
+    Query::find_speakers_by_conversation_id(&state.db, conversation_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Renames a speaker. Transcripts and exports pick the name up directly, the
+/// embedded chunks are embedded again and a current summary that mentions the
+/// old name is regenerated with its template.
+#[tauri::command]
+pub async fn rename_speaker(
+    state: State<'_, AppState>,
+    queue: State<'_, JobQueue>,
+    speaker_id: i32,
+    name: String,
+) -> Result<speaker::Model, String> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err("Speaker name cannot be empty".to_string());
+    }
+
+    let (speaker, old_name) = Mutation::rename_speaker(&state.db, speaker_id, name)
+        .await
+        .map_err(|e| e.to_string())?;
+    if old_name == speaker.name {
+        return Ok(speaker);
+    }
+
+    let summary =
+        Query::find_current_summary_by_conversation_id(&state.db, speaker.conversation_id)
+            .await
+            .map_err(|e| e.to_string())?;
+    if let Some(summary) = summary.filter(|summary| summary.content.contains(&old_name)) {
+        let params = serde_json::to_string(&SummaryVersionOptions {
+            template_id: summary.template_id,
+            model: None,
+        })
+        .map_err(|e| e.to_string())?;
+        queue
+            .enqueue_with_params(
+                &state.db,
+                speaker.conversation_id,
+                JobKind::Resummarize,
+                Some(params),
+            )
+            .await?;
+    }
+    queue
+        .enqueue(&state.db, speaker.conversation_id, JobKind::Embed)
+        .await?;
+
+    Ok(speaker)
+}
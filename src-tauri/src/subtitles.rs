@@ -0,0 +1,251 @@
+use entity::transcript_segment;
+use serde::{Deserialize, Serialize};
+
+use crate::speakers::SpeakerNames;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SubtitleFormat {
+    Srt,
+    Vtt,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cue {
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub speaker: Option<String>,
+    pub text: String,
+}
+
+pub fn speaker_label(speaker: i32) -> String {
+    format!("Speaker {}", speaker + 1)
+}
+
+/// Builds one cue per segment, labelled with the speaker's name.
+pub fn cues_from_segments(
+    segments: &[transcript_segment::Model],
+    speakers: &SpeakerNames,
+) -> Vec<Cue> {
+    segments
+        .iter()
+        .filter(|segment| !segment.text.trim().is_empty())
+        .map(|segment| Cue {
+            start_ms: segment.start_ms,
+            end_ms: segment.end_ms,
+            speaker: Some(speakers.label(segment.speaker)),
+            text: segment.text.trim().to_string(),
+        })
+        .collect()
+}
+
+/// The distinct speakers of the cues in order of first appearance. Their
+/// position is the `speaker` of the segments [`segments_from_cues`] returns.
+pub fn speakers_from_cues(cues: &[Cue]) -> Vec<Option<String>> {
+    let mut speakers: Vec<Option<String>> = Vec::new();
+    for cue in cues {
+        if !speakers.contains(&cue.speaker) {
+            speakers.push(cue.speaker.clone());
+        }
+    }
+    speakers
+}
+
+/// Converts parsed cues back into transcript segments. Speakers are numbered
+/// in order of first appearance.
+pub fn segments_from_cues(cues: &[Cue], conversation_id: i32) -> Vec<transcript_segment::Model> {
+    let speakers = speakers_from_cues(cues);
+    cues.iter()
+        .map(|cue| {
+            let speaker = speakers
+                .iter()
+                .position(|s| s == &cue.speaker)
+                .unwrap_or_default();
+            transcript_segment::Model {
+                id: 0,
+                conversation_id,
+                start_ms: cue.start_ms,
+                end_ms: cue.end_ms,
+                speaker: speaker as i32,
+                text: format!(" {}", cue.text),
+            }
+        })
+        .collect()
+}
+
+pub fn render(cues: &[Cue], format: SubtitleFormat) -> String {
+    match format {
+        SubtitleFormat::Srt => render_srt(cues),
+        SubtitleFormat::Vtt => render_vtt(cues),
+    }
+}
+
+pub fn render_srt(cues: &[Cue]) -> String {
+    let mut output = String::new();
+    for (index, cue) in cues.iter().enumerate() {
+        output.push_str(&format!(
+            "{}\n{} --> {}\n",
+            index + 1,
+            format_timestamp(cue.start_ms, ','),
+            format_timestamp(cue.end_ms, ',')
+        ));
+        match &cue.speaker {
+            Some(speaker) => output.push_str(&format!("{}: {}\n\n", speaker, cue.text)),
+            None => output.push_str(&format!("{}\n\n", cue.text)),
+        }
+    }
+    output
+}
+
+pub fn render_vtt(cues: &[Cue]) -> String {
+    let mut output = String::from("WEBVTT\n\n");
+    for cue in cues {
+        output.push_str(&format!(
+            "{} --> {}\n",
+            format_timestamp(cue.start_ms, '.'),
+            format_timestamp(cue.end_ms, '.')
+        ));
+        match &cue.speaker {
+            Some(speaker) => output.push_str(&format!("<v {}>{}\n\n", speaker, cue.text)),
+            None => output.push_str(&format!("{}\n\n", cue.text)),
+        }
+    }
+    output
+}
+
+pub fn parse_srt(content: &str) -> Result<Vec<Cue>, String> {
+    let content = content.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+
+    let mut cues = Vec::new();
+    for block in content.split("\n\n") {
+        let mut lines = block.lines().map(str::trim).filter(|l| !l.is_empty());
+
+        let mut timing_line = match lines.next() {
+            Some(line) => line,
+            None => continue,
+        };
+        // The numeric counter is optional in the wild
+        if !timing_line.contains("-->") {
+            timing_line = lines
+                .next()
+                .ok_or_else(|| format!("Cue '{}' has no timing line", timing_line))?;
+        }
+
+        let (start, end) = timing_line
+            .split_once("-->")
+            .ok_or_else(|| format!("Invalid timing line: {}", timing_line))?;
+        let start_ms = parse_timestamp(start.trim())?;
+        // Drop any cue settings that follow the end timestamp
+        let end_ms = parse_timestamp(end.split_whitespace().next().unwrap_or_default())?;
+
+        let text = lines.collect::<Vec<&str>>().join(" ");
+        let (speaker, text) = split_speaker(&text);
+
+        cues.push(Cue {
+            start_ms,
+            end_ms,
+            speaker,
+            text,
+        });
+    }
+
+    Ok(cues)
+}
+
+fn split_speaker(text: &str) -> (Option<String>, String) {
+    match text.split_once(": ") {
+        Some((speaker, rest))
+            if !speaker.is_empty()
+                && speaker.len() <= 32
+                && speaker.split_whitespace().count() <= 3 =>
+        {
+            (Some(speaker.to_string()), rest.to_string())
+        }
+        _ => (None, text.to_string()),
+    }
+}
+
+fn format_timestamp(ms: i64, separator: char) -> String {
+    let ms = ms.max(0);
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        ms / 3_600_000,
+        (ms / 60_000) % 60,
+        (ms / 1000) % 60,
+        separator,
+        ms % 1000
+    )
+}
+
+/// Parses `hh:mm:ss,mmm`. The fraction is of a second whatever its number
+/// of digits, `00:00:01.5` is 1500 ms; digits past milliseconds are dropped.
+fn parse_timestamp(timestamp: &str) -> Result<i64, String> {
+    let invalid = || format!("Invalid timestamp: {}", timestamp);
+
+    let (clock, fraction) = timestamp.split_once([',', '.']).ok_or_else(invalid)?;
+    if fraction.is_empty() || !fraction.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let millis: i64 = format!("{:0<3.3}", fraction)
+        .parse()
+        .map_err(|_| invalid())?;
+
+    let mut seconds: i64 = 0;
+    for part in clock.split(':') {
+        seconds = seconds * 60 + part.parse::<i64>().map_err(|_| invalid())?;
+    }
+
+    Ok(seconds * 1000 + millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srt_round_trip() {
+        let cues = vec![
+            Cue {
+                start_ms: 0,
+                end_ms: 2_500,
+                speaker: Some(speaker_label(0)),
+                text: "Shall we get started?".to_string(),
+            },
+            Cue {
+                start_ms: 3_723_004,
+                end_ms: 3_725_000,
+                speaker: Some(speaker_label(1)),
+                text: "Yes: the launch moved to Friday.".to_string(),
+            },
+        ];
+
+        let rendered = render_srt(&cues);
+        assert!(rendered.contains("01:02:03,004 --> 01:02:05,000"));
+        assert_eq!(parse_srt(&rendered).unwrap(), cues);
+    }
+
+    #[test]
+    fn parses_srt_without_counters_or_speakers() {
+        let cues =
+            parse_srt("\u{feff}00:00:01.000 --> 00:00:02.000\r\nhello\r\nworld\r\n").unwrap();
+
+        assert_eq!(
+            cues,
+            vec![Cue {
+                start_ms: 1_000,
+                end_ms: 2_000,
+                speaker: None,
+                text: "hello world".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn short_fractions_are_parts_of_a_second() {
+        let cues = parse_srt("1\n00:00:01,5 --> 00:00:02,25\nhello\n").unwrap();
+
+        assert_eq!((cues[0].start_ms, cues[0].end_ms), (1_500, 2_250));
+        assert_eq!(parse_timestamp("00:00:02.0405").unwrap(), 2_040);
+        assert!(parse_timestamp("00:00:02,").is_err());
+    }
+}
@@ -1,127 +1,811 @@
-use std::{fs::File, io::Write, path::PathBuf};
-
+use chrono::NaiveDate;
+use entity::{summary_template, transcript_segment};
 use log::info;
-use ollama_rs::{
-    generation::{completion::request::GenerationRequest, parameters::FormatType},
-    Ollama,
-};
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::llm::{json_from_response, SummaryBackend};
+use crate::speakers::SpeakerNames;
+
+// Rough budget per prompt, leaving room in llama3's 8k context for the
+// instructions and the answer.
+pub const CHUNK_TOKEN_BUDGET: usize = 3000;
+// Chunks are also closed after this much audio so citations stay precise.
+const CHUNK_MAX_DURATION_MS: i64 = 10 * 60 * 1000;
 
 #[derive(Serialize, Deserialize)]
 pub struct SummaryJSON {
     pub result: String,
     pub action_items: Vec<ActionItem>,
+    /// The per-chunk summaries the final summary was built from. Points in
+    /// `result` cite them as `[n]`, the chunk's `index`.
+    #[serde(default)]
+    pub chunks: Vec<ChunkSummary>,
 }
 
-pub async fn summarize_and_write(
-    text: String,
-    summary_output_file_path: &PathBuf,
-) -> Result<(), String> {
-    let summary = summarize(&text).await?;
-    let action_items = generate_action_items(&text).await?;
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkSummary {
+    pub index: usize,
+    /// Times in the conversation, counting pauses.
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub summary: String,
+}
 
-    let summary = SummaryJSON {
-        result: summary,
-        action_items: action_items.action_items,
-    };
+/// A slice of the transcript small enough for a single prompt. Every speaker
+/// turn in `text` starts with its `[mm:ss]` offset.
+#[derive(Debug, Clone)]
+pub struct TranscriptChunk {
+    pub index: usize,
+    /// The segment the chunk begins with.
+    pub first_segment_id: i32,
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub text: String,
+}
+
+/// Very rough token estimate; English averages about four characters per token.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.len() / 4 + 1
+}
+
+pub fn format_offset(ms: i64) -> String {
+    let seconds = ms / 1000;
+    if seconds >= 3600 {
+        format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds % 3600 / 60,
+            seconds % 60
+        )
+    } else {
+        format!("{:02}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+/// Splits the transcript into chunks that fit `token_budget` and span at most
+/// `max_duration_ms`. Chunks break between speaker turns where possible and
+/// between segments when a single turn is too long.
+pub fn chunk_segments(
+    segments: &[transcript_segment::Model],
+    speakers: &SpeakerNames,
+    token_budget: usize,
+    max_duration_ms: i64,
+) -> Vec<TranscriptChunk> {
+    let mut chunks: Vec<TranscriptChunk> = Vec::new();
+    let mut current: Option<TranscriptChunk> = None;
+    let mut current_speaker = None;
+
+    for segment in segments {
+        let text = segment.text.trim();
+        if text.is_empty() {
+            continue;
+        }
+
+        let turn_changed = current_speaker != Some(segment.speaker);
+        let turn_header = format!(
+            "[{}] {}:",
+            format_offset(segment.start_ms),
+            speakers.label(segment.speaker)
+        );
+        let addition = if turn_changed {
+            format!("\n{} {}", turn_header, text)
+        } else {
+            format!(" {}", text)
+        };
+
+        if let Some(chunk) = &current {
+            let over_budget =
+                estimate_tokens(&chunk.text) + estimate_tokens(&addition) > token_budget;
+            let over_duration = segment.end_ms - chunk.start_ms > max_duration_ms;
+            // Prefer to cut at a turn change, but never exceed the token budget
+            if over_budget || (over_duration && turn_changed) {
+                chunks.extend(current.take());
+            }
+        }
+
+        match current.as_mut() {
+            Some(chunk) => {
+                chunk.text.push_str(&addition);
+                chunk.end_ms = segment.end_ms;
+            }
+            None => {
+                current = Some(TranscriptChunk {
+                    index: chunks.len() + 1,
+                    first_segment_id: segment.id,
+                    start_ms: segment.start_ms,
+                    end_ms: segment.end_ms,
+                    // A new chunk always names the speaker
+                    text: format!("{} {}", turn_header, text),
+                });
+            }
+        }
+        current_speaker = Some(segment.speaker);
+    }
+    chunks.extend(current);
+
+    chunks
+}
+
+/// Used when there is no default summary template.
+pub const DEFAULT_SUMMARY_PROMPT: &str = "Summarize this meeting from {{date}} between \
+    {{participants}}. Keep names, decisions and numbers.\n{{transcript}}";
+
+const CHUNK_SUMMARY_PROMPT: &str =
+    "Summarize this part of a meeting transcript. Keep names, decisions and numbers.";
+
+/// The prompt and optional output schema a summary is generated with.
+#[derive(Debug, Clone)]
+pub struct SummaryPrompt {
+    pub template_id: Option<i32>,
+    pub prompt: String,
+    pub output_schema: Option<Value>,
+}
+
+impl Default for SummaryPrompt {
+    fn default() -> Self {
+        SummaryPrompt {
+            template_id: None,
+            prompt: DEFAULT_SUMMARY_PROMPT.to_string(),
+            output_schema: None,
+        }
+    }
+}
+
+impl SummaryPrompt {
+    pub fn from_template(template: &summary_template::Model) -> Result<Self, String> {
+        let output_schema = match &template.output_schema {
+            Some(schema) => Some(serde_json::from_str(schema).map_err(|e| {
+                format!(
+                    "Output schema of template {} is invalid: {}",
+                    template.name, e
+                )
+            })?),
+            None => None,
+        };
+
+        Ok(SummaryPrompt {
+            template_id: Some(template.id),
+            prompt: template.prompt.clone(),
+            output_schema,
+        })
+    }
+}
+
+/// Fills in the `{{transcript}}`, `{{participants}}` and `{{date}}`
+/// placeholders of a template prompt.
+pub fn render_prompt(prompt: &str, transcript: &str, participants: &str, date: &str) -> String {
+    prompt
+        .replace("{{participants}}", participants)
+        .replace("{{date}}", date)
+        // Last, so placeholders spoken in the meeting are left alone
+        .replace("{{transcript}}", transcript)
+}
+
+/// The speakers of the transcript in the order they first speak.
+pub fn participants(segments: &[transcript_segment::Model], speakers: &SpeakerNames) -> String {
+    let mut indices: Vec<i32> = Vec::new();
+    for segment in segments {
+        if !indices.contains(&segment.speaker) {
+            indices.push(segment.speaker);
+        }
+    }
+    indices
+        .into_iter()
+        .map(|speaker| speakers.label(speaker))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Summarizes the transcript with `prompt`, the meeting having taken place on
+/// `date`. A transcript that doesn't fit one prompt is summarized chunk by
+/// chunk first and the template is applied to the chunk summaries, which the
+/// result cites. Returns the summary and those chunk summaries.
+pub async fn summarize_transcript(
+    backend: &dyn SummaryBackend,
+    segments: &[transcript_segment::Model],
+    speakers: &SpeakerNames,
+    prompt: &SummaryPrompt,
+    date: &str,
+) -> Result<(String, Vec<ChunkSummary>), String> {
+    let chunks = chunk_segments(
+        segments,
+        speakers,
+        CHUNK_TOKEN_BUDGET,
+        CHUNK_MAX_DURATION_MS,
+    );
+    info!("Summarizing transcript in {} chunks", chunks.len());
+    let participants = participants(segments, speakers);
+
+    if chunks.len() <= 1 {
+        let transcript = chunks.first().map_or("", |chunk| chunk.text.as_str());
+        let result = complete_prompt(backend, prompt, transcript, &participants, date).await?;
+        return Ok((result, Vec::new()));
+    }
 
-    let json_string =
-        serde_json::to_string_pretty(&summary).expect("failed to serialize transcription");
+    let mut chunk_summaries = Vec::with_capacity(chunks.len());
+    for chunk in &chunks {
+        chunk_summaries.push(summarize_chunk(backend, chunk).await?);
+    }
 
-    let mut file = File::create(summary_output_file_path).expect("couldn't create file");
-    file.write_all(json_string.as_bytes())
-        .expect("could not write to file");
+    let transcript = format!(
+        "The transcript was too long, so here are summaries of its consecutive parts, each \
+        labelled with its number in brackets. After every point, cite the numbers of the parts \
+        it came from, like [2] or [3][4].\n{}",
+        condense_summaries(backend, &chunk_summaries).await?
+    );
+    let result = complete_prompt(backend, prompt, &transcript, &participants, date).await?;
+    Ok((result, chunk_summaries))
+}
+
+/// Renders the prompt and completes it. Structured output is checked to be
+/// JSON and stored pretty-printed.
+async fn complete_prompt(
+    backend: &dyn SummaryBackend,
+    prompt: &SummaryPrompt,
+    transcript: &str,
+    participants: &str,
+    date: &str,
+) -> Result<String, String> {
+    let request = render_prompt(&prompt.prompt, transcript, participants, date);
+    let response = backend
+        .complete(&request, prompt.output_schema.as_ref())
+        .await?;
 
-    Ok(())
+    if prompt.output_schema.is_none() {
+        return Ok(response.trim().to_string());
+    }
+    let value: Value = serde_json::from_str(json_from_response(&response))
+        .map_err(|e| format!("Summary is not valid JSON: {}", e))?;
+    serde_json::to_string_pretty(&value).map_err(|e| e.to_string())
 }
 
-pub async fn summarize(text: &String) -> Result<String, String> {
-    let ollama = Ollama::default();
+/// Collects the action items of every chunk of the transcript of a meeting
+/// held on `date`, which due dates are relative to.
+pub async fn extract_action_items(
+    backend: &dyn SummaryBackend,
+    segments: &[transcript_segment::Model],
+    speakers: &SpeakerNames,
+    date: &str,
+) -> Result<Vec<ActionItem>, String> {
+    let mut action_items = Vec::new();
+    for chunk in chunk_segments(
+        segments,
+        speakers,
+        CHUNK_TOKEN_BUDGET,
+        CHUNK_MAX_DURATION_MS,
+    ) {
+        action_items.extend(generate_action_items(backend, &chunk, segments, date).await?);
+    }
+    Ok(action_items)
+}
+
+async fn summarize_chunk(
+    backend: &dyn SummaryBackend,
+    chunk: &TranscriptChunk,
+) -> Result<ChunkSummary, String> {
+    let prompt = format!(
+        "{}\nThis part covers {} - {} of the meeting.\n{}",
+        CHUNK_SUMMARY_PROMPT,
+        format_offset(chunk.start_ms),
+        format_offset(chunk.end_ms),
+        chunk.text
+    );
+
+    Ok(ChunkSummary {
+        index: chunk.index,
+        start_ms: chunk.start_ms,
+        end_ms: chunk.end_ms,
+        summary: backend.complete(&prompt, None).await?.trim().to_string(),
+    })
+}
+
+/// Lists the chunk summaries with their citation numbers. When they don't
+/// fit a single prompt they are combined in groups, keeping the citations of
+/// the originals, and the combined summaries again until they fit.
+async fn condense_summaries(
+    backend: &dyn SummaryBackend,
+    summaries: &[ChunkSummary],
+) -> Result<String, String> {
+    let mut entries: Vec<String> = summaries
+        .iter()
+        .map(|summary| {
+            format!(
+                "[{}] ({} - {}): {}",
+                summary.index,
+                format_offset(summary.start_ms),
+                format_offset(summary.end_ms),
+                summary.summary
+            )
+        })
+        .collect();
+    let mut separator = "\n";
+
+    loop {
+        let groups = group_by_budget(entries, CHUNK_TOKEN_BUDGET);
+        if groups.len() == 1 {
+            return Ok(groups.concat().join(separator));
+        }
 
-    let model = "llama3:latest".to_string();
-    let prompt = format!("Can you summarize this: {}", text);
+        let tokens: usize = groups
+            .iter()
+            .flatten()
+            .map(|entry| estimate_tokens(entry))
+            .sum();
+        let mut combined = Vec::with_capacity(groups.len());
+        for group in &groups {
+            let prompt = format!(
+                "These are summaries of consecutive parts of one meeting, each labelled with its \
+                number in brackets. Combine them into one summary. After every point, cite the \
+                numbers of the parts it came from, like [2] or [3][4].\n{}",
+                group.join(separator)
+            );
+            combined.push(backend.complete(&prompt, None).await?.trim().to_string());
+        }
+        separator = "\n\n";
 
-    let res = ollama
-        .generate(GenerationRequest::new(model, prompt))
-        .await
-        .expect("Failed to generate summary");
+        // A model that doesn't shorten what it combines would never fit
+        let combined_tokens: usize = combined.iter().map(|entry| estimate_tokens(entry)).sum();
+        if combined_tokens >= tokens {
+            return Ok(combined.join(separator));
+        }
+        entries = combined;
+    }
+}
 
-    Ok(res.response)
+/// Splits `entries` in order into groups that fit `token_budget`. An entry
+/// over the budget gets a group of its own.
+fn group_by_budget(entries: Vec<String>, token_budget: usize) -> Vec<Vec<String>> {
+    let mut groups: Vec<Vec<String>> = vec![Vec::new()];
+    let mut group_tokens = 0;
+    for entry in entries {
+        let tokens = estimate_tokens(&entry);
+        if group_tokens + tokens > token_budget && !groups[groups.len() - 1].is_empty() {
+            groups.push(Vec::new());
+            group_tokens = 0;
+        }
+        group_tokens += tokens;
+        groups
+            .last_mut()
+            .expect("groups is never empty")
+            .push(entry);
+    }
+    groups
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ActionItem {
+    pub title: String,
+    /// Speaker label, e.g. `Speaker 2`.
+    pub assignee: Option<String>,
+    /// `YYYY-MM-DD`
+    pub due_date: Option<String>,
+    /// Start of the transcript segment the item came from.
+    pub source_ms: Option<i64>,
+}
+
+/// An action item as the model writes it, before validation.
+#[derive(Debug, Deserialize)]
+struct RawActionItem {
     title: String,
+    assignee: Option<String>,
+    due_date: Option<String>,
+    source_timestamp: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ActionItems {
-    action_items: Vec<ActionItem>,
+#[derive(Debug, Deserialize)]
+struct RawActionItems {
+    action_items: Vec<RawActionItem>,
 }
 
-pub async fn generate_action_items(text: &String) -> Result<ActionItems, String> {
-    let ollama = Ollama::default();
+const MAX_ACTION_ITEM_ATTEMPTS: usize = 3;
+
+pub fn parse_offset(timestamp: &str) -> Option<i64> {
+    let parts = timestamp
+        .trim()
+        .trim_matches(|c| c == '[' || c == ']')
+        .split(':')
+        .map(|part| part.parse::<i64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    let seconds = match parts.as_slice() {
+        [minutes, seconds] => minutes * 60 + seconds,
+        [hours, minutes, seconds] => hours * 3600 + minutes * 60 + seconds,
+        _ => return None,
+    };
+    Some(seconds * 1000)
+}
+
+/// Parses and checks the model's answer. The error explains what was wrong
+/// so it can be fed back to the model.
+fn validate_action_items(
+    response: &str,
+    chunk: &TranscriptChunk,
+    segments: &[transcript_segment::Model],
+) -> Result<Vec<ActionItem>, String> {
+    let raw: RawActionItems = serde_json::from_str(json_from_response(response))
+        .map_err(|e| format!("The answer is not valid JSON for the schema: {}", e))?;
+
+    let mut action_items = Vec::with_capacity(raw.action_items.len());
+    for item in raw.action_items {
+        let title = item.title.trim().to_string();
+        if title.is_empty() {
+            return Err("Every action item needs a non-empty title".to_string());
+        }
+
+        let assignee = item
+            .assignee
+            .map(|assignee| assignee.trim().to_string())
+            .filter(|assignee| {
+                !assignee.is_empty()
+                    && !matches!(
+                        assignee.to_lowercase().as_str(),
+                        "null" | "unknown" | "none"
+                    )
+            });
+
+        let due_date = match item.due_date.as_deref().map(str::trim) {
+            None | Some("") => None,
+            Some(due_date) => {
+                NaiveDate::parse_from_str(due_date, "%Y-%m-%d").map_err(|_| {
+                    format!(
+                        "Due date \"{}\" of \"{}\" is not YYYY-MM-DD",
+                        due_date, title
+                    )
+                })?;
+                Some(due_date.to_string())
+            }
+        };
+
+        let source_ms = match item.source_timestamp.as_deref().map(str::trim) {
+            None | Some("") => None,
+            Some(timestamp) => {
+                let ms = parse_offset(timestamp).ok_or(format!(
+                    "Source timestamp \"{}\" of \"{}\" is not mm:ss",
+                    timestamp, title
+                ))?;
+                // Snap to the segment it points into, staying inside the chunk
+                let ms = ms.clamp(chunk.start_ms, chunk.end_ms);
+                Some(
+                    segments
+                        .iter()
+                        .rev()
+                        .find(|segment| {
+                            segment.start_ms >= chunk.start_ms && segment.start_ms <= ms
+                        })
+                        .map_or(chunk.start_ms, |segment| segment.start_ms),
+                )
+            }
+        };
 
-    let model = "llama3:latest".to_string();
+        action_items.push(ActionItem {
+            title,
+            assignee,
+            due_date,
+            source_ms,
+        });
+    }
+
+    Ok(action_items)
+}
+
+/// Extracts the action items of one chunk. Invalid answers are sent back to
+/// the model with the validation error, up to `MAX_ACTION_ITEM_ATTEMPTS` times.
+pub async fn generate_action_items(
+    backend: &dyn SummaryBackend,
+    chunk: &TranscriptChunk,
+    segments: &[transcript_segment::Model],
+    date: &str,
+) -> Result<Vec<ActionItem>, String> {
     let prompt = format!(
-        "Create action items from a transcript.
-        You must format your output as a JSON value that adheres to a given \"JSON Schema\" instance.
-        \"JSON Schema\" is a declarative language that allows you to annotate and validate JSON documents.
-        For example, the example \"JSON Schema\" instance {{\"properties\": {{\"foo\": {{\"description\": \"a list of test words\", \"type\": \"array\", \"items\": {{\"type\": \"string\"}}}}}}, \"required\": [\"foo\"]}}
-        would match an object with one required property, \"foo\". The \"type\" property specifies \"foo\" must be an \"array\", and the \"description\" property semantically describes it as \"a list of test words\". The items within \"foo\" must be strings.
-        Thus, the object {{\"foo\": [\"bar\", \"baz\"]}} is a well-formatted instance of this example \"JSON Schema\". The object {{\"properties\": {{\"foo\": [\"bar\", \"baz\"]}}}} is not well-formatted.
-        Your output will be parsed and type-checked according to the provided schema instance, so make sure all fields in your output match the schema exactly and there are no trailing commas!
-        Here is the JSON Schema instance your output must adhere to. Include the enclosing markdown codeblock:
-        ```json
-        {{
-            \"type\": \"object\",
-            \"properties\": {{
-                \"action_items\": {{
-                    \"type\": \"array\",
-                    \"items\": {{
-                        \"type\": \"object\",
-                        \"properties\": {{
-                            \"title\": {{
-                                \"type\": \"string\",
-                                \"description\": \"The title of the action item\"
-                            }}
-                        }},
-                        \"required\": [\"title\"],
-                        \"additionalProperties\": false
-                    }}
-                }}
-            }},
-            \"required\": [\"actionItems\"],
-            \"additionalProperties\": false,
-            \"$schema\": \"http://json-schema.org/draft-07/schema#\"
-        }}
-        ```
-        transcript: {}",
-        text
+        "Create action items from a transcript of a meeting on {}. Every speaker turn starts with its \
+        [mm:ss] timestamp and speaker label. For each action item give the speaker label of the \
+        person responsible as the assignee, the due date as YYYY-MM-DD if one was agreed, and \
+        the timestamp of the turn it was mentioned in as source_timestamp.\ntranscript: {}",
+        date,
+        chunk.text
     );
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "action_items": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "title": {
+                            "type": "string",
+                            "description": "The title of the action item"
+                        },
+                        "assignee": {
+                            "type": ["string", "null"],
+                            "description": "Speaker label of the person responsible, e.g. Speaker 2"
+                        },
+                        "due_date": {
+                            "type": ["string", "null"],
+                            "description": "Due date as YYYY-MM-DD"
+                        },
+                        "source_timestamp": {
+                            "type": ["string", "null"],
+                            "description": "Timestamp of the turn the item was mentioned in, as mm:ss"
+                        }
+                    },
+                    "required": ["title"],
+                    "additionalProperties": false
+                }
+            }
+        },
+        "required": ["action_items"],
+        "additionalProperties": false,
+        "$schema": "http://json-schema.org/draft-07/schema#"
+    });
+
+    let mut request = prompt.clone();
+    let mut last_error = String::new();
+    for attempt in 1..=MAX_ACTION_ITEM_ATTEMPTS {
+        let response = backend.complete(&request, Some(&schema)).await?;
+        info!("action items (attempt {}): {}", attempt, response);
+
+        match validate_action_items(&response, chunk, segments) {
+            Ok(action_items) => return Ok(action_items),
+            Err(err) => {
+                info!("Invalid action items: {}", err);
+                request = format!(
+                    "{}\n\nYour previous answer was:\n{}\nIt was rejected because: {}\nAnswer again.",
+                    prompt, response, err
+                );
+                last_error = err;
+            }
+        }
+    }
+
+    Err(format!(
+        "Action items not formatted correctly after {} attempts: {}",
+        MAX_ACTION_ITEM_ATTEMPTS, last_error
+    ))
+}
+
+const MAX_TITLE_CHARS: usize = 60;
+
+/// Turns a model's answer into a bare title: drops preambles such as
+/// "Here is a title:", markdown and quotes, and caps the length at a word
+/// boundary.
+pub fn sanitize_title(response: &str) -> Option<String> {
+    let mut lines = response
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .peekable();
+    let mut line = lines.next()?;
+    // "Here is a short meeting title:" followed by the title on its own line
+    if line.ends_with(':') {
+        line = lines.peek().copied().unwrap_or(line);
+    }
+
+    let mut title = line;
+    // Only the model's own lead-ins, a title like "Title IX: budget review"
+    // stays as it is
+    if let Some((prefix, rest)) = title.split_once(':') {
+        let prefix = prefix
+            .trim_matches(|c: char| matches!(c, '*' | '#' | '_') || c.is_whitespace())
+            .to_lowercase();
+        if prefix == "title"
+            || prefix.starts_with("here is")
+            || prefix.starts_with("here's")
+            || prefix.starts_with("sure")
+        {
+            title = rest;
+        }
+    }
+    let title = title
+        .trim()
+        .trim_matches(|c: char| matches!(c, '"' | '\'' | '*' | '#' | '`' | '“' | '”' | '‘' | '’'))
+        .trim()
+        .trim_end_matches('.')
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
 
-    let generation_request = GenerationRequest::new(model, prompt).format(FormatType::Json);
+    if title.is_empty() {
+        return None;
+    }
+    if title.chars().count() <= MAX_TITLE_CHARS {
+        return Some(title);
+    }
 
-    let res = ollama
-        .generate(generation_request)
-        .await
-        .expect("failed to generate action items");
-    info!("action items: {}", res.response);
-    let json: ActionItems =
-        serde_json::from_str(&res.response).expect("Action items not formatted correctly");
-    Ok(json)
+    let mut capped = String::new();
+    for word in title.split(' ') {
+        if capped.chars().count() + word.chars().count() + 1 > MAX_TITLE_CHARS {
+            break;
+        }
+        if !capped.is_empty() {
+            capped.push(' ');
+        }
+        capped.push_str(word);
+    }
+    if capped.is_empty() {
+        capped = title.chars().take(MAX_TITLE_CHARS).collect();
+    }
+    Some(capped)
 }
 
-pub async fn generate_title(text: &String) -> Result<String, String> {
-    let ollama = Ollama::default();
+pub async fn generate_title(backend: &dyn SummaryBackend, text: &String) -> Result<String, String> {
+    let prompt = format!(
+        "Generate a short meeting title of at most six words from this. Answer with the title only: {}",
+        text
+    );
+
+    let response = backend.complete(&prompt, None).await?;
+    sanitize_title(&response).ok_or(format!("No title in response: {}", response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::segment;
+
+    #[test]
+    fn templates_are_rendered_with_participants_and_date() {
+        let segments = vec![
+            segment(1, 0, 1_000, 1, "Hi."),
+            segment(2, 1_000, 2_000, 0, "Hello."),
+            segment(3, 2_000, 3_000, 1, "Let's begin."),
+        ];
+
+        let prompt = render_prompt(
+            "Standup on {{date}} with {{participants}}:\n{{transcript}}",
+            "Someone said {{date}}",
+            &participants(&segments, &SpeakerNames::default()),
+            "2024-11-03",
+        );
+
+        assert_eq!(
+            prompt,
+            "Standup on 2024-11-03 with Speaker 2, Speaker 1:\nSomeone said {{date}}"
+        );
+    }
+
+    #[test]
+    fn chunks_break_at_turns_after_the_time_window() {
+        let segments = vec![
+            segment(1, 0, 4_000, 0, "Hello everyone."),
+            segment(2, 4_000, 8_000, 0, "Let's start."),
+            segment(3, 8_000, 12_000, 1, "Thanks."),
+            segment(4, 12_000, 16_000, 1, "First item."),
+        ];
+
+        let chunks = chunk_segments(&segments, &SpeakerNames::default(), 1000, 5_000);
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].index, 1);
+        assert_eq!((chunks[0].start_ms, chunks[0].end_ms), (0, 8_000));
+        assert_eq!(
+            chunks[0].text,
+            "[00:00] Speaker 1: Hello everyone. Let's start."
+        );
+        assert_eq!((chunks[1].start_ms, chunks[1].end_ms), (8_000, 16_000));
+        assert_eq!(
+            (chunks[0].first_segment_id, chunks[1].first_segment_id),
+            (1, 3)
+        );
+        assert_eq!(chunks[1].text, "[00:08] Speaker 2: Thanks. First item.");
+    }
+
+    #[test]
+    fn long_turns_are_split_to_fit_the_budget() {
+        let segments: Vec<_> = (0..10)
+            .map(|i| {
+                segment(
+                    i as i32 + 1,
+                    i * 1000,
+                    (i + 1) * 1000,
+                    0,
+                    &"word ".repeat(20),
+                )
+            })
+            .collect();
+
+        let chunks = chunk_segments(&segments, &SpeakerNames::default(), 60, i64::MAX);
+
+        assert!(chunks.len() > 1);
+        assert!(chunks
+            .iter()
+            .all(|chunk| estimate_tokens(&chunk.text) <= 60));
+        assert!(chunks
+            .iter()
+            .all(|chunk| chunk.text.contains("] Speaker 1: ")));
+    }
+
+    #[test]
+    fn action_items_are_validated_and_snapped_to_segments() {
+        let segments = vec![
+            segment(1, 0, 4_000, 0, "Hello everyone."),
+            segment(2, 4_000, 9_000, 1, "I'll send the report by Friday."),
+        ];
+        let chunk = &chunk_segments(&segments, &SpeakerNames::default(), 1000, i64::MAX)[0];
+
+        let items = validate_action_items(
+            r#"```json
+            {"action_items": [{"title": "Send the report", "assignee": "Speaker 2",
+              "due_date": "2024-11-01", "source_timestamp": "00:06"}]}
+            ```"#,
+            chunk,
+            &segments,
+        )
+        .unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].assignee.as_deref(), Some("Speaker 2"));
+        assert_eq!(items[0].source_ms, Some(4_000));
+
+        let err = validate_action_items(
+            r#"{"action_items": [{"title": "Send the report", "due_date": "Friday"}]}"#,
+            chunk,
+            &segments,
+        )
+        .unwrap_err();
+        assert!(err.contains("YYYY-MM-DD"));
+    }
+
+    #[test]
+    fn titles_are_sanitized() {
+        assert_eq!(
+            sanitize_title("Here is a short meeting title:\n\n\"Q3 Budget Review\"").as_deref(),
+            Some("Q3 Budget Review")
+        );
+        assert_eq!(
+            sanitize_title("**Title:** Hiring plan for 2025.").as_deref(),
+            Some("Hiring plan for 2025")
+        );
+        assert_eq!(
+            sanitize_title("Title IX: budget review").as_deref(),
+            Some("Title IX: budget review")
+        );
+        assert_eq!(sanitize_title(" \n\"\" "), None);
+
+        let long = sanitize_title(&"word ".repeat(30)).unwrap();
+        assert!(long.chars().count() <= MAX_TITLE_CHARS);
+        assert!(long.ends_with("word"));
+    }
+
+    /// Answers every prompt with a third of its length.
+    struct ShorteningBackend {
+        calls: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl SummaryBackend for ShorteningBackend {
+        async fn complete(&self, prompt: &str, _: Option<&Value>) -> Result<String, String> {
+            self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok("x".repeat(prompt.len() / 3))
+        }
+    }
 
-    let model = "llama3:latest".to_string();
-    let prompt = format!("Can you generate a short meeting title from this: {}", text);
+    #[tokio::test]
+    async fn combined_summaries_are_condensed_until_they_fit() {
+        let summaries: Vec<ChunkSummary> = (0..40)
+            .map(|i| ChunkSummary {
+                index: i + 1,
+                start_ms: i as i64 * 60_000,
+                end_ms: (i as i64 + 1) * 60_000,
+                summary: "word ".repeat(320),
+            })
+            .collect();
+        let backend = ShorteningBackend {
+            calls: Default::default(),
+        };
 
-    let res = ollama
-        .generate(GenerationRequest::new(model, prompt))
-        .await
-        .expect("failed to generate title");
+        let condensed = condense_summaries(&backend, &summaries).await.unwrap();
 
-    Ok(res.response)
+        assert!(estimate_tokens(&condensed) <= CHUNK_TOKEN_BUDGET);
+        // One pass over the groups of summaries didn't fit, so there was another
+        let calls = backend.calls.load(std::sync::atomic::Ordering::SeqCst);
+        let first_pass = group_by_budget(
+            summaries
+                .iter()
+                .map(|summary| summary.summary.clone())
+                .collect(),
+            CHUNK_TOKEN_BUDGET,
+        )
+        .len();
+        assert!(calls > first_pass);
+    }
 }
@@ -0,0 +1,19 @@
+use entity::transcript_segment;
+
+/// A transcript segment of conversation 1.
+pub fn segment(
+    id: i32,
+    start_ms: i64,
+    end_ms: i64,
+    speaker: i32,
+    text: &str,
+) -> transcript_segment::Model {
+    transcript_segment::Model {
+        id,
+        conversation_id: 1,
+        start_ms,
+        end_ms,
+        speaker,
+        text: text.to_string(),
+    }
+}
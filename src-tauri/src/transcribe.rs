@@ -1,39 +1,130 @@
 use std::{
-    fs::{read_dir, read_to_string, File},
-    io::Write,
-    path::PathBuf,
+    fs::{read_dir, read_to_string},
+    path::{Path, PathBuf},
     sync::Arc,
 };
 
+use entity::{conversation, speaker::SpeakerSource, transcript, transcript_segment};
 use hound::{SampleFormat, WavReader};
 use log::info;
 use serde::{Deserialize, Serialize};
+use service::{sea_orm::DatabaseConnection, Mutation, Query};
 use tauri::Manager;
-use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters};
+use whisper_rs::{FullParams, SamplingStrategy, WhisperState};
+
+use crate::{
+    diarization::{track_source, Track},
+    manifest::RecordingManifest,
+    model_registry::ModelInfo,
+    recorder::RecordingState,
+    AppState,
+};
+
+/// Setting key holding the JSON-encoded [`TrackMode`].
+pub const TRACK_MODE_SETTING: &str = "track_mode";
 
-use crate::recorder::RecordingState;
+/// The `speaker` of segments transcribed from the microphone track.
+pub const MIC_SPEAKER: i32 = 0;
+/// The `speaker` of segments transcribed from the system audio track.
+pub const SYSTEM_SPEAKER: i32 = 1;
 
 #[derive(Serialize, Deserialize)]
 pub struct TranscriptionJSON {
     pub full_text: Vec<String>,
 }
 
-pub fn transcribe_wav_file_and_write(
-    handle: tauri::AppHandle,
-    wav_filepath: &PathBuf,
-    transcription_output_file_path: &PathBuf,
-) -> Result<(), String> {
-    let filepath_str = wav_filepath.to_str().unwrap_or_default().to_owned();
-    info!("{}", filepath_str);
-
-    let whisper_path = handle
+/// The tinydiarize model bundled with the app.
+pub fn whisper_model_path(handle: &tauri::AppHandle) -> PathBuf {
+    handle
         .path()
         .resource_dir()
         .expect("failed to get resource dir")
-        .join("src/models/ggml-small.en-tdrz.bin");
-    if !whisper_path.exists() {
-        panic!("whisper file doesn't exist");
+        .join("src/models/ggml-small.en-tdrz.bin")
+}
+
+/// How a conversation should be transcribed, taken from its row.
+#[derive(Debug, Clone, Default)]
+pub struct TranscriptionOptions {
+    pub language: Option<String>,
+    pub translate: bool,
+    pub initial_prompt: Option<String>,
+    pub beam_size: Option<i32>,
+    pub temperature: Option<f64>,
+    /// Mark speaker turns, for models fine-tuned for tinydiarize.
+    pub tdrz: bool,
+}
+
+impl From<&conversation::Model> for TranscriptionOptions {
+    fn from(conversation: &conversation::Model) -> Self {
+        TranscriptionOptions {
+            language: conversation.language.clone(),
+            translate: conversation.translate,
+            initial_prompt: conversation.initial_prompt.clone(),
+            beam_size: conversation.beam_size,
+            temperature: conversation.temperature,
+            tdrz: false,
+        }
+    }
+}
+
+impl TranscriptionOptions {
+    /// English-only models can neither transcribe another language nor
+    /// translate.
+    pub fn check_model(&self, model: &ModelInfo) -> Result<(), String> {
+        if model.multilingual {
+            return Ok(());
+        }
+        if self.translate {
+            return Err(format!(
+                "Model {} only transcribes English and cannot translate",
+                model.name
+            ));
+        }
+        match self.language.as_deref() {
+            Some(language) if language != "en" => Err(format!(
+                "Model {} only transcribes English, not {}",
+                model.name, language
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn full_params(&self) -> FullParams {
+        let strategy = match self.beam_size {
+            Some(beam_size) if beam_size > 1 => SamplingStrategy::BeamSearch {
+                beam_size,
+                patience: -1.0,
+            },
+            _ => SamplingStrategy::default(),
+        };
+
+        let mut params = FullParams::new(strategy);
+        // whisper auto-detects the language when given "auto"
+        params.set_language(Some(self.language.as_deref().unwrap_or("auto")));
+        params.set_translate(self.translate);
+        params.set_tdrz_enable(self.tdrz);
+        if let Some(initial_prompt) = self.initial_prompt.as_deref() {
+            if !initial_prompt.trim().is_empty() {
+                params.set_initial_prompt(initial_prompt);
+            }
+        }
+        if let Some(temperature) = self.temperature {
+            params.set_temperature(temperature as f32);
+        }
+        params
     }
+}
+
+/// Transcribes a 16 kHz mono WAV file. Returns the segments together with the
+/// language whisper detected (or was told to use).
+pub fn transcribe_wav_file(
+    state: &mut WhisperState,
+    wav_filepath: &PathBuf,
+    conversation_id: i32,
+    options: &TranscriptionOptions,
+) -> Result<(Vec<transcript_segment::Model>, Option<String>), String> {
+    let filepath_str = wav_filepath.to_str().unwrap_or_default().to_owned();
+    info!("{}", filepath_str);
 
     let mut reader = WavReader::open(filepath_str).expect("failed to read file");
     let spec = reader.spec();
@@ -58,16 +149,8 @@ pub fn transcribe_wav_file_and_write(
     whisper_rs::convert_integer_to_float_audio(&original_samples, &mut samples)
         .expect("failed to convert samples");
 
-    let ctx = WhisperContext::new_with_params(
-        &whisper_path.to_string_lossy(),
-        WhisperContextParameters::default(),
-    )
-    .expect("failed to open model");
-    let mut state = ctx.create_state().expect("failed to create state");
-    let mut params = FullParams::new(SamplingStrategy::default());
-    params.set_initial_prompt("experience");
+    let mut params = options.full_params();
     params.set_progress_callback_safe(|progress| info!("Progress callback: {}%", progress));
-    params.set_tdrz_enable(true);
 
     let st = std::time::Instant::now();
     state
@@ -76,20 +159,23 @@ pub fn transcribe_wav_file_and_write(
 
     let et = std::time::Instant::now();
 
+    let detected_language = state
+        .full_lang_id_from_state()
+        .ok()
+        .and_then(whisper_rs::get_lang_str)
+        .map(str::to_string);
+    info!("Detected language: {:?}", detected_language);
+
     let num_segments = state
         .full_n_segments()
         .expect("failed to get number of segments");
-    let mut full_text: Vec<String> = vec![String::new()];
-    let mut full_text_index = 0;
+    let mut segments = Vec::with_capacity(num_segments as usize);
+    let mut speaker = 0;
     for i in 0..num_segments {
         let segment = state
             .full_get_segment_text(i)
             .expect("failed to get segment");
-        full_text[full_text_index].push_str(&segment);
-        if state.full_get_segment_speaker_turn_next(i) {
-            full_text.push(String::new());
-            full_text_index += 1
-        }
+        // whisper timestamps are in centiseconds
         let start_timestamp = state
             .full_get_segment_t0(i)
             .expect("failed to get start timestamp");
@@ -97,20 +183,125 @@ pub fn transcribe_wav_file_and_write(
             .full_get_segment_t1(i)
             .expect("failed to get end timestamp");
         info!("[{} - {}]: {}", start_timestamp, end_timestamp, segment);
+
+        segments.push(transcript_segment::Model {
+            id: 0,
+            conversation_id,
+            start_ms: start_timestamp * 10,
+            end_ms: end_timestamp * 10,
+            speaker,
+            text: segment,
+        });
+
+        if state.full_get_segment_speaker_turn_next(i) {
+            speaker += 1;
+        }
     }
     info!("Transcription took {}ms", (et - st).as_millis());
 
-    let transcription = TranscriptionJSON {
-        full_text: full_text,
+    Ok((segments, detected_language))
+}
+
+/// How the microphone and system audio of a recording are transcribed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackMode {
+    /// Both tracks are mixed to mono and transcribed together.
+    #[default]
+    Mixed,
+    /// Every track is transcribed on its own, so each segment is known to
+    /// come from the user or from the remote participants.
+    Separate,
+}
+
+impl TrackMode {
+    pub async fn load(db: &DatabaseConnection) -> Result<Self, String> {
+        match Query::find_setting(db, TRACK_MODE_SETTING)
+            .await
+            .map_err(|e| e.to_string())?
+        {
+            Some(value) => serde_json::from_str(&value).map_err(|e| e.to_string()),
+            None => Ok(TrackMode::default()),
+        }
+    }
+}
+
+/// The microphone and system audio tracks of a recording when it was
+/// recorded to be transcribed separately. Imported files only have the mix.
+pub fn separate_tracks(recording_dir: &Path) -> Result<Option<(PathBuf, PathBuf)>, String> {
+    let track_mode = RecordingManifest::load(recording_dir)?
+        .map(|manifest| manifest.track_mode)
+        .unwrap_or_default();
+    if track_mode != TrackMode::Separate {
+        return Ok(None);
+    }
+    let mic = recording_dir.join("input").join("combined.wav");
+    let system = recording_dir.join("output").join("combined.wav");
+    Ok((mic.exists() && system.exists()).then_some((mic, system)))
+}
+
+/// Puts the segments of both tracks on one timeline, attributed to
+/// [`MIC_SPEAKER`] and [`SYSTEM_SPEAKER`]. The segments of each track are
+/// shifted by its delay in `delays_ms`, the way the tracks are mixed.
+pub fn merge_track_segments(
+    mic: Vec<transcript_segment::Model>,
+    system: Vec<transcript_segment::Model>,
+    delays_ms: [i64; 2],
+) -> Vec<transcript_segment::Model> {
+    let attribute = |speaker: i32, delay_ms: i64| {
+        move |segment: transcript_segment::Model| transcript_segment::Model {
+            speaker,
+            start_ms: segment.start_ms + delay_ms,
+            end_ms: segment.end_ms + delay_ms,
+            ..segment
+        }
     };
+    let mut segments: Vec<transcript_segment::Model> = mic
+        .into_iter()
+        .map(attribute(MIC_SPEAKER, delays_ms[0]))
+        .chain(
+            system
+                .into_iter()
+                .map(attribute(SYSTEM_SPEAKER, delays_ms[1])),
+        )
+        .collect();
+    segments.sort_by_key(|segment| (segment.start_ms, segment.end_ms));
+    segments
+}
 
-    let json_string =
-        serde_json::to_string_pretty(&transcription).expect("failed to serialize transcription");
+/// Transcribes the microphone and system audio tracks of a recording on their
+/// own and merges the segments. Remote speech the microphone picked up from
+/// the speakers is dropped, the system track has it first hand.
+pub fn transcribe_tracks(
+    state: &mut WhisperState,
+    mic_path: &PathBuf,
+    system_path: &PathBuf,
+    delays_ms: [i64; 2],
+    conversation_id: i32,
+    options: &TranscriptionOptions,
+) -> Result<(Vec<transcript_segment::Model>, Option<String>), String> {
+    let (mic_segments, mic_language) =
+        transcribe_wav_file(state, mic_path, conversation_id, options)?;
+    let (system_segments, system_language) =
+        transcribe_wav_file(state, system_path, conversation_id, options)?;
+
+    let mic = Track::open(mic_path)?;
+    let system = Track::open(system_path)?;
+    // Where the system track is at a time on the microphone track
+    let system_offset_ms = delays_ms[0] - delays_ms[1];
+    let mic_segments = mic_segments
+        .into_iter()
+        .filter(|segment| {
+            let (start_ms, end_ms) = (segment.start_ms, segment.end_ms);
+            let system = system.slice(start_ms + system_offset_ms, end_ms + system_offset_ms);
+            track_source(mic.slice(start_ms, end_ms), system) != SpeakerSource::Them
+        })
+        .collect();
 
-    let mut file = File::create(transcription_output_file_path).expect("couldn't create file");
-    file.write_all(json_string.as_bytes())
-        .expect("could not write to file");
-    Ok(())
+    Ok((
+        merge_track_segments(mic_segments, system_segments, delays_ms),
+        mic_language.or(system_language),
+    ))
 }
 
 #[tauri::command]
@@ -124,13 +315,29 @@ pub async fn get_real_time_transcription(
         None => return Err("Data directory not set".to_string()),
     };
 
-    let audio_dir = data_dir.join("chunks/audio");
+    let conversation_id = match state_guard.conversation_id {
+        Some(id) => id,
+        None => {
+            return Ok(TranscriptionJSON {
+                full_text: Vec::new(),
+            })
+        }
+    };
+
+    let live_dir = data_dir
+        .join("chunks/audio")
+        .join(conversation_id.to_string())
+        .join("live");
+    if !live_dir.exists() {
+        return Ok(TranscriptionJSON {
+            full_text: Vec::new(),
+        });
+    }
 
-    let mut paths: Vec<PathBuf> = match read_dir(audio_dir) {
+    let mut paths: Vec<PathBuf> = match read_dir(live_dir) {
         Ok(entries) => entries
             .filter_map(|entry| entry.ok())
             .map(|entry| entry.path())
-            .filter(|path| path.file_name() != Some(std::ffi::OsStr::new("transcription.json")))
             .collect(),
         Err(err) => return Err(format!("Failed to read directory: {}", err)),
     };
@@ -164,80 +371,129 @@ pub async fn get_real_time_transcription(
 
 #[tauri::command]
 pub async fn get_complete_transcription(
-    state: tauri::State<'_, Arc<tauri::async_runtime::Mutex<RecordingState>>>,
-    conversation_id: u64,
+    state: tauri::State<'_, AppState>,
+    conversation_id: i32,
 ) -> Result<TranscriptionJSON, String> {
-    let state_guard = state.lock().await;
-
-    let data_dir = match &state_guard.data_dir {
-        Some(dir) => dir,
-        None => return Err("Data directory not set".to_string()),
-    };
-
-    let audio_dir = data_dir
-        .join("chunks/audio")
-        .join(conversation_id.to_string());
-
-    let mut paths: Vec<PathBuf> = match read_dir(audio_dir) {
-        Ok(entries) => entries
-            .filter_map(|entry| entry.ok())
-            .map(|entry| entry.path())
-            .filter(|path| path.file_name() == Some(std::ffi::OsStr::new("transcription.json")))
-            .collect(),
-        Err(err) => return Err(format!("Failed to read directory: {}", err)),
-    };
-
-    info!("Found {} transcription files", paths.len());
-
-    paths.sort_by(|a, b| {
-        let a_name = a.file_stem().and_then(|s| s.to_str()).unwrap_or("");
-        let b_name = b.file_stem().and_then(|s| s.to_str()).unwrap_or("");
-        a_name.cmp(b_name)
-    });
-
-    let mut merged_content = TranscriptionJSON {
-        full_text: Vec::new(),
-    };
-
-    for path in paths {
-        if path.extension().and_then(|ext| ext.to_str()) == Some("json") {
-            let content = read_to_string(&path)
-                .map_err(|err| format!("Failed to read file {}: {}", path.display(), err))?;
-
-            let json_content: TranscriptionJSON =
-                serde_json::from_str(&content).map_err(|err| {
-                    format!("Failed to parse JSON in file {}: {}", path.display(), err)
-                })?;
-
-            merged_content.full_text.extend(json_content.full_text);
-        }
+    let segments = Query::find_transcript_segments_by_conversation_id(&state.db, conversation_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    info!("Found {} transcript segments", segments.len());
+
+    if !segments.is_empty() {
+        return Ok(TranscriptionJSON {
+            full_text: transcript::paragraphs(
+                segments
+                    .iter()
+                    .map(|segment| (segment.speaker, segment.text.as_str())),
+            ),
+        });
     }
 
-    Ok(merged_content)
+    // Transcripts from before segments were stored have only their text
+    let transcript = Query::find_transcript_by_conversation_id(&state.db, conversation_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(TranscriptionJSON {
+        full_text: transcript
+            .map(|transcript| {
+                transcript
+                    .text
+                    .split("\n\n")
+                    .filter(|paragraph| !paragraph.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default(),
+    })
 }
 
-pub async fn load_transcription(transcription_path: PathBuf) -> Result<TranscriptionJSON, String> {
-    let mut json = TranscriptionJSON {
-        full_text: Vec::new(),
-    };
+#[tauri::command]
+pub async fn get_transcript_segments(
+    state: tauri::State<'_, AppState>,
+    conversation_id: i32,
+) -> Result<Vec<transcript_segment::Model>, String> {
+    Query::find_transcript_segments_by_conversation_id(&state.db, conversation_id)
+        .await
+        .map_err(|e| e.to_string())
+}
 
-    let content = read_to_string(&transcription_path).map_err(|err| {
-        format!(
-            "Failed to read file {}: {}",
-            transcription_path.display(),
-            err
-        )
-    })?;
+#[tauri::command]
+pub async fn get_track_mode(state: tauri::State<'_, AppState>) -> Result<TrackMode, String> {
+    TrackMode::load(&state.db).await
+}
 
-    let json_content: TranscriptionJSON = serde_json::from_str(&content).map_err(|err| {
-        format!(
-            "Failed to parse JSON in file {}: {}",
-            transcription_path.display(),
-            err
-        )
-    })?;
+/// Sets how recordings are transcribed from the next one started on.
+#[tauri::command]
+pub async fn set_track_mode(
+    state: tauri::State<'_, AppState>,
+    mode: TrackMode,
+) -> Result<(), String> {
+    let value = serde_json::to_string(&mode).map_err(|e| e.to_string())?;
+    Mutation::set_setting(&state.db, TRACK_MODE_SETTING, value)
+        .await
+        .map_err(|e| e.to_string())
+}
 
-    json.full_text.extend(json_content.full_text);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::segment;
+
+    #[test]
+    fn tracks_merge_onto_one_timeline() {
+        let mic = vec![
+            segment(1, 0, 2_000, 3, "Hi all."),
+            segment(2, 5_000, 7_000, 3, "Sounds good."),
+        ];
+        let system = vec![segment(3, 2_000, 4_000, 3, "Hello!")];
+
+        // System audio started 4 s after the microphone
+        let segments = merge_track_segments(mic, system, [0, 4_000]);
+
+        let merged: Vec<(&str, i64, i32)> = segments
+            .iter()
+            .map(|segment| (segment.text.as_str(), segment.start_ms, segment.speaker))
+            .collect();
+        assert_eq!(
+            merged,
+            vec![
+                ("Hi all.", 0, MIC_SPEAKER),
+                ("Sounds good.", 5_000, MIC_SPEAKER),
+                ("Hello!", 6_000, SYSTEM_SPEAKER),
+            ]
+        );
+    }
 
-    Ok(json)
-}
\ No newline at end of file
+    #[test]
+    fn english_only_models_refuse_other_languages() {
+        let model = ModelInfo {
+            name: "ggml-tiny.en.bin".to_string(),
+            path: PathBuf::from("ggml-tiny.en.bin"),
+            format: "ggml".to_string(),
+            multilingual: false,
+            tdrz: false,
+            size_bytes: 0,
+            sha256: None,
+            expected_sha256: None,
+            verified: None,
+            bundled: false,
+        };
+        let options = |language: Option<&str>, translate| TranscriptionOptions {
+            language: language.map(str::to_string),
+            translate,
+            ..TranscriptionOptions::default()
+        };
+
+        assert!(options(None, false).check_model(&model).is_ok());
+        assert!(options(Some("en"), false).check_model(&model).is_ok());
+        assert!(options(Some("de"), false).check_model(&model).is_err());
+        assert!(options(None, true).check_model(&model).is_err());
+
+        let multilingual = ModelInfo {
+            multilingual: true,
+            ..model
+        };
+        assert!(options(Some("de"), true).check_model(&multilingual).is_ok());
+    }
+}
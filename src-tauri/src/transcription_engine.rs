@@ -0,0 +1,120 @@
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use log::info;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use whisper_rs::{WhisperContext, WhisperContextParameters, WhisperState};
+
+/// Owns the loaded whisper model and hands out states to transcribe with.
+///
+/// The model is loaded once and kept warm. Access is limited to
+/// `concurrency` states at a time, since each one runs whisper across all
+/// cores. Live transcription has slots of its own, so it never waits behind
+/// a long background job. Requesting a different model swaps the loaded
+/// context; states that are already handed out keep using the model they
+/// were created from.
+pub struct TranscriptionEngine {
+    loaded: Mutex<Option<(PathBuf, Arc<WhisperContext>)>>,
+    permits: Arc<Semaphore>,
+    live_permits: Arc<Semaphore>,
+}
+
+/// A whisper state together with its slot in the engine.
+pub struct EngineState {
+    state: WhisperState,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Deref for EngineState {
+    type Target = WhisperState;
+
+    fn deref(&self) -> &Self::Target {
+        &self.state
+    }
+}
+
+impl DerefMut for EngineState {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.state
+    }
+}
+
+impl TranscriptionEngine {
+    pub fn new(concurrency: usize, live_concurrency: usize) -> Self {
+        TranscriptionEngine {
+            loaded: Mutex::new(None),
+            permits: Arc::new(Semaphore::new(concurrency.max(1))),
+            live_permits: Arc::new(Semaphore::new(live_concurrency.max(1))),
+        }
+    }
+
+    /// Waits for a free slot and creates a state for the given model,
+    /// loading it first if it isn't the one in memory.
+    pub async fn acquire_for(self: Arc<Self>, model_path: PathBuf) -> Result<EngineState, String> {
+        let permits = self.permits.clone();
+        self.acquire_with(permits, model_path).await
+    }
+
+    /// Like [`Self::acquire_for`], but takes one of the slots kept for live
+    /// transcription.
+    pub async fn acquire_live_for(
+        self: Arc<Self>,
+        model_path: PathBuf,
+    ) -> Result<EngineState, String> {
+        let permits = self.live_permits.clone();
+        self.acquire_with(permits, model_path).await
+    }
+
+    async fn acquire_with(
+        self: Arc<Self>,
+        permits: Arc<Semaphore>,
+        model_path: PathBuf,
+    ) -> Result<EngineState, String> {
+        let permit = permits.acquire_owned().await.map_err(|e| e.to_string())?;
+
+        tauri::async_runtime::spawn_blocking(move || {
+            let ctx = self.context(&model_path)?;
+            let state = ctx
+                .create_state()
+                .map_err(|e| format!("Failed to create whisper state: {}", e))?;
+            Ok(EngineState {
+                state,
+                _permit: permit,
+            })
+        })
+        .await
+        .map_err(|e| e.to_string())?
+    }
+
+    fn context(&self, model_path: &Path) -> Result<Arc<WhisperContext>, String> {
+        let mut loaded = self.loaded.lock().expect("whisper context lock poisoned");
+
+        if let Some((path, ctx)) = loaded.as_ref() {
+            if path == model_path {
+                return Ok(ctx.clone());
+            }
+        }
+
+        if !model_path.exists() {
+            return Err(format!(
+                "Whisper model {} doesn't exist",
+                model_path.display()
+            ));
+        }
+
+        info!("Loading whisper model {}", model_path.display());
+        let st = std::time::Instant::now();
+        let ctx = Arc::new(
+            WhisperContext::new_with_params(
+                &model_path.to_string_lossy(),
+                WhisperContextParameters::default(),
+            )
+            .map_err(|e| format!("Failed to open model: {}", e))?,
+        );
+        info!("Loading whisper model took {}ms", st.elapsed().as_millis());
+
+        *loaded = Some((model_path.to_path_buf(), ctx.clone()));
+        Ok(ctx)
+    }
+}
@@ -45,7 +45,7 @@ export const useCreateConversationMutation = () => {
   const createConversationMutation = useMutation({
     mutationFn: async () => {
       const conversation = await invoke("create_conversation", {
-        form: { title: "the title" },
+        form: { title: "" },
       });
       return conversation as any;
     },